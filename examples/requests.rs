@@ -1,206 +1,108 @@
 use otus_axum::{
+    client::{ClientError, DEFAULT_BASE_URL, SmartHomeClient},
     handlers::DeviceForm,
     models::{Device, House, Room},
 };
-use std::collections::HashMap;
-
-static HTTP_HOST: &str = "http://localhost:3000";
+use std::time::Duration;
+
+/// Client of `SMARTHOME_URL`, the local server by default, authenticating
+/// with the admin token in `SMARTHOME_TOKEN`.
+fn client() -> SmartHomeClient {
+    let token = std::env::var("SMARTHOME_TOKEN").expect("SMARTHOME_TOKEN must be set");
+    let base_url = std::env::var("SMARTHOME_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_owned());
+
+    SmartHomeClient::builder()
+        .base_url(base_url)
+        .token(token)
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap()
+}
 
-fn main() {
+fn main() -> Result<(), ClientError> {
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap()
-        .block_on(add_and_update_ops())
+        .block_on(add_and_update_ops(&client()))
 }
 
-async fn add_and_update_ops() {
-    set_up().await;
+async fn add_and_update_ops(client: &SmartHomeClient) -> Result<(), ClientError> {
+    set_up(client).await?;
 
-    upd_the_device(
-        new_device(upd_the_room(new_room(upd_the_house(new_house().await).await).await).await)
-            .await,
-    )
-    .await;
-}
+    let house = upd_the_house(client, new_house(client).await?).await?;
+    let room = upd_the_room(client, new_room(client, house).await?).await?;
+    let house_id = room.house;
 
-async fn set_up() {
-    let client = reqwest::Client::new();
+    let device = new_device(client, room).await?;
+    let device = upd_the_device(client, house_id, device).await?;
 
-    let response = client
-        .delete(format!("{}/house", HTTP_HOST))
-        .send()
-        .await
-        .unwrap();
+    for room in client.rooms(house_id).await? {
+        println!("{}:", room.name);
+        for device in client.devices(house_id, room.id).await? {
+            println!(
+                "  {} ({}) on: {}",
+                device.name, device.device_type, device.state
+            );
+        }
+    }
+    println!("updated device {}", device.id);
 
-    assert!(response.status().is_success());
+    Ok(())
 }
 
-async fn new_house() -> House {
-    let name = "la casa de mi primо";
-
-    let mut data = HashMap::new();
-    data.insert("name", name);
-
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/{}", HTTP_HOST, "house"))
-        .json(&data)
-        .send()
-        .await
-        .unwrap();
-
-    assert!(response.status().is_success());
-
-    let result = response.json::<House>().await;
-    assert!(result.is_ok());
-
-    let house = result.unwrap();
+/// Starts from an empty server; run it with `ALLOW_DROP_ALL=true`.
+async fn set_up(client: &SmartHomeClient) -> Result<(), ClientError> {
+    let token = client.drop_all_token().await?;
+    client.drop_all(&token.token).await?;
 
-    assert_eq!(&house.name, name);
-
-    house
+    Ok(())
 }
 
-async fn upd_the_house(house: House) -> House {
-    let name = "la casa de mi primo updated";
-
-    let renamed_house = House {
-        name: name.to_owned(),
-        ..house
-    };
+async fn new_house(client: &SmartHomeClient) -> Result<House, ClientError> {
+    client.add_house("la casa de mi primо").await
+}
 
-    let client = reqwest::Client::new();
-    let response = client
-        .put(format!("{}/houses/{}", HTTP_HOST, house.id))
-        .json(&renamed_house)
-        .send()
+async fn upd_the_house(client: &SmartHomeClient, house: House) -> Result<House, ClientError> {
+    client
+        .update_house(house.id, "la casa de mi primo updated")
         .await
-        .unwrap();
-
-    assert!(response.status().is_success());
-
-    let result = response.json::<House>().await;
-    assert!(result.is_ok());
-
-    let upd_house = result.unwrap();
-
-    assert_eq!(&upd_house.name, name);
-
-    upd_house
 }
 
-async fn new_room(house: House) -> Room {
-    let name = "cocina";
-    let mut data = HashMap::new();
-    data.insert("name", name);
-
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/houses/{}/rooms", HTTP_HOST, house.id))
-        .json(&data)
-        .send()
-        .await;
-
-    let response = response.unwrap();
-
-    assert!(response.status().is_success());
-
-    let result = response.json::<Room>().await;
-    assert!(result.is_ok());
-
-    let room = result.unwrap();
-
-    assert_eq!(&room.name, name);
-
-    room
+async fn new_room(client: &SmartHomeClient, house: House) -> Result<Room, ClientError> {
+    client.add_room(house.id, "cocina").await
 }
 
-async fn upd_the_room(room: Room) -> Room {
-    let name = "cocina updated";
-
-    let renamed_room = Room {
-        name: name.to_owned(),
-        ..room
-    };
-
-    let client = reqwest::Client::new();
-    let response = client
-        .put(format!(
-            "{}/houses/{}/rooms/{}",
-            HTTP_HOST, room.house, room.id
-        ))
-        .json(&renamed_room)
-        .send()
+async fn upd_the_room(client: &SmartHomeClient, room: Room) -> Result<Room, ClientError> {
+    client
+        .update_room(room.house, room.id, "cocina updated")
         .await
-        .unwrap();
-
-    assert!(response.status().is_success());
-
-    let result = response.json::<Room>().await;
-    assert!(result.is_ok());
-
-    let upd_room = result.unwrap();
-
-    assert_eq!(&upd_room.name, name);
-
-    room
 }
 
-async fn new_device(room: Room) -> Device {
-    let name = "temperatura en el refrigorico";
+async fn new_device(client: &SmartHomeClient, room: Room) -> Result<Device, ClientError> {
     let data = DeviceForm {
-        name: name.into(),
+        name: "temperatura en el refrigorico".into(),
         state: false,
         device: "termometro".into(),
+        power: None,
     };
 
-    let url = format!(
-        "{}/houses/{}/rooms/{}/devices",
-        HTTP_HOST, room.house, room.id
-    );
-
-    let client = reqwest::Client::new();
-    let response = client.post(url).json(&data).send().await.unwrap();
-
-    assert!(response.status().is_success());
-
-    let result = response.json::<Device>().await;
-    assert!(result.is_ok());
-
-    let device = result.unwrap();
-
-    assert_eq!(&device.name, name);
-
-    device
+    client.add_device(room.house, room.id, &data).await
 }
 
-async fn upd_the_device(device: Device) -> Device {
+async fn upd_the_device(
+    client: &SmartHomeClient,
+    house_id: i32,
+    device: Device,
+) -> Result<Device, ClientError> {
     let renamed = DeviceForm {
         name: "hello world".into(),
         state: true,
         device: "socket".into(),
+        power: Some(60.0),
     };
 
-    let client = reqwest::Client::new();
-    let response = client
-        .put(format!(
-            "{}/houses/1/rooms/{}/devices/{}",
-            HTTP_HOST, device.room, device.id
-        ))
-        .json(&renamed)
-        .send()
+    client
+        .update_device(house_id, device.room, device.id, &renamed)
         .await
-        .unwrap();
-
-    assert!(response.status().is_success());
-
-    let result = response.json::<Device>().await;
-    assert!(result.is_ok());
-
-    let device = result.unwrap();
-
-    assert_eq!(device.name, renamed.name);
-
-    device
 }
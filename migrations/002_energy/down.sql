@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS device_state_log;
+ALTER TABLE device DROP COLUMN power;
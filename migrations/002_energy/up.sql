@@ -0,0 +1,11 @@
+ALTER TABLE device ADD COLUMN power DOUBLE;
+
+CREATE TABLE device_state_log (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    device INTEGER NOT NULL REFERENCES device(id),
+    state BOOLEAN NOT NULL,
+    power DOUBLE,
+    changed_at BIGINT NOT NULL
+);
+
+CREATE INDEX device_state_log_by_device ON device_state_log (device, changed_at);
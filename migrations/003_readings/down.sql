@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS reading;
+ALTER TABLE device DROP COLUMN last_reading_at;
+ALTER TABLE device DROP COLUMN last_reading;
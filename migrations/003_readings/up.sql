@@ -0,0 +1,11 @@
+ALTER TABLE device ADD COLUMN last_reading DOUBLE;
+ALTER TABLE device ADD COLUMN last_reading_at BIGINT;
+
+CREATE TABLE reading (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    device INTEGER NOT NULL REFERENCES device(id),
+    value DOUBLE NOT NULL,
+    taken_at BIGINT NOT NULL
+);
+
+CREATE INDEX reading_by_device ON reading (device, taken_at);
@@ -0,0 +1,12 @@
+CREATE TABLE alert (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    device INTEGER NOT NULL REFERENCES device(id),
+    kind TEXT NOT NULL,
+    threshold DOUBLE NOT NULL,
+    for_secs BIGINT NOT NULL DEFAULT 0,
+    state TEXT NOT NULL DEFAULT 'ok',
+    breached_since BIGINT,
+    changed_at BIGINT NOT NULL
+);
+
+CREATE INDEX alert_by_device ON alert (device);
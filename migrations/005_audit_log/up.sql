@@ -0,0 +1,16 @@
+CREATE TABLE audit_log (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    at BIGINT NOT NULL,
+    actor TEXT NOT NULL,
+    method TEXT NOT NULL,
+    route TEXT NOT NULL,
+    uri TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    house INTEGER,
+    room INTEGER,
+    device INTEGER,
+    before TEXT,
+    after TEXT
+);
+
+CREATE INDEX audit_log_by_time ON audit_log (at);
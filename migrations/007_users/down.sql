@@ -0,0 +1,4 @@
+ALTER TABLE api_token DROP COLUMN expires_at;
+ALTER TABLE api_token DROP COLUMN user;
+DROP TABLE IF EXISTS house_member;
+DROP TABLE IF EXISTS user;
@@ -0,0 +1,17 @@
+CREATE TABLE user (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    login TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE house_member (
+    house INTEGER NOT NULL REFERENCES house(id),
+    user INTEGER NOT NULL REFERENCES user(id),
+    role TEXT NOT NULL,
+
+    PRIMARY KEY (house, user)
+);
+
+ALTER TABLE api_token ADD COLUMN user INTEGER REFERENCES user(id);
+ALTER TABLE api_token ADD COLUMN expires_at BIGINT;
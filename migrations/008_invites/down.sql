@@ -0,0 +1,4 @@
+ALTER TABLE api_token DROP COLUMN invite;
+DROP TABLE IF EXISTS invite_device;
+DROP TABLE IF EXISTS invite_room;
+DROP TABLE IF EXISTS invite;
@@ -0,0 +1,26 @@
+CREATE TABLE invite (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    house INTEGER NOT NULL REFERENCES house(id),
+    name TEXT NOT NULL,
+    permission TEXT NOT NULL,
+    whole_house BOOLEAN NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    revoked_at BIGINT
+);
+
+CREATE TABLE invite_room (
+    invite INTEGER NOT NULL REFERENCES invite(id),
+    room INTEGER NOT NULL REFERENCES room(id),
+
+    PRIMARY KEY (invite, room)
+);
+
+CREATE TABLE invite_device (
+    invite INTEGER NOT NULL REFERENCES invite(id),
+    device INTEGER NOT NULL REFERENCES device(id),
+
+    PRIMARY KEY (invite, device)
+);
+
+ALTER TABLE api_token ADD COLUMN invite INTEGER REFERENCES invite(id);
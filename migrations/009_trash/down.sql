@@ -0,0 +1,81 @@
+-- The old constraints cannot hold deleted rows next to live ones of the same name,
+-- so the trash is emptied first.
+PRAGMA foreign_keys = OFF;
+
+BEGIN IMMEDIATE;
+
+CREATE TEMP TABLE trashed_device AS
+    SELECT device.id FROM device
+    JOIN room ON room.id = device.room
+    JOIN house ON house.id = room.house
+    WHERE device.deleted_at IS NOT NULL OR room.deleted_at IS NOT NULL OR house.deleted_at IS NOT NULL;
+CREATE TEMP TABLE trashed_room AS
+    SELECT room.id FROM room
+    JOIN house ON house.id = room.house
+    WHERE room.deleted_at IS NOT NULL OR house.deleted_at IS NOT NULL;
+CREATE TEMP TABLE trashed_invite AS
+    SELECT invite.id FROM invite
+    JOIN house ON house.id = invite.house
+    WHERE house.deleted_at IS NOT NULL;
+
+DELETE FROM reading WHERE device IN (SELECT id FROM trashed_device);
+DELETE FROM alert WHERE device IN (SELECT id FROM trashed_device);
+DELETE FROM device_state_log WHERE device IN (SELECT id FROM trashed_device);
+DELETE FROM invite_device
+    WHERE device IN (SELECT id FROM trashed_device) OR invite IN (SELECT id FROM trashed_invite);
+DELETE FROM invite_room
+    WHERE room IN (SELECT id FROM trashed_room) OR invite IN (SELECT id FROM trashed_invite);
+UPDATE api_token SET invite = NULL, revoked_at = coalesce(revoked_at, unixepoch())
+    WHERE invite IN (SELECT id FROM trashed_invite);
+DELETE FROM invite WHERE id IN (SELECT id FROM trashed_invite);
+DELETE FROM house_member WHERE house IN (SELECT id FROM house WHERE deleted_at IS NOT NULL);
+
+CREATE TABLE old_house (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+INSERT INTO old_house (id, name) SELECT id, name FROM house WHERE deleted_at IS NULL;
+
+CREATE TABLE old_room (
+    id INTEGER NOT NULL PRIMARY KEY  AUTOINCREMENT,
+    house INTEGER NOT NULL REFERENCES house(id),
+    name TEXT NOT NULL,
+
+    constraint unique_room_in_house UNIQUE (house, name)
+);
+INSERT INTO old_room (id, house, name)
+    SELECT id, house, name FROM room WHERE id NOT IN (SELECT id FROM trashed_room);
+
+CREATE TABLE old_device (
+    id INTEGER NOT NULL PRIMARY KEY  AUTOINCREMENT,
+    room INTEGER NOT NULL REFERENCES room(id),
+    name TEXT NOT NULL,
+    device_type TEXT NOT NULL,
+    state BOOLEAN NOT NULL,
+    power DOUBLE,
+    last_reading DOUBLE,
+    last_reading_at BIGINT,
+
+    constraint unique_device_in_room UNIQUE (room, name)
+);
+INSERT INTO old_device (id, room, name, device_type, state, power, last_reading, last_reading_at)
+    SELECT id, room, name, device_type, state, power, last_reading, last_reading_at FROM device
+    WHERE id NOT IN (SELECT id FROM trashed_device);
+
+UPDATE sqlite_sequence
+    SET seq = (SELECT new.seq FROM sqlite_sequence AS new WHERE new.name = substr(sqlite_sequence.name, 5))
+    WHERE name IN ('old_house', 'old_room', 'old_device')
+        AND EXISTS (SELECT 1 FROM sqlite_sequence AS new WHERE new.name = substr(sqlite_sequence.name, 5));
+
+DROP TABLE device;
+DROP TABLE room;
+DROP TABLE house;
+ALTER TABLE old_house RENAME TO house;
+ALTER TABLE old_room RENAME TO room;
+ALTER TABLE old_device RENAME TO device;
+
+DROP TABLE trashed_device;
+DROP TABLE trashed_room;
+DROP TABLE trashed_invite;
+
+COMMIT;
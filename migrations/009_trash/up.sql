@@ -0,0 +1,54 @@
+-- Deleted houses, rooms and devices stay in the trash until restored or purged,
+-- so names only have to be unique among the rows that are not deleted.
+-- SQLite cannot drop a UNIQUE constraint, hence the rebuilt tables.
+PRAGMA foreign_keys = OFF;
+
+BEGIN IMMEDIATE;
+
+CREATE TABLE new_house (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    deleted_at BIGINT
+);
+INSERT INTO new_house (id, name) SELECT id, name FROM house;
+
+CREATE TABLE new_room (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    house INTEGER NOT NULL REFERENCES house(id),
+    name TEXT NOT NULL,
+    deleted_at BIGINT
+);
+INSERT INTO new_room (id, house, name) SELECT id, house, name FROM room;
+
+CREATE TABLE new_device (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    room INTEGER NOT NULL REFERENCES room(id),
+    name TEXT NOT NULL,
+    device_type TEXT NOT NULL,
+    state BOOLEAN NOT NULL,
+    power DOUBLE,
+    last_reading DOUBLE,
+    last_reading_at BIGINT,
+    deleted_at BIGINT
+);
+INSERT INTO new_device (id, room, name, device_type, state, power, last_reading, last_reading_at)
+    SELECT id, room, name, device_type, state, power, last_reading, last_reading_at FROM device;
+
+-- Ids of rows deleted before the rebuild stay used.
+UPDATE sqlite_sequence
+    SET seq = (SELECT old.seq FROM sqlite_sequence AS old WHERE old.name = substr(sqlite_sequence.name, 5))
+    WHERE name IN ('new_house', 'new_room', 'new_device')
+        AND EXISTS (SELECT 1 FROM sqlite_sequence AS old WHERE old.name = substr(sqlite_sequence.name, 5));
+
+DROP TABLE device;
+DROP TABLE room;
+DROP TABLE house;
+ALTER TABLE new_house RENAME TO house;
+ALTER TABLE new_room RENAME TO room;
+ALTER TABLE new_device RENAME TO device;
+
+CREATE UNIQUE INDEX unique_house_name ON house (name) WHERE deleted_at IS NULL;
+CREATE UNIQUE INDEX unique_room_in_house ON room (house, name) WHERE deleted_at IS NULL;
+CREATE UNIQUE INDEX unique_device_in_room ON device (room, name) WHERE deleted_at IS NULL;
+
+COMMIT;
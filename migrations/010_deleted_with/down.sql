@@ -0,0 +1,3 @@
+ALTER TABLE device DROP COLUMN deleted_with;
+ALTER TABLE room DROP COLUMN deleted_with;
+ALTER TABLE house DROP COLUMN deleted_with;
@@ -0,0 +1,22 @@
+CREATE TABLE house (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE room (
+    id SERIAL PRIMARY KEY,
+    house INTEGER NOT NULL REFERENCES house(id),
+    name TEXT NOT NULL,
+
+    constraint unique_room_in_house UNIQUE (house, name)
+);
+
+CREATE TABLE device (
+    id SERIAL PRIMARY KEY,
+    room INTEGER NOT NULL REFERENCES room(id),
+    name TEXT NOT NULL,
+    device_type TEXT NOT NULL,
+    state BOOLEAN NOT NULL,
+
+    constraint unique_device_in_room UNIQUE (room, name)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE device DROP COLUMN last_reading_at;
+ALTER TABLE device DROP COLUMN last_reading;
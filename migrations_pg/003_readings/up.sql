@@ -0,0 +1,3 @@
+-- Readings themselves stay in the SQLite database; the layout only keeps the latest one.
+ALTER TABLE device ADD COLUMN last_reading DOUBLE PRECISION;
+ALTER TABLE device ADD COLUMN last_reading_at BIGINT;
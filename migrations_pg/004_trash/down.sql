@@ -0,0 +1,27 @@
+-- The old constraints cannot hold deleted rows next to live ones of the same name,
+-- so the trash is emptied first. Readings and alerts of the deleted devices are in
+-- SQLite and are left to the server.
+DELETE FROM device_state_log WHERE device IN (
+    SELECT device.id FROM device
+    JOIN room ON room.id = device.room
+    JOIN house ON house.id = room.house
+    WHERE device.deleted_at IS NOT NULL OR room.deleted_at IS NOT NULL OR house.deleted_at IS NOT NULL
+);
+DELETE FROM device USING room, house
+    WHERE room.id = device.room AND house.id = room.house
+        AND (device.deleted_at IS NOT NULL OR room.deleted_at IS NOT NULL OR house.deleted_at IS NOT NULL);
+DELETE FROM room USING house
+    WHERE house.id = room.house AND (room.deleted_at IS NOT NULL OR house.deleted_at IS NOT NULL);
+DELETE FROM house WHERE deleted_at IS NOT NULL;
+
+DROP INDEX unique_device_in_room;
+ALTER TABLE device DROP COLUMN deleted_at;
+ALTER TABLE device ADD CONSTRAINT unique_device_in_room UNIQUE (room, name);
+
+DROP INDEX unique_room_in_house;
+ALTER TABLE room DROP COLUMN deleted_at;
+ALTER TABLE room ADD CONSTRAINT unique_room_in_house UNIQUE (house, name);
+
+DROP INDEX unique_house_name;
+ALTER TABLE house DROP COLUMN deleted_at;
+ALTER TABLE house ADD CONSTRAINT house_name_key UNIQUE (name);
@@ -0,0 +1,13 @@
+-- Deleted houses, rooms and devices stay in the trash until restored or purged,
+-- so names only have to be unique among the rows that are not deleted.
+ALTER TABLE house ADD COLUMN deleted_at BIGINT;
+ALTER TABLE house DROP CONSTRAINT house_name_key;
+CREATE UNIQUE INDEX unique_house_name ON house (name) WHERE deleted_at IS NULL;
+
+ALTER TABLE room ADD COLUMN deleted_at BIGINT;
+ALTER TABLE room DROP CONSTRAINT unique_room_in_house;
+CREATE UNIQUE INDEX unique_room_in_house ON room (house, name) WHERE deleted_at IS NULL;
+
+ALTER TABLE device ADD COLUMN deleted_at BIGINT;
+ALTER TABLE device DROP CONSTRAINT unique_device_in_room;
+CREATE UNIQUE INDEX unique_device_in_room ON device (room, name) WHERE deleted_at IS NULL;
@@ -0,0 +1,10 @@
+-- Rows taken by the same delete share `deleted_with`, the row the delete was
+-- asked for, so that `deleted_at` can stay the time of the delete. Rows already
+-- in the trash were grouped by their time alone.
+ALTER TABLE house ADD COLUMN deleted_with TEXT;
+ALTER TABLE room ADD COLUMN deleted_with TEXT;
+ALTER TABLE device ADD COLUMN deleted_with TEXT;
+
+UPDATE house SET deleted_with = 'at ' || deleted_at WHERE deleted_at IS NOT NULL;
+UPDATE room SET deleted_with = 'at ' || deleted_at WHERE deleted_at IS NOT NULL;
+UPDATE device SET deleted_with = 'at ' || deleted_at WHERE deleted_at IS NOT NULL;
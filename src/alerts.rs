@@ -0,0 +1,189 @@
+use std::{sync::Arc, time::Duration};
+
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    AppState, events::Event, models::Alert, repository, schema::alert, telemetry::Counted, unix_now,
+};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    Above,
+    Below,
+}
+
+impl AlertKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertKind::Above => "above",
+            AlertKind::Below => "below",
+        }
+    }
+}
+
+/// `ok → firing → resolved`; a resolved alert fires again on the next breach.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Ok,
+    Firing,
+    Resolved,
+}
+
+impl AlertState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertState::Ok => "ok",
+            AlertState::Firing => "firing",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct AlertForm {
+    pub kind: AlertKind,
+    pub threshold: f64,
+    /// How long the threshold must stay breached before the alert fires.
+    #[serde(default)]
+    pub for_secs: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, IntoParams)]
+pub struct AlertsQuery {
+    pub state: Option<AlertState>,
+}
+
+/// Feeds a reading taken at `at` into the rule, returning whether its state changed.
+pub fn evaluate(rule: &mut Alert, value: f64, at: i64) -> bool {
+    let breached = match rule.kind.as_str() {
+        "above" => value > rule.threshold,
+        "below" => value < rule.threshold,
+        _ => false,
+    };
+
+    if breached {
+        let since = *rule.breached_since.get_or_insert(at);
+
+        if rule.state != AlertState::Firing.as_str() && at - since >= rule.for_secs {
+            rule.state = AlertState::Firing.as_str().to_owned();
+            rule.changed_at = at;
+            return true;
+        }
+    } else {
+        rule.breached_since = None;
+
+        if rule.state == AlertState::Firing.as_str() {
+            rule.state = AlertState::Resolved.as_str().to_owned();
+            rule.changed_at = at;
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Persists the evaluation result.
+pub fn save(dbh: &mut diesel::SqliteConnection, rule: &Alert) -> Result<(), diesel::result::Error> {
+    diesel::update(alert::table.filter(alert::id.eq(rule.id)))
+        .set((
+            alert::state.eq(&rule.state),
+            alert::breached_since.eq(rule.breached_since),
+            alert::changed_at.eq(rule.changed_at),
+        ))
+        .execute(dbh)
+        .counted()?;
+
+    Ok(())
+}
+
+/// Sets the rule firing as of `at`, unless it was fired or its breach ended or
+/// restarted since `rule` was loaded; true if it was set.
+pub fn fire(
+    dbh: &mut diesel::SqliteConnection,
+    rule: &mut Alert,
+    at: i64,
+) -> Result<bool, diesel::result::Error> {
+    let Some(since) = rule.breached_since else {
+        return Ok(false);
+    };
+
+    let fired = diesel::update(alert::table.filter(alert::id.eq(rule.id)))
+        .filter(alert::breached_since.eq(since))
+        .filter(alert::state.ne(AlertState::Firing.as_str()))
+        .set((
+            alert::state.eq(AlertState::Firing.as_str()),
+            alert::changed_at.eq(at),
+        ))
+        .execute(dbh)
+        .counted()?;
+    if fired == 0 {
+        return Ok(false);
+    }
+
+    rule.state = AlertState::Firing.as_str().to_owned();
+    rule.changed_at = at;
+
+    Ok(true)
+}
+
+/// Tells the subscribers of the house about a transition, once it is saved.
+pub fn notify(app_state: &AppState, house: i32, rule: Alert) {
+    let _ = app_state.events.send(Event::Alert { house, alert: rule });
+}
+
+/// Fires `for_secs` rules whose breach outlasted the duration without a new reading arriving.
+pub fn sweep(app_state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
+    let mut dbh = app_state.pool.get()?;
+    let now = unix_now();
+
+    let pending = alert::table
+        .filter(alert::breached_since.is_not_null())
+        .filter(alert::state.ne(AlertState::Firing.as_str()))
+        .select(Alert::as_select())
+        .load(&mut dbh)
+        .counted()?;
+
+    for mut rule in pending {
+        let since = rule.breached_since.unwrap_or(now);
+
+        if now - since >= rule.for_secs {
+            let Some(house) = repository::house_of(app_state.repo.as_ref(), rule.device)? else {
+                continue;
+            };
+
+            // A reading may have ended the breach since the rules were loaded.
+            if fire(&mut dbh, &mut rule, now)? {
+                notify(app_state, house, rule);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs [`sweep`] every `period` until the server shuts down.
+pub fn spawn_sweeper(app_state: Arc<AppState>, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = app_state.shutdown.cancelled() => break,
+            }
+
+            let app_state = Arc::clone(&app_state);
+            let res =
+                tokio::task::spawn_blocking(move || sweep(&app_state).map_err(|e| e.to_string()))
+                    .await;
+
+            if let Ok(Err(e)) = res {
+                tracing::error!("alert sweep failed: {e}");
+            }
+        }
+    })
+}
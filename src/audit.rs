@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use axum::{
+    RequestExt,
+    body::{Body, to_bytes},
+    extract::{MatchedPath, RawPathParams, Request, State},
+    http::{Method, header},
+    middleware::Next,
+    response::Response,
+};
+use diesel::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    AppState, auth,
+    models::{AuditLog, NewAuditLog},
+    schema,
+    telemetry::Counted,
+    unix_now,
+};
+
+/// Largest response body kept as the `after` snapshot of a create.
+const MAX_SNAPSHOT: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub at: i64,
+    pub actor: String,
+    pub method: String,
+    pub route: String,
+    pub uri: String,
+    pub status: u16,
+    pub house: Option<i32>,
+    pub room: Option<i32>,
+    pub device: Option<i32>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+impl From<AuditLog> for AuditEntry {
+    fn from(log: AuditLog) -> Self {
+        let parse = |s: Option<String>| s.and_then(|s| serde_json::from_str(&s).ok());
+
+        Self {
+            id: log.id,
+            at: log.at,
+            actor: log.actor,
+            method: log.method,
+            route: log.route,
+            uri: log.uri,
+            status: log.status as u16,
+            house: log.house,
+            room: log.room,
+            device: log.device,
+            before: parse(log.before),
+            after: parse(log.after),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, IntoParams)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub route: Option<String>,
+    pub house: Option<i32>,
+    pub room: Option<i32>,
+    pub device: Option<i32>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Ids of the house, room and device addressed by the route.
+#[derive(Default, Debug)]
+struct Target {
+    house: Option<i32>,
+    room: Option<i32>,
+    device: Option<i32>,
+}
+
+impl Target {
+    fn from_params(params: &RawPathParams) -> Self {
+        let mut target = Target::default();
+
+        for (key, value) in params {
+            let value = value.parse().ok();
+            match key {
+                "house_id" => target.house = value,
+                "room_id" => target.room = value,
+                "device_id" => target.device = value,
+                _ => {}
+            }
+        }
+
+        target
+    }
+
+    /// JSON of the innermost addressed row, if it exists.
+    fn snapshot(&self, app_state: &AppState) -> Option<String> {
+        let repo = app_state.repo.as_ref();
+
+        let value = if let Some(device_id) = self.device {
+            repo.device(device_id)
+                .ok()?
+                .map(|d| serde_json::to_string(&d))
+        } else if let Some(room_id) = self.room {
+            repo.room(room_id).ok()?.map(|r| serde_json::to_string(&r))
+        } else if let Some(house_id) = self.house {
+            repo.house(house_id)
+                .ok()?
+                .map(|h| serde_json::to_string(&h))
+        } else {
+            None
+        };
+
+        value?.ok()
+    }
+}
+
+/// Route layer writing an `audit_log` row for every mutating request.
+///
+/// `before` is the addressed row ahead of the call. `after` is the JSON
+/// response of a create, or the row as it is after any other call.
+pub async fn record(
+    State(app_state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let method = req.method().clone();
+
+    if !matches!(
+        method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    ) {
+        return next.run(req).await;
+    }
+
+    let uri = req.uri().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| req.uri().path().to_owned(), |p| p.as_str().to_owned());
+    let target = req
+        .extract_parts::<RawPathParams>()
+        .await
+        .map(|params| Target::from_params(&params))
+        .unwrap_or_default();
+    // Handlers reuse the caller instead of looking the token up again.
+    let actor = match auth::authenticate(&app_state, req.headers()) {
+        Ok(caller) => {
+            let name = caller.name.clone();
+            req.extensions_mut().insert(caller);
+            name
+        }
+        Err(_) => "anonymous".to_owned(),
+    };
+
+    let before = target.snapshot(&app_state);
+
+    let response = next.run(req).await;
+    let status = response.status();
+
+    let (response, after) = if method == Method::POST && status.is_success() && is_json(&response) {
+        let (parts, body) = response.into_parts();
+        match to_bytes(body, usize::MAX).await {
+            Ok(bytes) => {
+                let after = (bytes.len() <= MAX_SNAPSHOT)
+                    .then(|| redact(&bytes))
+                    .flatten();
+                (Response::from_parts(parts, Body::from(bytes)), after)
+            }
+            Err(_) => (Response::from_parts(parts, Body::empty()), None),
+        }
+    } else {
+        (response, target.snapshot(&app_state))
+    };
+
+    let entry = NewAuditLog {
+        at: unix_now(),
+        actor,
+        method: method.to_string(),
+        route,
+        uri,
+        status: status.as_u16().into(),
+        house: target.house,
+        room: target.room,
+        device: target.device,
+        before,
+        after,
+    };
+
+    let written = app_state
+        .pool
+        .get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut dbh| {
+            diesel::insert_into(schema::audit_log::table)
+                .values(&entry)
+                .execute(&mut dbh)
+                .counted()
+                .map_err(|e| e.to_string())
+        });
+
+    if let Err(e) = written {
+        tracing::error!(
+            "failed to write audit log for {} {}: {e}",
+            entry.method,
+            entry.uri
+        );
+    }
+
+    response
+}
+
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"))
+}
+
+/// Issued tokens and sessions carry their secret in the body; keep it out of the log.
+fn redact(body: &[u8]) -> Option<String> {
+    let mut value = serde_json::from_slice::<serde_json::Value>(body).ok()?;
+
+    if let Some(token) = value.get_mut("token").filter(|t| t.is_string()) {
+        *token = "<redacted>".into();
+    }
+
+    serde_json::to_string(&value).ok()
+}
@@ -0,0 +1,505 @@
+use std::sync::Arc;
+
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::SaltString};
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, StatusCode, header, request::Parts},
+};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
+    SelectableHelper, SqliteConnection,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::{
+    AppState, DbPool,
+    models::{ApiToken, Invite, NewApiToken},
+    repository::{HomeRepository, RepoError},
+    schema::{api_token, house_member, invite, invite_device, invite_room},
+    telemetry::{Counted, CountedRow},
+    unix_now,
+};
+
+/// The authenticated owner of the bearer token on the request.
+#[derive(Clone, Debug)]
+pub struct Caller {
+    pub token: i32,
+    pub name: String,
+    pub admin: bool,
+    /// The user the token was issued to; service tokens have none.
+    pub user: Option<i32>,
+    /// The invite the token was issued for.
+    pub invite: Option<i32>,
+}
+
+/// A caller holding an admin token.
+#[derive(Clone, Debug)]
+pub struct Admin(pub Caller);
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct TokenForm {
+    pub name: String,
+    #[serde(default)]
+    pub admin: bool,
+    /// Login of the user the token acts for.
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+/// Access level of a user in a house, from the least to the most privileged.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Guest,
+    /// Switches devices on and off without changing them otherwise; what
+    /// control invites get, never a role of a member.
+    #[serde(skip)]
+    Operator,
+    Member,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Guest => "guest",
+            Role::Operator => "operator",
+            Role::Member => "member",
+            Role::Owner => "owner",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "guest" => Some(Role::Guest),
+            "member" => Some(Role::Member),
+            "owner" => Some(Role::Owner),
+            _ => None,
+        }
+    }
+}
+
+/// What an invite lets its holder do with the devices in its scope.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    View,
+    Control,
+}
+
+impl Permission {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::View => "view",
+            Permission::Control => "control",
+        }
+    }
+
+    pub fn parse(permission: &str) -> Option<Self> {
+        match permission {
+            "view" => Some(Permission::View),
+            "control" => Some(Permission::Control),
+            _ => None,
+        }
+    }
+}
+
+/// A house, optionally narrowed down to one of its rooms and a device in it.
+#[derive(Clone, Copy, Debug)]
+pub struct Resource {
+    pub house: i32,
+    pub room: Option<i32>,
+    pub device: Option<i32>,
+}
+
+impl Resource {
+    pub fn house(house: i32) -> Self {
+        Self {
+            house,
+            room: None,
+            device: None,
+        }
+    }
+
+    pub fn room(self, room: i32) -> Self {
+        Self {
+            room: Some(room),
+            ..self
+        }
+    }
+
+    pub fn device(self, device: i32) -> Self {
+        Self {
+            device: Some(device),
+            ..self
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct UserForm {
+    pub login: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct Session {
+    pub token: String,
+    pub expires_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct MemberForm {
+    pub login: String,
+    pub role: Role,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct Member {
+    pub user: i32,
+    pub login: String,
+    pub role: Role,
+}
+
+/// An invite to the house; without rooms and devices it covers the whole house.
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct InviteForm {
+    pub name: String,
+    pub permission: Permission,
+    #[serde(default)]
+    pub rooms: Vec<i32>,
+    #[serde(default)]
+    pub devices: Vec<i32>,
+    /// Seconds the invite stays valid.
+    pub ttl_secs: i64,
+}
+
+/// An invite with the rooms and devices it is scoped to.
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct InviteScope {
+    pub id: i32,
+    pub house: i32,
+    pub name: String,
+    pub permission: Permission,
+    pub whole_house: bool,
+    pub rooms: Vec<i32>,
+    pub devices: Vec<i32>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+/// A freshly created invite; the token is only ever shown here.
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct IssuedInvite {
+    pub id: i32,
+    pub token: String,
+    pub expires_at: i64,
+}
+
+/// A freshly issued token; the secret is only ever shown here.
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct IssuedToken {
+    pub id: i32,
+    pub name: String,
+    pub admin: bool,
+    pub token: String,
+}
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Resolves the `Authorization: Bearer` header to a live token.
+pub fn authenticate(
+    app_state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Caller, (StatusCode, String)> {
+    let unauthorized = |msg: &str| (StatusCode::UNAUTHORIZED, msg.to_owned());
+
+    let secret = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| unauthorized("missing bearer token"))?;
+
+    let mut dbh = app_state
+        .pool
+        .get()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let token = api_token::table
+        .filter(api_token::token_hash.eq(hash_token(secret.trim())))
+        .filter(api_token::revoked_at.is_null())
+        .filter(
+            api_token::expires_at
+                .is_null()
+                .or(api_token::expires_at.gt(unix_now())),
+        )
+        .select(ApiToken::as_select())
+        .first(&mut dbh)
+        .optional()
+        .counted()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| unauthorized("invalid, expired or revoked token"))?;
+
+    Ok(Caller {
+        token: token.id,
+        name: token.name,
+        admin: token.admin,
+        user: token.user,
+        invite: token.invite,
+    })
+}
+
+/// Checks that the caller holds at least `role` in the house of the resource
+/// and that the resource exists as addressed.
+///
+/// Admin tokens pass every check; other callers need a `house_member` row.
+/// The role goes first, so callers without access to a house get 403 whether
+/// the ids they ask for exist or not.
+pub fn authorize(
+    repo: &dyn HomeRepository,
+    dbh: &mut SqliteConnection,
+    caller: &Caller,
+    resource: Resource,
+    role: Role,
+) -> Result<(), (StatusCode, String)> {
+    let unavailable = |e: RepoError| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+    authorize_role(dbh, caller, resource, role)?;
+
+    // Members and invites outlive a deleted house until it is purged.
+    if repo.house(resource.house).map_err(unavailable)?.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("no house {}", resource.house),
+        ));
+    }
+
+    if let Some(room_id) = resource.room {
+        let found = repo.room(room_id).map_err(unavailable)?;
+
+        if found.is_none_or(|r| r.house != resource.house) {
+            return Err((StatusCode::NOT_FOUND, format!("no room {room_id}")));
+        }
+    }
+
+    if let (Some(room_id), Some(device_id)) = (resource.room, resource.device) {
+        let found = repo.device(device_id).map_err(unavailable)?;
+
+        if found.is_none_or(|d| d.room != room_id) {
+            return Err((StatusCode::NOT_FOUND, format!("no device {device_id}")));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the caller holds at least `role` in the house of the resource,
+/// without looking the resource up, so also in a deleted house.
+pub fn authorize_role(
+    dbh: &mut SqliteConnection,
+    caller: &Caller,
+    resource: Resource,
+    role: Role,
+) -> Result<(), (StatusCode, String)> {
+    let internal = |e: diesel::result::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+    if caller.admin {
+        return Ok(());
+    }
+
+    if let Some(invite_id) = caller.invite {
+        return invited(dbh, invite_id, resource, role);
+    }
+
+    let granted = match caller.user {
+        Some(user_id) => house_member::table
+            .filter(house_member::house.eq(resource.house))
+            .filter(house_member::user.eq(user_id))
+            .select(house_member::role)
+            .first::<String>(dbh)
+            .optional()
+            .counted()
+            .map_err(internal)?
+            .and_then(|r| Role::parse(&r)),
+        None => None,
+    };
+
+    match granted {
+        Some(granted) if granted >= role => Ok(()),
+        Some(_) => Err((
+            StatusCode::FORBIDDEN,
+            format!("{} role required", role.as_str()),
+        )),
+        None => Err((
+            StatusCode::FORBIDDEN,
+            format!("no access to house {}", resource.house),
+        )),
+    }
+}
+
+/// Checks that the caller may add a house: users, who become its owner, and
+/// admin tokens. Invite tokens are bound to their house, and a house added by
+/// a service token would have no owner.
+pub fn authorize_new_house(caller: &Caller) -> Result<(), (StatusCode, String)> {
+    if caller.invite.is_some() || (caller.user.is_none() && !caller.admin) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "only users and admins can add houses".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks an invite token against its scope.
+///
+/// `view` stands in for the guest role and `control` for the operator role,
+/// the latter only on devices: invites never change the layout of the house
+/// or the devices in it, they only switch devices.
+fn invited(
+    dbh: &mut SqliteConnection,
+    invite_id: i32,
+    resource: Resource,
+    role: Role,
+) -> Result<(), (StatusCode, String)> {
+    let internal = |e: diesel::result::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let forbidden = |msg: String| Err((StatusCode::FORBIDDEN, msg));
+
+    let found = invite::table
+        .filter(invite::id.eq(invite_id))
+        .filter(invite::revoked_at.is_null())
+        .filter(invite::expires_at.gt(unix_now()))
+        .select(Invite::as_select())
+        .first(dbh)
+        .optional()
+        .counted()
+        .map_err(internal)?;
+
+    let Some(found) = found.filter(|i| i.house == resource.house) else {
+        return forbidden(format!("no access to house {}", resource.house));
+    };
+
+    let permission = Permission::parse(&found.permission).unwrap_or(Permission::View);
+    let needed = match role {
+        Role::Guest => Permission::View,
+        Role::Operator if resource.device.is_some() => Permission::Control,
+        _ => return forbidden(format!("{} role required", role.as_str())),
+    };
+    if permission < needed {
+        return forbidden(format!("{} permission required", needed.as_str()));
+    }
+
+    if found.whole_house {
+        return Ok(());
+    }
+
+    let in_room = match resource.room {
+        Some(room_id) => {
+            invite_room::table
+                .filter(invite_room::invite.eq(invite_id))
+                .filter(invite_room::room.eq(room_id))
+                .count()
+                .get_result::<i64>(dbh)
+                .counted_row()
+                .map_err(internal)?
+                > 0
+        }
+        None => false,
+    };
+    let on_device = match resource.device {
+        Some(device_id) => {
+            invite_device::table
+                .filter(invite_device::invite.eq(invite_id))
+                .filter(invite_device::device.eq(device_id))
+                .count()
+                .get_result::<i64>(dbh)
+                .counted_row()
+                .map_err(internal)?
+                > 0
+        }
+        None => false,
+    };
+
+    if in_room || on_device {
+        Ok(())
+    } else {
+        forbidden("outside of the invite scope".to_owned())
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())?;
+
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+impl FromRequestParts<Arc<AppState>> for Caller {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(caller) = parts.extensions.get::<Caller>() {
+            return Ok(caller.clone());
+        }
+
+        let caller = authenticate(state, &parts.headers)?;
+        parts.extensions.insert(caller.clone());
+
+        Ok(caller)
+    }
+}
+
+impl FromRequestParts<Arc<AppState>> for Admin {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let caller = Caller::from_request_parts(parts, state).await?;
+
+        if !caller.admin {
+            return Err((StatusCode::FORBIDDEN, "admin token required".to_owned()));
+        }
+
+        Ok(Admin(caller))
+    }
+}
+
+/// Makes sure `secret` is a valid admin token, so a fresh server can be reached at all.
+/// Surrounding whitespace is ignored, as it is when the token is presented.
+pub fn bootstrap(pool: &DbPool, secret: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut dbh = pool.get()?;
+
+    diesel::insert_or_ignore_into(api_token::table)
+        .values(&NewApiToken {
+            name: "bootstrap".to_owned(),
+            token_hash: hash_token(secret.trim()),
+            admin: true,
+            created_at: unix_now(),
+            user: None,
+            expires_at: None,
+            invite: None,
+        })
+        .execute(&mut dbh)
+        .counted()?;
+
+    Ok(())
+}
@@ -0,0 +1,367 @@
+//! Online snapshots of the SQLite database behind [`AppState::pool`](crate::AppState).
+//!
+//! Backups are written with `VACUUM INTO`, which copies a consistent read
+//! snapshot while the server keeps serving. A restore attaches a snapshot and
+//! swaps the rows of every table in one transaction, so other connections see
+//! either the old data or the restored one. Layouts kept outside SQLite are
+//! neither backed up nor restored.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use diesel::{
+    QueryableByName, RunQueryDsl, SqliteConnection, sql_query,
+    sql_types::{BigInt, Text},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    AppState,
+    telemetry::{Counted, CountedRow},
+};
+
+const PREFIX: &str = "smarthome-";
+const SUFFIX: &str = ".db";
+
+/// Where backups go and how many of them are kept.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupSettings {
+    pub dir: PathBuf,
+    /// Newest backups kept once a new one is written; older ones are deleted.
+    pub keep: usize,
+    /// Seconds between scheduled backups, 0 for none.
+    pub interval_secs: u64,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("backups"),
+            keep: 7,
+            interval_secs: 0,
+        }
+    }
+}
+
+/// A backup file in the backup directory.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Backup {
+    pub name: String,
+    pub bytes: u64,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct RestoreForm {
+    /// Name of a backup listed by `GET /admin/backups`.
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Restored {
+    pub name: String,
+    /// Rows copied from the backup.
+    pub rows: usize,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    /// The server was started without a backup directory.
+    Disabled,
+    NotFound(String),
+    /// The backup was taken at a different schema version.
+    Incompatible(String),
+    /// The backup has rows referencing rows it does not have.
+    Inconsistent(String),
+    Io(std::io::Error),
+    Pool(diesel::r2d2::PoolError),
+    Query(diesel::result::Error),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Disabled => write!(f, "backups are disabled on this server"),
+            BackupError::NotFound(name) => write!(f, "no backup {name}"),
+            BackupError::Incompatible(name) => {
+                write!(f, "backup {name} was taken with other migrations applied")
+            }
+            BackupError::Inconsistent(name) => {
+                write!(f, "backup {name} has rows referencing missing ones")
+            }
+            BackupError::Io(e) => write!(f, "{e}"),
+            BackupError::Pool(e) => write!(f, "{e}"),
+            BackupError::Query(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<std::io::Error> for BackupError {
+    fn from(e: std::io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for BackupError {
+    fn from(e: diesel::r2d2::PoolError) -> Self {
+        BackupError::Pool(e)
+    }
+}
+
+impl From<diesel::result::Error> for BackupError {
+    fn from(e: diesel::result::Error) -> Self {
+        BackupError::Query(e)
+    }
+}
+
+fn settings(app_state: &AppState) -> Result<&BackupSettings, BackupError> {
+    app_state.backups.as_ref().ok_or(BackupError::Disabled)
+}
+
+/// Writes a new backup, then deletes all but the newest `keep` ones.
+pub fn create(app_state: &AppState) -> Result<Backup, BackupError> {
+    let settings = settings(app_state)?;
+    std::fs::create_dir_all(&settings.dir)?;
+
+    // Zero-padded nanoseconds keep backups of the same second in creation order.
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let created_at = now.as_secs() as i64;
+    let name = format!("{PREFIX}{created_at}-{:09}{SUFFIX}", now.subsec_nanos());
+    let path = settings.dir.join(&name);
+
+    let mut dbh = app_state.pool.get()?;
+    sql_query("VACUUM INTO ?")
+        .bind::<Text, _>(path.to_string_lossy())
+        .execute(&mut dbh)
+        .counted()?;
+
+    prune(&settings.dir, settings.keep)?;
+
+    Ok(Backup {
+        name,
+        bytes: std::fs::metadata(&path)?.len(),
+        created_at,
+    })
+}
+
+/// Backups in the directory, newest first.
+pub fn list(dir: &Path) -> Result<Vec<Backup>, BackupError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut res = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        let Some(created_at) = name
+            .strip_prefix(PREFIX)
+            .and_then(|rest| rest.strip_suffix(SUFFIX))
+            .and_then(|rest| rest.split('-').next())
+            .and_then(|ts| ts.parse().ok())
+        else {
+            continue;
+        };
+
+        res.push(Backup {
+            name,
+            bytes: entry.metadata()?.len(),
+            created_at,
+        });
+    }
+
+    res.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.name.cmp(&a.name)));
+
+    Ok(res)
+}
+
+/// Deletes all but the newest `keep` backups, returning how many went.
+pub fn prune(dir: &Path, keep: usize) -> Result<usize, BackupError> {
+    let stale = list(dir)?.into_iter().skip(keep).collect::<Vec<_>>();
+
+    for backup in &stale {
+        std::fs::remove_file(dir.join(&backup.name))?;
+    }
+
+    Ok(stale.len())
+}
+
+#[derive(QueryableByName)]
+struct Name {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+#[derive(QueryableByName)]
+struct Version {
+    #[diesel(sql_type = Text)]
+    version: String,
+}
+
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+/// Replaces the contents of the database with the backup named `name`.
+pub fn restore(app_state: &AppState, name: &str) -> Result<Restored, BackupError> {
+    let settings = settings(app_state)?;
+
+    // Only names from the listing, so no path ever leaves the backup directory.
+    if !list(&settings.dir)?.iter().any(|b| b.name == name) {
+        return Err(BackupError::NotFound(name.to_owned()));
+    }
+    let path = settings.dir.join(name);
+
+    let mut dbh = app_state.pool.get()?;
+
+    // Refilled tables reference each other in any order, so foreign keys are
+    // checked once all rows are in rather than row by row.
+    let foreign_keys = sql_query("SELECT foreign_keys AS count FROM pragma_foreign_keys")
+        .get_result::<Count>(&mut dbh)
+        .counted_row()?
+        .count
+        > 0;
+
+    sql_query("ATTACH DATABASE ? AS snapshot")
+        .bind::<Text, _>(path.to_string_lossy())
+        .execute(&mut dbh)
+        .counted()?;
+    sql_query("PRAGMA foreign_keys = OFF")
+        .execute(&mut dbh)
+        .counted()?;
+
+    let res = copy_snapshot(&mut dbh, name, foreign_keys);
+
+    // The connection goes back to the pool as it came, without the backup attached.
+    let detached = sql_query(format!(
+        "PRAGMA foreign_keys = {}",
+        if foreign_keys { "ON" } else { "OFF" }
+    ))
+    .execute(&mut dbh)
+    .counted()
+    .and_then(|_| {
+        sql_query("DETACH DATABASE snapshot")
+            .execute(&mut dbh)
+            .counted()
+    });
+
+    let rows = res?;
+    detached?;
+
+    Ok(Restored {
+        name: name.to_owned(),
+        rows,
+    })
+}
+
+fn versions(dbh: &mut SqliteConnection, schema: &str) -> Result<Vec<String>, BackupError> {
+    Ok(sql_query(format!(
+        "SELECT version FROM {schema}.__diesel_schema_migrations ORDER BY version"
+    ))
+    .load::<Version>(dbh)
+    .counted()?
+    .into_iter()
+    .map(|v| v.version)
+    .collect())
+}
+
+fn copy_snapshot(
+    dbh: &mut SqliteConnection,
+    name: &str,
+    check_references: bool,
+) -> Result<usize, BackupError> {
+    if versions(dbh, "main")? != versions(dbh, "snapshot").unwrap_or_default() {
+        return Err(BackupError::Incompatible(name.to_owned()));
+    }
+
+    let tables = sql_query(
+        "SELECT name FROM main.sqlite_master WHERE type = 'table' \
+         AND name NOT LIKE 'sqlite_%' AND name <> '__diesel_schema_migrations'",
+    )
+    .load::<Name>(dbh)
+    .counted()?;
+
+    dbh.immediate_transaction(|conn| {
+        let mut rows = 0;
+        for table in &tables {
+            let table = table.name.replace('"', "\"\"");
+
+            sql_query(format!("DELETE FROM main.\"{table}\""))
+                .execute(conn)
+                .counted()?;
+            rows += sql_query(format!(
+                "INSERT INTO main.\"{table}\" SELECT * FROM snapshot.\"{table}\""
+            ))
+            .execute(conn)
+            .counted()?;
+        }
+
+        // Carry the AUTOINCREMENT counters over too, if the backup has any.
+        let counters = sql_query(
+            "SELECT count(*) AS count FROM snapshot.sqlite_master WHERE name = 'sqlite_sequence'",
+        )
+        .get_result::<Count>(conn)
+        .counted_row()?;
+        if counters.count > 0 {
+            sql_query("DELETE FROM main.sqlite_sequence")
+                .execute(conn)
+                .counted()?;
+            sql_query("INSERT INTO main.sqlite_sequence SELECT * FROM snapshot.sqlite_sequence")
+                .execute(conn)
+                .counted()?;
+        }
+
+        if check_references {
+            let broken = sql_query("SELECT count(*) AS count FROM main.pragma_foreign_key_check")
+                .get_result::<Count>(conn)
+                .counted_row()?;
+            if broken.count > 0 {
+                return Err(BackupError::Inconsistent(name.to_owned()));
+            }
+        }
+
+        Ok(rows)
+    })
+}
+
+/// Writes a backup every `period` until the server shuts down.
+pub fn spawn_scheduler(app_state: Arc<AppState>, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        // The first tick is immediate; the first backup is due one period after start.
+        ticker.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = app_state.shutdown.cancelled() => break,
+            }
+
+            let app_state = Arc::clone(&app_state);
+            let res =
+                tokio::task::spawn_blocking(move || create(&app_state).map_err(|e| e.to_string()))
+                    .await;
+
+            match res {
+                Ok(Ok(backup)) => tracing::info!(name = %backup.name, "backup written"),
+                Ok(Err(e)) => tracing::error!("scheduled backup failed: {e}"),
+                Err(e) => tracing::error!("scheduled backup failed: {e}"),
+            }
+        }
+    })
+}
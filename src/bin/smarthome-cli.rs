@@ -0,0 +1,540 @@
+use std::{error::Error, fs, path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use otus_axum::{
+    client::{ClientError, DEFAULT_BASE_URL, SmartHomeClient},
+    energy::{EnergyReport, Period},
+    handlers::DeviceForm,
+    layout::{Action, ConfigSync, Format, Kind},
+    models::{Device, House, Room},
+};
+use serde::Serialize;
+
+/// Command line client of the smart home server.
+#[derive(Parser, Debug)]
+#[command(
+    name = "smarthome-cli",
+    about = "Manage smart home houses from the terminal"
+)]
+struct Cli {
+    /// Base URL of the server.
+    #[arg(long, env = "SMARTHOME_URL", default_value = DEFAULT_BASE_URL)]
+    url: String,
+
+    /// API, session or invite token.
+    #[arg(long, env = "SMARTHOME_TOKEN", hide_env_values = true)]
+    token: String,
+
+    /// Seconds a request may take.
+    #[arg(long, env = "SMARTHOME_TIMEOUT_SECS", default_value_t = 10)]
+    timeout_secs: u64,
+
+    /// Aligned columns for people, JSON for scripts.
+    #[arg(short, long, value_enum, default_value_t = Output::Table)]
+    output: Output,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Output {
+    Table,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Houses the token can see.
+    #[command(subcommand)]
+    House(HouseCommand),
+    /// Rooms of a house.
+    #[command(subcommand)]
+    Room(RoomCommand),
+    /// Devices of a room.
+    #[command(subcommand)]
+    Device(DeviceCommand),
+    /// Every room and device of a house with its energy use.
+    Report {
+        house: i32,
+        #[arg(long, value_enum, default_value_t = PeriodArg::Day)]
+        period: PeriodArg,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HouseCommand {
+    List,
+    Add {
+        name: String,
+    },
+    Rename {
+        house: i32,
+        name: String,
+    },
+    Rm {
+        house: i32,
+    },
+    /// Prints the house with its rooms and devices as a document.
+    Export {
+        house: i32,
+        #[arg(long, value_enum, default_value_t = FormatArg::Yaml)]
+        format: FormatArg,
+        /// Includes room and device ids, so that `config` can rename them.
+        #[arg(long)]
+        ids: bool,
+    },
+    /// Makes the house look like the document in the file: YAML for `.yaml`
+    /// and `.yml` files, JSON otherwise.
+    Config {
+        house: i32,
+        file: PathBuf,
+        /// Only prints the changes.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum RoomCommand {
+    List { house: i32 },
+    Add { house: i32, name: String },
+    Rename { house: i32, room: i32, name: String },
+    Rm { house: i32, room: i32 },
+}
+
+#[derive(Subcommand, Debug)]
+enum DeviceCommand {
+    List {
+        house: i32,
+        room: i32,
+    },
+    Add {
+        house: i32,
+        room: i32,
+        name: String,
+        /// Device type, e.g. `socket` or `termometro`.
+        #[arg(long = "type")]
+        device_type: String,
+        /// Power draw in watts, sockets only.
+        #[arg(long)]
+        power: Option<f64>,
+        /// Adds the device switched on.
+        #[arg(long)]
+        on: bool,
+    },
+    Rename {
+        house: i32,
+        room: i32,
+        device: i32,
+        name: String,
+    },
+    On {
+        house: i32,
+        room: i32,
+        device: i32,
+    },
+    Off {
+        house: i32,
+        room: i32,
+        device: i32,
+    },
+    Rm {
+        house: i32,
+        room: i32,
+        device: i32,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum PeriodArg {
+    Day,
+    Week,
+    Month,
+}
+
+impl From<PeriodArg> for Period {
+    fn from(period: PeriodArg) -> Self {
+        match period {
+            PeriodArg::Day => Period::Day,
+            PeriodArg::Week => Period::Week,
+            PeriodArg::Month => Period::Month,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FormatArg {
+    Json,
+    Yaml,
+}
+
+impl From<FormatArg> for Format {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Json => Format::Json,
+            FormatArg::Yaml => Format::Yaml,
+        }
+    }
+}
+
+/// Rooms and devices of a house, as printed by `report`.
+#[derive(Serialize, Debug)]
+struct Report {
+    house: House,
+    rooms: Vec<RoomReport>,
+    energy: EnergyReport,
+}
+
+#[derive(Serialize, Debug)]
+struct RoomReport {
+    #[serde(flatten)]
+    room: Room,
+    devices: Vec<Device>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(&cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let client = SmartHomeClient::builder()
+        .base_url(&cli.url)
+        .token(&cli.token)
+        .timeout(Duration::from_secs(cli.timeout_secs))
+        .build()?;
+    let out = cli.output;
+
+    match &cli.command {
+        Command::House(cmd) => match cmd {
+            HouseCommand::List => print_houses(out, &client.houses().await?),
+            HouseCommand::Add { name } => print_houses(out, &[client.add_house(name).await?]),
+            HouseCommand::Rename { house, name } => {
+                print_houses(out, &[client.update_house(*house, name).await?])
+            }
+            HouseCommand::Rm { house } => print_message(out, &client.delete_house(*house).await?),
+            HouseCommand::Export { house, format, ids } => {
+                print!(
+                    "{}",
+                    client.export_house(*house, (*format).into(), *ids).await?
+                )
+            }
+            HouseCommand::Config {
+                house,
+                file,
+                dry_run,
+            } => {
+                let document = fs::read_to_string(file)
+                    .map_err(|e| format!("cannot read {}: {e}", file.display()))?;
+                let format = match file.extension().and_then(|e| e.to_str()) {
+                    Some("yaml" | "yml") => Format::Yaml,
+                    _ => Format::Json,
+                };
+                let sync = client
+                    .sync_config(*house, &document, format, *dry_run)
+                    .await?;
+                print_config(out, &sync)
+            }
+        },
+        Command::Room(cmd) => match cmd {
+            RoomCommand::List { house } => print_rooms(out, &client.rooms(*house).await?),
+            RoomCommand::Add { house, name } => {
+                print_rooms(out, &[client.add_room(*house, name).await?])
+            }
+            RoomCommand::Rename { house, room, name } => {
+                print_rooms(out, &[client.update_room(*house, *room, name).await?])
+            }
+            RoomCommand::Rm { house, room } => {
+                print_message(out, &client.delete_room(*house, *room).await?)
+            }
+        },
+        Command::Device(cmd) => match cmd {
+            DeviceCommand::List { house, room } => {
+                print_devices(out, &client.devices(*house, *room).await?)
+            }
+            DeviceCommand::Add {
+                house,
+                room,
+                name,
+                device_type,
+                power,
+                on,
+            } => {
+                let form = DeviceForm {
+                    name: name.clone(),
+                    state: *on,
+                    device: device_type.clone(),
+                    power: *power,
+                };
+                print_devices(out, &[client.add_device(*house, *room, &form).await?])
+            }
+            DeviceCommand::Rename {
+                house,
+                room,
+                device,
+                name,
+            } => {
+                let current = find_device(&client, *house, *room, *device).await?;
+                let form = DeviceForm {
+                    name: name.clone(),
+                    ..form_of(current)
+                };
+                let updated = client.update_device(*house, *room, *device, &form).await?;
+                print_devices(out, &[updated])
+            }
+            DeviceCommand::On {
+                house,
+                room,
+                device,
+            }
+            | DeviceCommand::Off {
+                house,
+                room,
+                device,
+            } => {
+                let current = find_device(&client, *house, *room, *device).await?;
+                let form = DeviceForm {
+                    state: matches!(cmd, DeviceCommand::On { .. }),
+                    ..form_of(current)
+                };
+                let updated = client.update_device(*house, *room, *device, &form).await?;
+                print_devices(out, &[updated])
+            }
+            DeviceCommand::Rm {
+                house,
+                room,
+                device,
+            } => print_message(out, &client.delete_device(*house, *room, *device).await?),
+        },
+        Command::Report { house, period } => {
+            let report = report(&client, *house, (*period).into()).await?;
+            print_report(out, &report)
+        }
+    }
+
+    Ok(())
+}
+
+/// The API has no single-device read, so the device is picked from its room.
+async fn find_device(
+    client: &SmartHomeClient,
+    house: i32,
+    room: i32,
+    device: i32,
+) -> Result<Device, ClientError> {
+    client
+        .devices(house, room)
+        .await?
+        .into_iter()
+        .find(|d| d.id == device)
+        .ok_or_else(|| ClientError::Api {
+            status: reqwest::StatusCode::NOT_FOUND,
+            message: format!("no device {device}"),
+        })
+}
+
+/// Update form keeping everything about `device` as it is.
+fn form_of(device: Device) -> DeviceForm {
+    DeviceForm {
+        name: device.name,
+        state: device.state,
+        device: device.device_type,
+        power: device.power,
+    }
+}
+
+async fn report(
+    client: &SmartHomeClient,
+    house: i32,
+    period: Period,
+) -> Result<Report, ClientError> {
+    let found = client
+        .houses()
+        .await?
+        .into_iter()
+        .find(|h| h.id == house)
+        .ok_or_else(|| ClientError::Api {
+            status: reqwest::StatusCode::NOT_FOUND,
+            message: format!("no house {house}"),
+        })?;
+
+    let mut rooms = Vec::new();
+    for room in client.rooms(house).await? {
+        let devices = client.devices(house, room.id).await?;
+        rooms.push(RoomReport { room, devices });
+    }
+
+    Ok(Report {
+        house: found,
+        rooms,
+        energy: client.energy(house, period).await?,
+    })
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("responses serialize")
+    );
+}
+
+fn print_message(out: Output, message: &str) {
+    match out {
+        Output::Json => print_json(message),
+        Output::Table => println!("{message}"),
+    }
+}
+
+fn print_houses(out: Output, houses: &[House]) {
+    match out {
+        Output::Json => print_json(houses),
+        Output::Table => print_table(
+            &["ID", "NAME"],
+            houses
+                .iter()
+                .map(|h| vec![h.id.to_string(), h.name.clone()])
+                .collect(),
+        ),
+    }
+}
+
+fn print_rooms(out: Output, rooms: &[Room]) {
+    match out {
+        Output::Json => print_json(rooms),
+        Output::Table => print_table(
+            &["ID", "HOUSE", "NAME"],
+            rooms
+                .iter()
+                .map(|r| vec![r.id.to_string(), r.house.to_string(), r.name.clone()])
+                .collect(),
+        ),
+    }
+}
+
+fn device_row(device: &Device) -> Vec<String> {
+    vec![
+        device.id.to_string(),
+        device.name.clone(),
+        device.device_type.clone(),
+        if device.state { "on" } else { "off" }.to_owned(),
+        device.power.map(|w| format!("{w} W")).unwrap_or_default(),
+        device
+            .last_reading
+            .map(|v| v.to_string())
+            .unwrap_or_default(),
+    ]
+}
+
+const DEVICE_HEADERS: [&str; 6] = ["ID", "NAME", "TYPE", "STATE", "POWER", "LAST READING"];
+
+fn print_devices(out: Output, devices: &[Device]) {
+    match out {
+        Output::Json => print_json(devices),
+        Output::Table => print_table(&DEVICE_HEADERS, devices.iter().map(device_row).collect()),
+    }
+}
+
+fn print_config(out: Output, sync: &ConfigSync) {
+    if out == Output::Json {
+        return print_json(sync);
+    }
+
+    if sync.changes.is_empty() {
+        return println!("nothing to change");
+    }
+
+    print_table(
+        &["ACTION", "KIND", "ID", "NAME", "ROOM", "WAS"],
+        sync.changes
+            .iter()
+            .map(|step| {
+                let action = match step.action {
+                    Action::Create => "create",
+                    Action::Rename => "rename",
+                    Action::Update => "update",
+                    Action::Delete => "delete",
+                };
+                let kind = match step.kind {
+                    Kind::House => "house",
+                    Kind::Room => "room",
+                    Kind::Device => "device",
+                };
+                vec![
+                    action.to_owned(),
+                    kind.to_owned(),
+                    step.id.map(|id| id.to_string()).unwrap_or_default(),
+                    step.name.clone(),
+                    step.room.clone().unwrap_or_default(),
+                    step.was.clone().unwrap_or_default(),
+                ]
+            })
+            .collect(),
+    );
+    if sync.dry_run {
+        println!();
+        println!("dry run, nothing was changed");
+    }
+}
+
+fn print_report(out: Output, report: &Report) {
+    if out == Output::Json {
+        return print_json(report);
+    }
+
+    println!("{} (house {})", report.house.name, report.house.id);
+    for room in &report.rooms {
+        let kwh = report
+            .energy
+            .rooms
+            .iter()
+            .find(|r| r.room == room.room.id)
+            .map_or(0.0, |r| r.kwh);
+
+        println!();
+        println!("{} (room {}), {kwh:.3} kWh", room.room.name, room.room.id);
+        print_table(
+            &DEVICE_HEADERS,
+            room.devices.iter().map(device_row).collect(),
+        );
+    }
+    println!();
+    let period = match report.energy.period {
+        Period::Day => "day",
+        Period::Week => "week",
+        Period::Month => "30 days",
+    };
+    println!("{:.3} kWh over the last {period}", report.energy.kwh);
+}
+
+/// Prints left-aligned columns as wide as their longest cell.
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths = headers.iter().map(|h| h.len()).collect::<Vec<_>>();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let padded = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>();
+        println!("{}", padded.join("  ").trim_end());
+    };
+
+    line(headers.to_vec());
+    for row in &rows {
+        line(row.iter().map(String::as_str).collect());
+    }
+}
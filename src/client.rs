@@ -0,0 +1,674 @@
+use std::{fmt, time::Duration};
+
+use reqwest::{
+    Method, RequestBuilder, StatusCode,
+    header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue, InvalidHeaderValue},
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    alerts::{AlertForm, AlertsQuery},
+    audit::{AuditEntry, AuditQuery},
+    auth::{
+        InviteForm, InviteScope, IssuedInvite, IssuedToken, Member, MemberForm, Session, TokenForm,
+        UserForm,
+    },
+    backup::{Backup, RestoreForm, Restored},
+    energy::{EnergyQuery, EnergyReport, Period},
+    events::Event,
+    handlers::{DeviceForm, DropAllQuery, DropAllToken, DroppedCounts, HouseForm, RoomForm},
+    health::Readiness,
+    layout::{ConfigQuery, ConfigSync, ExportQuery, Format, ImportMode, ImportQuery, Imported},
+    models::{Alert, ApiToken, Device, House, Reading, Room, User},
+    readings::{ReadingForm, ReadingStats, ReadingsQuery},
+    trash::Trashed,
+};
+
+/// Address the server listens on by default.
+pub const DEFAULT_BASE_URL: &str = "http://localhost:3000";
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The token cannot be sent as a header.
+    Token(InvalidHeaderValue),
+    /// The request did not get an answer: connection refused, timeout and the like.
+    Http(reqwest::Error),
+    /// The server answered with an error status and this message.
+    Api { status: StatusCode, message: String },
+    /// The server answered, but not with the expected body.
+    Decode(serde_json::Error),
+}
+
+impl ClientError {
+    /// Status of an error answered by the server.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Api { status, .. } => Some(*status),
+            ClientError::Http(e) => e.status(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Token(e) => write!(f, "invalid token: {e}"),
+            ClientError::Http(e) => write!(f, "request failed: {e}"),
+            ClientError::Api { status, message } => write!(f, "{status}: {message}"),
+            ClientError::Decode(e) => write!(f, "unexpected response: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+/// Settings of a [`SmartHomeClient`]; start from [`SmartHomeClient::builder`].
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    base_url: String,
+    token: Option<String>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+}
+
+impl ClientBuilder {
+    pub fn base_url(self, base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            ..self
+        }
+    }
+
+    /// Bearer token sent with every request: an API, session or invite token.
+    pub fn token(self, token: impl Into<String>) -> Self {
+        Self {
+            token: Some(token.into()),
+            ..self
+        }
+    }
+
+    /// Limit on a whole request, from connecting to reading the body.
+    ///
+    /// Leave it unset for clients following [`SmartHomeClient::events`],
+    /// which stays open as long as the server runs.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    pub fn connect_timeout(self, timeout: Duration) -> Self {
+        Self {
+            connect_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    pub fn build(self) -> Result<SmartHomeClient, ClientError> {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &self.token {
+            let mut value =
+                HeaderValue::from_str(&format!("Bearer {token}")).map_err(ClientError::Token)?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let mut http = reqwest::Client::builder().default_headers(headers);
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            http = http.connect_timeout(timeout);
+        }
+
+        Ok(SmartHomeClient {
+            http: http.build()?,
+            base_url: self.base_url.trim_end_matches('/').to_owned(),
+        })
+    }
+}
+
+/// Typed client of the smart home HTTP API, one method per endpoint.
+#[derive(Debug, Clone)]
+pub struct SmartHomeClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl SmartHomeClient {
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder {
+            base_url: DEFAULT_BASE_URL.to_owned(),
+            token: None,
+            timeout: None,
+            connect_timeout: None,
+        }
+    }
+
+    /// Client of the server at `base_url` authenticating with `token`.
+    pub fn new(base_url: &str, token: &str) -> Result<Self, ClientError> {
+        Self::builder().base_url(base_url).token(token).build()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", self.base_url, path))
+    }
+
+    async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, ClientError> {
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+
+        if !status.is_success() {
+            return Err(ClientError::Api {
+                status,
+                message: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+
+        serde_json::from_slice(&body).map_err(ClientError::Decode)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ClientError> {
+        Self::send(self.request(Method::GET, path)).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<String, ClientError> {
+        Self::send(self.request(Method::DELETE, path)).await
+    }
+
+    async fn post<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, ClientError> {
+        Self::send(self.request(Method::POST, path).json(body)).await
+    }
+
+    async fn put<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, ClientError> {
+        Self::send(self.request(Method::PUT, path).json(body)).await
+    }
+
+    pub async fn houses(&self) -> Result<Vec<House>, ClientError> {
+        self.get("/house").await
+    }
+
+    pub async fn add_house(&self, name: &str) -> Result<House, ClientError> {
+        let form = HouseForm {
+            name: name.to_owned(),
+        };
+        self.post("/house", &form).await
+    }
+
+    pub async fn update_house(&self, house: i32, name: &str) -> Result<House, ClientError> {
+        let form = HouseForm {
+            name: name.to_owned(),
+        };
+        self.put(&format!("/houses/{house}"), &form).await
+    }
+
+    pub async fn delete_house(&self, house: i32) -> Result<String, ClientError> {
+        self.delete(&format!("/houses/{house}")).await
+    }
+
+    pub async fn rooms(&self, house: i32) -> Result<Vec<Room>, ClientError> {
+        self.get(&format!("/houses/{house}/rooms")).await
+    }
+
+    pub async fn add_room(&self, house: i32, name: &str) -> Result<Room, ClientError> {
+        let form = RoomForm {
+            name: name.to_owned(),
+        };
+        self.post(&format!("/houses/{house}/rooms"), &form).await
+    }
+
+    pub async fn update_room(
+        &self,
+        house: i32,
+        room: i32,
+        name: &str,
+    ) -> Result<Room, ClientError> {
+        let form = RoomForm {
+            name: name.to_owned(),
+        };
+        self.put(&format!("/houses/{house}/rooms/{room}"), &form)
+            .await
+    }
+
+    pub async fn delete_room(&self, house: i32, room: i32) -> Result<String, ClientError> {
+        self.delete(&format!("/houses/{house}/rooms/{room}")).await
+    }
+
+    pub async fn devices(&self, house: i32, room: i32) -> Result<Vec<Device>, ClientError> {
+        self.get(&format!("/houses/{house}/rooms/{room}/devices"))
+            .await
+    }
+
+    pub async fn add_device(
+        &self,
+        house: i32,
+        room: i32,
+        form: &DeviceForm,
+    ) -> Result<Device, ClientError> {
+        self.post(&format!("/houses/{house}/rooms/{room}/devices"), form)
+            .await
+    }
+
+    pub async fn update_device(
+        &self,
+        house: i32,
+        room: i32,
+        device: i32,
+        form: &DeviceForm,
+    ) -> Result<Device, ClientError> {
+        self.put(
+            &format!("/houses/{house}/rooms/{room}/devices/{device}"),
+            form,
+        )
+        .await
+    }
+
+    pub async fn delete_device(
+        &self,
+        house: i32,
+        room: i32,
+        device: i32,
+    ) -> Result<String, ClientError> {
+        self.delete(&format!("/houses/{house}/rooms/{room}/devices/{device}"))
+            .await
+    }
+
+    /// Deleted houses, rooms and devices the caller can restore.
+    pub async fn trash(&self) -> Result<Vec<Trashed>, ClientError> {
+        self.get("/trash").await
+    }
+
+    pub async fn restore_house(&self, house: i32) -> Result<House, ClientError> {
+        Self::send(self.request(Method::POST, &format!("/houses/{house}/restore"))).await
+    }
+
+    pub async fn restore_room(&self, house: i32, room: i32) -> Result<Room, ClientError> {
+        Self::send(self.request(
+            Method::POST,
+            &format!("/houses/{house}/rooms/{room}/restore"),
+        ))
+        .await
+    }
+
+    pub async fn restore_device(
+        &self,
+        house: i32,
+        room: i32,
+        device: i32,
+    ) -> Result<Device, ClientError> {
+        Self::send(self.request(
+            Method::POST,
+            &format!("/houses/{house}/rooms/{room}/devices/{device}/restore"),
+        ))
+        .await
+    }
+
+    /// Sends a batch of sensor readings, returning how many were stored.
+    pub async fn add_readings(
+        &self,
+        house: i32,
+        room: i32,
+        device: i32,
+        batch: &[ReadingForm],
+    ) -> Result<usize, ClientError> {
+        self.post(
+            &format!("/houses/{house}/rooms/{room}/devices/{device}/readings"),
+            batch,
+        )
+        .await
+    }
+
+    pub async fn readings(
+        &self,
+        house: i32,
+        room: i32,
+        device: i32,
+        query: &ReadingsQuery,
+    ) -> Result<Vec<Reading>, ClientError> {
+        let path = format!("/houses/{house}/rooms/{room}/devices/{device}/readings");
+        Self::send(self.request(Method::GET, &path).query(query)).await
+    }
+
+    pub async fn reading_stats(
+        &self,
+        house: i32,
+        room: i32,
+        device: i32,
+        query: &ReadingsQuery,
+    ) -> Result<Vec<ReadingStats>, ClientError> {
+        let path = format!("/houses/{house}/rooms/{room}/devices/{device}/readings/stats");
+        Self::send(self.request(Method::GET, &path).query(query)).await
+    }
+
+    pub async fn alerts(
+        &self,
+        house: i32,
+        room: i32,
+        device: i32,
+    ) -> Result<Vec<Alert>, ClientError> {
+        self.get(&format!(
+            "/houses/{house}/rooms/{room}/devices/{device}/alerts"
+        ))
+        .await
+    }
+
+    pub async fn add_alert(
+        &self,
+        house: i32,
+        room: i32,
+        device: i32,
+        form: &AlertForm,
+    ) -> Result<Alert, ClientError> {
+        self.post(
+            &format!("/houses/{house}/rooms/{room}/devices/{device}/alerts"),
+            form,
+        )
+        .await
+    }
+
+    pub async fn delete_alert(
+        &self,
+        house: i32,
+        room: i32,
+        device: i32,
+        alert: i32,
+    ) -> Result<String, ClientError> {
+        self.delete(&format!(
+            "/houses/{house}/rooms/{room}/devices/{device}/alerts/{alert}"
+        ))
+        .await
+    }
+
+    pub async fn house_alerts(
+        &self,
+        house: i32,
+        query: &AlertsQuery,
+    ) -> Result<Vec<Alert>, ClientError> {
+        let path = format!("/houses/{house}/alerts");
+        Self::send(self.request(Method::GET, &path).query(query)).await
+    }
+
+    /// The document of the house, its rooms and devices, as `format` text;
+    /// with `ids` it can be edited and sent back to [`Self::sync_config`].
+    pub async fn export_house(
+        &self,
+        house: i32,
+        format: Format,
+        ids: bool,
+    ) -> Result<String, ClientError> {
+        let response = self
+            .request(Method::GET, &format!("/houses/{house}/export"))
+            .query(&ExportQuery { format, ids })
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            return Err(ClientError::Api {
+                status,
+                message: text,
+            });
+        }
+
+        Ok(text)
+    }
+
+    /// Recreates the house `document`, an export in `format`, on the server.
+    pub async fn import_house(
+        &self,
+        document: &str,
+        format: Format,
+        on_conflict: ImportMode,
+    ) -> Result<Imported, ClientError> {
+        Self::send(
+            self.request(Method::POST, "/house/import")
+                .query(&ImportQuery { on_conflict })
+                .header(CONTENT_TYPE, format.content_type())
+                .body(document.to_owned()),
+        )
+        .await
+    }
+
+    /// Brings the house to the desired `document`, or with `dry_run` only
+    /// reports what that would change.
+    pub async fn sync_config(
+        &self,
+        house: i32,
+        document: &str,
+        format: Format,
+        dry_run: bool,
+    ) -> Result<ConfigSync, ClientError> {
+        Self::send(
+            self.request(Method::PUT, &format!("/houses/{house}/config"))
+                .query(&ConfigQuery { dry_run })
+                .header(CONTENT_TYPE, format.content_type())
+                .body(document.to_owned()),
+        )
+        .await
+    }
+
+    pub async fn energy(&self, house: i32, period: Period) -> Result<EnergyReport, ClientError> {
+        let path = format!("/houses/{house}/energy");
+        Self::send(
+            self.request(Method::GET, &path)
+                .query(&EnergyQuery { period }),
+        )
+        .await
+    }
+
+    /// Subscribes to the change notifications of a house.
+    pub async fn events(&self, house: i32) -> Result<EventStream, ClientError> {
+        let response = self
+            .request(Method::GET, &format!("/houses/{house}/events"))
+            .send()
+            .await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            return Err(ClientError::Api {
+                status,
+                message: response.text().await?,
+            });
+        }
+
+        Ok(EventStream {
+            response,
+            buffer: String::new(),
+        })
+    }
+
+    pub async fn members(&self, house: i32) -> Result<Vec<Member>, ClientError> {
+        self.get(&format!("/houses/{house}/members")).await
+    }
+
+    pub async fn add_member(&self, house: i32, form: &MemberForm) -> Result<Member, ClientError> {
+        self.post(&format!("/houses/{house}/members"), form).await
+    }
+
+    pub async fn delete_member(&self, house: i32, user: i32) -> Result<String, ClientError> {
+        self.delete(&format!("/houses/{house}/members/{user}"))
+            .await
+    }
+
+    pub async fn invites(&self, house: i32) -> Result<Vec<InviteScope>, ClientError> {
+        self.get(&format!("/houses/{house}/invites")).await
+    }
+
+    pub async fn add_invite(
+        &self,
+        house: i32,
+        form: &InviteForm,
+    ) -> Result<IssuedInvite, ClientError> {
+        self.post(&format!("/houses/{house}/invites"), form).await
+    }
+
+    pub async fn delete_invite(&self, house: i32, invite: i32) -> Result<String, ClientError> {
+        self.delete(&format!("/houses/{house}/invites/{invite}"))
+            .await
+    }
+
+    /// The invite the client's token was issued for.
+    pub async fn my_invite(&self) -> Result<InviteScope, ClientError> {
+        self.get("/invite").await
+    }
+
+    /// Opens a session; build another client with its token to act as the user.
+    pub async fn login(&self, login: &str, password: &str) -> Result<Session, ClientError> {
+        let form = UserForm {
+            login: login.to_owned(),
+            password: password.to_owned(),
+        };
+        self.post("/login", &form).await
+    }
+
+    /// Revokes the token the client authenticates with.
+    pub async fn logout(&self) -> Result<String, ClientError> {
+        Self::send(self.request(Method::POST, "/logout")).await
+    }
+
+    pub async fn healthz(&self) -> Result<serde_json::Value, ClientError> {
+        self.get("/healthz").await
+    }
+
+    /// Readiness of the server; unlike other calls a not-ready answer is not an error.
+    pub async fn readyz(&self) -> Result<Readiness, ClientError> {
+        let response = self.request(Method::GET, "/readyz").send().await?;
+        let body = response.bytes().await?;
+
+        serde_json::from_slice(&body).map_err(ClientError::Decode)
+    }
+
+    /// Metrics in the Prometheus text format.
+    pub async fn metrics(&self) -> Result<String, ClientError> {
+        let response = self.request(Method::GET, "/metrics").send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            return Err(ClientError::Api {
+                status,
+                message: text,
+            });
+        }
+
+        Ok(text)
+    }
+
+    pub async fn openapi(&self) -> Result<serde_json::Value, ClientError> {
+        self.get("/openapi.json").await
+    }
+
+    pub async fn audit(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, ClientError> {
+        Self::send(self.request(Method::GET, "/admin/audit").query(query)).await
+    }
+
+    /// Writes a backup of the database on the server.
+    pub async fn backup(&self) -> Result<Backup, ClientError> {
+        Self::send(self.request(Method::POST, "/admin/backup")).await
+    }
+
+    pub async fn backups(&self) -> Result<Vec<Backup>, ClientError> {
+        self.get("/admin/backups").await
+    }
+
+    /// Replaces the data on the server with the backup named `name`.
+    pub async fn restore(&self, name: &str) -> Result<Restored, ClientError> {
+        let form = RestoreForm {
+            name: name.to_owned(),
+        };
+        self.post("/admin/restore", &form).await
+    }
+
+    /// First step of wiping every house; pass the token on to [`Self::drop_all`].
+    pub async fn drop_all_token(&self) -> Result<DropAllToken, ClientError> {
+        Self::send(self.request(Method::POST, "/admin/house/drop-token")).await
+    }
+
+    pub async fn drop_all(&self, confirm: &str) -> Result<DroppedCounts, ClientError> {
+        let query = DropAllQuery {
+            confirm: Some(confirm.to_owned()),
+        };
+        Self::send(self.request(Method::DELETE, "/admin/house").query(&query)).await
+    }
+
+    pub async fn tokens(&self) -> Result<Vec<ApiToken>, ClientError> {
+        self.get("/admin/tokens").await
+    }
+
+    pub async fn add_token(&self, form: &TokenForm) -> Result<IssuedToken, ClientError> {
+        self.post("/admin/tokens", form).await
+    }
+
+    pub async fn revoke_token(&self, token: i32) -> Result<String, ClientError> {
+        self.delete(&format!("/admin/tokens/{token}")).await
+    }
+
+    pub async fn users(&self) -> Result<Vec<User>, ClientError> {
+        self.get("/admin/users").await
+    }
+
+    pub async fn add_user(&self, form: &UserForm) -> Result<User, ClientError> {
+        self.post("/admin/users", form).await
+    }
+}
+
+/// Server-sent events of a house, as returned by [`SmartHomeClient::events`].
+#[derive(Debug)]
+pub struct EventStream {
+    response: reqwest::Response,
+    buffer: String,
+}
+
+impl EventStream {
+    /// Waits for the next event; `None` once the server closes the stream.
+    pub async fn next(&mut self) -> Option<Result<Event, ClientError>> {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let message = self.buffer[..end].to_owned();
+                self.buffer.drain(..end + 2);
+
+                // Keep-alive comments carry no data.
+                let data = message
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data:"))
+                    .map(str::trim_start)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if data.is_empty() {
+                    continue;
+                }
+
+                return Some(serde_json::from_str(&data).map_err(ClientError::Decode));
+            }
+
+            match self.response.chunk().await {
+                Ok(Some(chunk)) => self.buffer.push_str(&String::from_utf8_lossy(&chunk)),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
+
+use axum::http::HeaderValue;
+use clap::{Parser, ValueEnum, builder::BoolishValueParser};
+use serde::{Deserialize, Serialize};
+use tower_http::cors::{Any, CorsLayer};
+
+use crate::{
+    DbPool,
+    backup::BackupSettings,
+    sqlite::{self, SqliteSettings, Synchronous},
+    trash::TrashSettings,
+};
+
+/// Command line of the server; every flag can also come from the environment.
+#[derive(Parser, Debug, Default)]
+#[command(name = "server", about = "Smart home HTTP server")]
+pub struct Cli {
+    /// TOML file with the settings; flags and environment override it.
+    #[arg(long, env = "SMARTHOME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// SQLite path, or a `postgres://` URL to keep houses, rooms and devices in Postgres.
+    #[arg(long, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+
+    /// SQLite path for everything but the layout when `--database-url` is a Postgres URL.
+    #[arg(long, env = "SMARTHOME_SQLITE_URL")]
+    pub sqlite_url: Option<String>,
+
+    #[arg(long, env = "SMARTHOME_BIND")]
+    pub bind: Option<IpAddr>,
+
+    #[arg(long, env = "SMARTHOME_PORT")]
+    pub port: Option<u16>,
+
+    #[arg(long, env = "SMARTHOME_POOL_SIZE")]
+    pub pool_size: Option<u32>,
+
+    #[arg(long, env = "SMARTHOME_POOL_MIN_IDLE")]
+    pub pool_min_idle: Option<u32>,
+
+    #[arg(long, env = "SMARTHOME_SQLITE_BUSY_TIMEOUT_MS")]
+    pub sqlite_busy_timeout_ms: Option<u64>,
+
+    #[arg(long, env = "SMARTHOME_SQLITE_SYNCHRONOUS")]
+    pub sqlite_synchronous: Option<Synchronous>,
+
+    /// Directory `POST /admin/backup` and the scheduled backups write to.
+    #[arg(long, env = "SMARTHOME_BACKUP_DIR")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Backups kept in the backup directory; older ones are deleted.
+    #[arg(long, env = "SMARTHOME_BACKUP_KEEP")]
+    pub backup_keep: Option<usize>,
+
+    /// Seconds between scheduled backups, 0 to turn them off.
+    #[arg(long, env = "SMARTHOME_BACKUP_INTERVAL_SECS")]
+    pub backup_interval_secs: Option<u64>,
+
+    /// Seconds deleted houses, rooms and devices can be restored before they are purged.
+    #[arg(long, env = "SMARTHOME_TRASH_RETENTION_SECS")]
+    pub trash_retention_secs: Option<u64>,
+
+    /// Seconds between purges of the trash, 0 to keep deleted rows forever.
+    #[arg(long, env = "SMARTHOME_TRASH_PURGE_INTERVAL_SECS")]
+    pub trash_purge_interval_secs: Option<u64>,
+
+    #[arg(long, env = "SMARTHOME_LOG_LEVEL")]
+    pub log_level: Option<LogLevel>,
+
+    #[arg(long, env = "SMARTHOME_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    /// Seconds in-flight requests get to finish once shutdown starts.
+    #[arg(long, env = "SMARTHOME_DRAIN_TIMEOUT_SECS")]
+    pub drain_timeout_secs: Option<u64>,
+
+    /// Origin allowed to call the API from a browser, `*` for any; repeatable.
+    #[arg(
+        long = "cors-origin",
+        env = "SMARTHOME_CORS_ORIGINS",
+        value_delimiter = ','
+    )]
+    pub cors_origins: Option<Vec<String>>,
+
+    /// Enables `DELETE /admin/house`.
+    #[arg(
+        long,
+        env = "ALLOW_DROP_ALL",
+        num_args = 0..=1,
+        default_missing_value = "true",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub allow_drop_all: Option<bool>,
+
+    /// Runs the background sweeper that fires alerts on stale sensors.
+    #[arg(
+        long,
+        env = "SMARTHOME_ALERT_SWEEPER",
+        num_args = 0..=1,
+        default_missing_value = "true",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub alert_sweeper: Option<bool>,
+
+    /// Prints the effective configuration as TOML and exits.
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+/// Database the house layout is kept in, told apart by the scheme of the URL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+impl Backend {
+    pub fn detect(url: &str) -> Self {
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            Backend::Postgres
+        } else {
+            Backend::Sqlite
+        }
+    }
+}
+
+/// Settings of the server: defaults, then the TOML file, then environment and flags.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database_url: String,
+    /// Tokens, readings, alerts and the audit log when `database_url` is a Postgres URL.
+    pub sqlite_url: String,
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Most connections the database pool keeps open.
+    pub pool_size: u32,
+    /// Idle connections the pool keeps ready; all of `pool_size` when unset.
+    pub pool_min_idle: Option<u32>,
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
+    /// Seconds in-flight requests get to finish once shutdown starts.
+    pub drain_timeout_secs: u64,
+    pub cors_origins: Vec<String>,
+    /// Foreign keys are off whenever the layout lives in Postgres.
+    pub sqlite: SqliteSettings,
+    pub backup: BackupSettings,
+    pub trash: TrashSettings,
+    pub features: Features,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    pub drop_all: bool,
+    pub alert_sweeper: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_url: String::new(),
+            sqlite_url: "smarthome.db".to_owned(),
+            bind: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 3000,
+            pool_size: 10,
+            pool_min_idle: None,
+            log_level: LogLevel::Info,
+            log_format: LogFormat::Pretty,
+            drain_timeout_secs: 30,
+            cors_origins: Vec::new(),
+            sqlite: SqliteSettings::default(),
+            backup: BackupSettings::default(),
+            trash: TrashSettings::default(),
+            features: Features::default(),
+        }
+    }
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            drop_all: false,
+            alert_sweeper: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "cannot read {}: {e}", path.display()),
+            ConfigError::Parse(path, e) => write!(f, "cannot parse {}: {e}", path.display()),
+            ConfigError::Invalid(problems) => write!(f, "{}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Layers the file named by `cli`, then `cli` itself, over the defaults and validates the result.
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let (mut config, foreign_keys) = match &cli.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| ConfigError::Read(path.clone(), e))?;
+                let config =
+                    toml::from_str(&text).map_err(|e| ConfigError::Parse(path.clone(), e))?;
+                (config, foreign_keys_set(&text))
+            }
+            None => (Config::default(), false),
+        };
+
+        config.apply(cli);
+
+        // Readings and alerts point at devices the SQLite database does not
+        // have when the layout lives in Postgres, so foreign keys stay off.
+        if config.backend() == Backend::Postgres {
+            if foreign_keys {
+                return Err(ConfigError::Invalid(vec![
+                    "sqlite.foreign_keys cannot be on with a Postgres database_url".to_owned(),
+                ]));
+            }
+            config.sqlite.foreign_keys = false;
+        }
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply(&mut self, cli: &Cli) {
+        if let Some(database_url) = &cli.database_url {
+            self.database_url = database_url.clone();
+        }
+        if let Some(sqlite_url) = &cli.sqlite_url {
+            self.sqlite_url = sqlite_url.clone();
+        }
+        if let Some(bind) = cli.bind {
+            self.bind = bind;
+        }
+        if let Some(port) = cli.port {
+            self.port = port;
+        }
+        if let Some(pool_size) = cli.pool_size {
+            self.pool_size = pool_size;
+        }
+        if let Some(pool_min_idle) = cli.pool_min_idle {
+            self.pool_min_idle = Some(pool_min_idle);
+        }
+        if let Some(busy_timeout_ms) = cli.sqlite_busy_timeout_ms {
+            self.sqlite.busy_timeout_ms = busy_timeout_ms;
+        }
+        if let Some(synchronous) = cli.sqlite_synchronous {
+            self.sqlite.synchronous = synchronous;
+        }
+        if let Some(dir) = &cli.backup_dir {
+            self.backup.dir = dir.clone();
+        }
+        if let Some(keep) = cli.backup_keep {
+            self.backup.keep = keep;
+        }
+        if let Some(interval_secs) = cli.backup_interval_secs {
+            self.backup.interval_secs = interval_secs;
+        }
+        if let Some(retention_secs) = cli.trash_retention_secs {
+            self.trash.retention_secs = retention_secs;
+        }
+        if let Some(purge_interval_secs) = cli.trash_purge_interval_secs {
+            self.trash.purge_interval_secs = purge_interval_secs;
+        }
+        if let Some(log_level) = cli.log_level {
+            self.log_level = log_level;
+        }
+        if let Some(log_format) = cli.log_format {
+            self.log_format = log_format;
+        }
+        if let Some(drain_timeout_secs) = cli.drain_timeout_secs {
+            self.drain_timeout_secs = drain_timeout_secs;
+        }
+        if let Some(cors_origins) = &cli.cors_origins {
+            self.cors_origins = cors_origins.clone();
+        }
+        if let Some(drop_all) = cli.allow_drop_all {
+            self.features.drop_all = drop_all;
+        }
+        if let Some(alert_sweeper) = cli.alert_sweeper {
+            self.features.alert_sweeper = alert_sweeper;
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self.database_url.trim().is_empty() {
+            problems.push("database_url must be set".to_owned());
+        }
+        if self.backend() == Backend::Postgres {
+            if !cfg!(feature = "postgres") {
+                problems.push(
+                    "database_url is a Postgres URL but the server was built without the postgres feature"
+                        .to_owned(),
+                );
+            }
+            if self.sqlite_url.trim().is_empty() {
+                problems.push("sqlite_url must be set with a Postgres database_url".to_owned());
+            }
+        }
+        if self.port == 0 {
+            problems.push("port must not be 0".to_owned());
+        }
+        if self.pool_size == 0 {
+            problems.push("pool_size must be at least 1".to_owned());
+        }
+        if self.pool_min_idle.is_some_and(|min| min > self.pool_size) {
+            problems.push("pool_min_idle must not exceed pool_size".to_owned());
+        }
+        if self.backup.keep == 0 {
+            problems.push("backup.keep must be at least 1".to_owned());
+        }
+        if self.backup.dir.as_os_str().is_empty() {
+            problems.push("backup.dir must be set".to_owned());
+        }
+        if i64::try_from(self.trash.retention_secs).is_err() {
+            problems.push("trash.retention_secs is too large".to_owned());
+        }
+        for origin in &self.cors_origins {
+            let valid = origin == "*"
+                || ((origin.starts_with("http://") || origin.starts_with("https://"))
+                    && HeaderValue::from_str(origin).is_ok());
+
+            if !valid {
+                problems.push(format!(
+                    "cors origin {origin:?} is not `*` or an http(s) origin"
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        Backend::detect(&self.database_url)
+    }
+
+    /// The SQLite database behind [`AppState::pool`](crate::AppState).
+    pub fn sqlite_url(&self) -> &str {
+        match self.backend() {
+            Backend::Sqlite => &self.database_url,
+            Backend::Postgres => &self.sqlite_url,
+        }
+    }
+
+    /// The pragmas for [`sqlite_url`](Self::sqlite_url).
+    pub fn sqlite_settings(&self) -> SqliteSettings {
+        self.sqlite.clone()
+    }
+
+    pub fn pool(&self) -> Result<DbPool, diesel::r2d2::PoolError> {
+        sqlite::pool(
+            self.sqlite_url(),
+            self.pool_size,
+            self.pool_min_idle,
+            self.sqlite_settings(),
+        )
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout_secs)
+    }
+
+    /// The CORS layer for the configured origins, if any are.
+    pub fn cors(&self) -> Option<CorsLayer> {
+        if self.cors_origins.is_empty() {
+            return None;
+        }
+
+        let layer = CorsLayer::new().allow_methods(Any).allow_headers(Any);
+
+        Some(if self.cors_origins.iter().any(|o| o == "*") {
+            layer.allow_origin(Any)
+        } else {
+            layer.allow_origin(
+                self.cors_origins
+                    .iter()
+                    .filter_map(|o| HeaderValue::from_str(o).ok())
+                    .collect::<Vec<_>>(),
+            )
+        })
+    }
+
+    /// Period of the scheduled backups, if they are on.
+    pub fn backup_interval(&self) -> Option<Duration> {
+        (self.backup.interval_secs > 0).then(|| Duration::from_secs(self.backup.interval_secs))
+    }
+
+    /// Period of the trash purges, if they are on.
+    pub fn purge_interval(&self) -> Option<Duration> {
+        (self.trash.purge_interval_secs > 0)
+            .then(|| Duration::from_secs(self.trash.purge_interval_secs))
+    }
+
+    /// The settings as TOML for `--print-config`, with the database password hidden.
+    pub fn to_toml(&self) -> String {
+        let shown = Config {
+            database_url: hide_password(&self.database_url),
+            ..self.clone()
+        };
+        toml::to_string(&shown).unwrap_or_default()
+    }
+}
+
+/// `url` with the password of its `user:password@` part replaced by `***`.
+fn hide_password(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_owned();
+    };
+    let authority = rest.find(['/', '?']).map_or(rest, |end| &rest[..end]);
+    let Some((user_info, host)) = authority.rsplit_once('@') else {
+        return url.to_owned();
+    };
+    let Some((user, _)) = user_info.split_once(':') else {
+        return url.to_owned();
+    };
+
+    format!("{scheme}://{user}:***@{host}{}", &rest[authority.len()..])
+}
+
+/// Whether the config file turns `sqlite.foreign_keys` on itself rather than
+/// leaving it at the default.
+fn foreign_keys_set(text: &str) -> bool {
+    toml::from_str::<toml::Table>(text)
+        .ok()
+        .and_then(|t| t.get("sqlite")?.get("foreign_keys")?.as_bool())
+        .unwrap_or(false)
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::DeviceStateLog;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    /// Length of the rolling window ending now, in seconds. A month is 30 days.
+    pub fn seconds(self) -> i64 {
+        const DAY: i64 = 24 * 60 * 60;
+
+        match self {
+            Period::Day => DAY,
+            Period::Week => 7 * DAY,
+            Period::Month => 30 * DAY,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, IntoParams)]
+pub struct EnergyQuery {
+    #[serde(default)]
+    pub period: Period,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct EnergyReport {
+    pub house: i32,
+    pub period: Period,
+    pub from: i64,
+    pub to: i64,
+    pub kwh: f64,
+    pub rooms: Vec<RoomEnergy>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct RoomEnergy {
+    pub room: i32,
+    pub name: String,
+    pub kwh: f64,
+    pub devices: Vec<DeviceEnergy>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DeviceEnergy {
+    pub device: i32,
+    pub name: String,
+    pub kwh: f64,
+}
+
+/// Integrates on-time × power draw over `[from, to)` into kWh.
+///
+/// `log` is the state history of a single device ordered by `changed_at`;
+/// each entry holds until the next one, the last one until `to`.
+pub fn consumption(log: &[DeviceStateLog], from: i64, to: i64) -> f64 {
+    let mut watt_seconds = 0.0;
+
+    for (i, entry) in log.iter().enumerate() {
+        let start = entry.changed_at.max(from);
+        let end = log.get(i + 1).map_or(to, |next| next.changed_at).min(to);
+
+        if entry.state && end > start {
+            watt_seconds += entry.power.unwrap_or_default() * (end - start) as f64;
+        }
+    }
+
+    watt_seconds / 3_600_000.0
+}
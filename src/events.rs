@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::{Alert, Device};
+
+/// Change notifications broadcast to `GET /houses/{house_id}/events` subscribers.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Device { house: i32, device: Device },
+    Alert { house: i32, alert: Alert },
+}
+
+impl Event {
+    pub fn house(&self) -> i32 {
+        match self {
+            Event::Device { house, .. } | Event::Alert { house, .. } => *house,
+        }
+    }
+}
@@ -1,322 +1,2784 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Json, Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Json, OriginalUri, Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{
+        Html, IntoResponse,
+        sse::{Event as SseEvent, KeepAlive, Sse},
+    },
+};
+use diesel::{
+    BelongingToDsl, ExpressionMethods, GroupedBy, OptionalExtension, QueryDsl, RunQueryDsl,
+    SelectableHelper,
 };
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
 use serde::Deserialize;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use utoipa::{IntoParams, OpenApi, ToSchema, openapi::Server};
 
 use crate::{
     AppState,
-    models::{Device, House, NewDevice, NewHouse, NewRoom, Room},
-    schema::{self, house::name},
+    alerts::{self, AlertForm, AlertsQuery},
+    audit::{AuditEntry, AuditQuery},
+    auth::{
+        self, Admin, Caller, InviteForm, InviteScope, IssuedInvite, IssuedToken, Member,
+        MemberForm, Permission, Resource, Role, Session, TokenForm, UserForm,
+    },
+    backup::{self, Backup, BackupError, RestoreForm, Restored},
+    energy::{self, DeviceEnergy, EnergyQuery, EnergyReport, RoomEnergy},
+    events::Event,
+    health::{self, Readiness},
+    layout::{
+        ConfigQuery, ConfigSync, ExportQuery, Format, HouseDoc, ImportMode, ImportQuery, Imported,
+        Kind, free_name,
+    },
+    metrics::METRICS,
+    models::{
+        Alert, ApiToken, AuditLog, Device, House, HouseMember, Invite, InviteDevice, InviteRoom,
+        NewAlert, NewApiToken, NewDevice, NewInvite, NewReading, NewUser, Reading, Room, SOCKET,
+        User, check_power, reports_readings,
+    },
+    openapi::{ApiDoc, DOCS_HTML},
+    random_token,
+    readings::{self, ReadingForm, ReadingStats, ReadingsQuery},
+    repository::{DeviceChange, HomeRepository, LayoutChange, RepoError},
+    schema,
+    telemetry::{Counted, CountedRow},
+    trash::{self, Trashed},
+    unix_now,
 };
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, serde::Serialize, Debug, ToSchema)]
 pub struct HouseForm {
-    name: String,
+    pub name: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, serde::Serialize, Debug, ToSchema)]
 pub struct RoomForm {
-    name: String,
+    pub name: String,
 }
 
-#[derive(Deserialize, serde::Serialize, Debug)]
+#[derive(Deserialize, serde::Serialize, Debug, ToSchema)]
 pub struct DeviceForm {
     pub name: String,
     pub state: bool,
     pub device: String,
+    /// Power draw in watts, only accepted for sockets.
+    #[serde(default)]
+    pub power: Option<f64>,
+}
+
+/// Seconds a login session stays valid.
+const SESSION_TTL: i64 = 7 * 24 * 3600;
+
+const MIN_PASSWORD_LEN: usize = 8;
+
+/// Longest an invite may stay valid, thirty days.
+const MAX_INVITE_TTL: i64 = 30 * 24 * 3600;
+
+/// Seconds a drop-all confirmation token stays valid.
+const DROP_ALL_TOKEN_TTL: i64 = 60;
+
+#[derive(Deserialize, serde::Serialize, Debug, ToSchema)]
+pub struct DropAllToken {
+    pub token: String,
+    pub expires_at: i64,
+}
+
+#[derive(Deserialize, serde::Serialize, Debug, IntoParams)]
+pub struct DropAllQuery {
+    pub confirm: Option<String>,
+}
+
+#[derive(Deserialize, serde::Serialize, Debug, ToSchema)]
+pub struct DroppedCounts {
+    pub houses: usize,
+    pub rooms: usize,
+    pub devices: usize,
+    pub readings: usize,
+    pub alerts: usize,
+    pub state_changes: usize,
+}
+
+impl DeviceForm {
+    fn check_power(&self) -> Result<(), (StatusCode, String)> {
+        check_power(&self.device, self.power).map_err(|e| (StatusCode::BAD_REQUEST, e))
+    }
 }
 
-pub async fn list_houses(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    use schema::house::dsl::*;
+#[utoipa::path(
+    get,
+    path = "/house",
+    tag = "houses",
+    responses(
+        (status = 200, description = "Houses the caller can see", body = Vec<House>),
+        (status = 401, description = "Missing, expired or revoked token"),
+    ),
+)]
+pub async fn list_houses(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+) -> Result<Json<Vec<House>>, (StatusCode, String)> {
+    if caller.admin {
+        let res = app_state.repo.houses().map_err(repo_error)?;
 
-    let mut dbh = state.pool.get().expect("cant connect");
+        return Ok(Json(res));
+    }
 
-    let res = house.load::<House>(&mut *dbh).expect("cant execute");
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    let visible = match (caller.user, caller.invite) {
+        (_, Some(invite_id)) => schema::invite::table
+            .filter(schema::invite::id.eq(invite_id))
+            .select(schema::invite::house)
+            .load::<i32>(&mut dbh)
+            .counted()
+            .map_err(internal_error)?,
+        (Some(user_id), None) => schema::house_member::table
+            .filter(schema::house_member::user.eq(user_id))
+            .select(schema::house_member::house)
+            .load::<i32>(&mut dbh)
+            .counted()
+            .map_err(internal_error)?,
+        // Service tokens without a user see no houses.
+        (None, None) => Vec::new(),
+    };
+
+    let res = app_state.repo.houses_in(&visible).map_err(repo_error)?;
 
-    Json(res)
+    Ok(Json(res))
 }
 
+#[utoipa::path(
+    post,
+    path = "/house",
+    tag = "houses",
+    request_body = HouseForm,
+    responses(
+        (status = 200, description = "The created house, owned by the caller", body = House),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Invite or service token"),
+        (status = 409, description = "Name already taken"),
+    ),
+)]
 pub async fn add_house(
-    State(state): State<Arc<AppState>>,
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
     Json(house_form): Json<HouseForm>,
 ) -> Result<Json<House>, (StatusCode, String)> {
-    use crate::schema::house::dsl::house;
+    auth::authorize_new_house(&caller)?;
 
-    let mut dbh = state.pool.get().map_err(internal_error)?;
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    let _ = diesel::insert_into(house)
-        .values(NewHouse {
-            name: house_form.name.clone(),
-        })
-        .execute(&mut *dbh)
-        .map_err(internal_error)?;
+    let applied = app_state
+        .repo
+        .apply_with(
+            &[LayoutChange::AddHouse {
+                name: house_form.name.clone(),
+            }],
+            &mut dbh,
+            &mut |conn, ids| own_house(conn, &caller, ids[0]),
+        )
+        .map_err(repo_error)?;
+
+    Ok(Json(House {
+        id: applied[0],
+        name: house_form.name,
+    }))
+}
 
-    let res = house
-        .filter(name.eq(house_form.name))
-        .select(House::as_select())
-        .first(&mut dbh)
-        .map_err(internal_error)?;
+/// Makes the caller the owner of the house it just created.
+///
+/// A house without an owner would be out of everyone's reach, so this runs
+/// as part of the change that adds the house.
+fn own_house(
+    conn: &mut diesel::SqliteConnection,
+    caller: &Caller,
+    house_id: i32,
+) -> diesel::QueryResult<()> {
+    let Some(user_id) = caller.user else {
+        return Ok(());
+    };
+
+    diesel::insert_into(schema::house_member::table)
+        .values(&HouseMember {
+            house: house_id,
+            user: user_id,
+            role: Role::Owner.as_str().to_owned(),
+        })
+        .execute(conn)
+        .counted()?;
 
-    Ok(Json(res))
+    Ok(())
 }
 
+#[utoipa::path(
+    put,
+    path = "/houses/{house_id}",
+    tag = "houses",
+    params(("house_id" = i32, Path)),
+    request_body = HouseForm,
+    responses(
+        (status = 200, description = "The renamed house", body = House),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+        (status = 409, description = "Name already taken"),
+    ),
+)]
 pub async fn upd_house(
     State(app_state): State<Arc<AppState>>,
+    caller: Caller,
     Path(house_id): Path<i32>,
     Json(house_form): Json<HouseForm>,
 ) -> Result<Json<House>, (StatusCode, String)> {
-    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+    auth::authorize_new_house(&caller)?;
 
-    use schema::house::dsl::*;
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    let _ = diesel::update(house)
-        .filter(id.eq(house_id))
-        .set(name.eq::<String>(house_form.name))
-        .execute(&mut *dbh)
-        .map_err(internal_error)?;
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Owner,
+    )?;
 
-    let res = house
-        .filter(id.eq(house_id))
-        .select(House::as_select())
-        .first(&mut dbh)
-        .map_err(internal_error)?;
+    let res = app_state
+        .repo
+        .rename_house(house_id, &house_form.name)
+        .map_err(repo_error)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no house {house_id}")))?;
 
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/houses/{house_id}",
+    tag = "houses",
+    params(("house_id" = i32, Path)),
+    responses(
+        (status = 200, description = "Number of houses moved to the trash", body = String),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
 pub async fn del_house(
     State(app_state): State<Arc<AppState>>,
+    caller: Caller,
     Path(house_id): Path<i32>,
 ) -> Result<Json<String>, (StatusCode, String)> {
     let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    use schema::house::dsl::*;
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Owner,
+    )?;
 
-    let res = diesel::delete(house.filter(id.eq(house_id)))
-        .execute(&mut *dbh)
-        .map_err(internal_error)?;
+    let res = app_state.repo.delete_house(house_id).map_err(repo_error)?;
 
     Ok(Json(res.to_string()))
 }
 
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/export",
+    tag = "houses",
+    params(
+        ("house_id" = i32, Path),
+        ExportQuery,
+    ),
+    responses(
+        (status = 200, description = "The house, its rooms and devices", content(
+            (HouseDoc = "application/json"),
+            (HouseDoc = "application/yaml"),
+        )),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "No such house"),
+    ),
+)]
+pub async fn export_house(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(house_id): Path<i32>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Guest,
+    )?;
+
+    let house = app_state
+        .repo
+        .house(house_id)
+        .map_err(repo_error)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no house {house_id}")))?;
+
+    let doc = HouseDoc::export(app_state.repo.as_ref(), &house, query.ids).map_err(repo_error)?;
+    let res = query
+        .format
+        .render(&doc)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(([(header::CONTENT_TYPE, query.format.content_type())], res))
+}
+
+#[utoipa::path(
+    post,
+    path = "/house/import",
+    tag = "houses",
+    params(ImportQuery),
+    request_body(
+        description = "A document from `GET /houses/{house_id}/export`, as JSON or YAML by its content type",
+        content(
+            (HouseDoc = "application/json"),
+            (HouseDoc = "application/yaml"),
+        ),
+    ),
+    responses(
+        (status = 200, description = "The house created or merged into", body = Imported),
+        (status = 400, description = "Unreadable or inconsistent document"),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed to add or merge into the house"),
+        (status = 409, description = "A house of that name exists and `on_conflict` is `fail`"),
+    ),
+)]
+pub async fn import_house(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Query(query): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<Imported>, (StatusCode, String)> {
+    let doc = house_doc(&headers, &body)?;
+
+    let houses = app_state.repo.houses().map_err(repo_error)?;
+
+    let (changes, merged) = match houses.iter().find(|h| h.name == doc.name) {
+        None => {
+            auth::authorize_new_house(&caller)?;
+            (doc.create(&doc.name), None)
+        }
+        Some(_) if query.on_conflict == ImportMode::Fail => {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("house {:?} already exists", doc.name),
+            ));
+        }
+        Some(_) if query.on_conflict == ImportMode::Rename => {
+            auth::authorize_new_house(&caller)?;
+            (doc.create(&free_name(&doc.name, &houses)), None)
+        }
+        Some(house) => {
+            let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+            auth::authorize(
+                app_state.repo.as_ref(),
+                &mut dbh,
+                &caller,
+                Resource::house(house.id),
+                Role::Member,
+            )?;
+
+            let rooms = app_state.repo.rooms(house.id).map_err(repo_error)?;
+            let devices = app_state.repo.house_devices(house.id).map_err(repo_error)?;
+
+            (doc.merge(house.id, &rooms, &devices), Some(house.clone()))
+        }
+    };
+
+    let created = merged.is_none();
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+    let applied = app_state
+        .repo
+        .apply_with(&changes, &mut dbh, &mut |conn, ids| {
+            if created {
+                own_house(conn, &caller, ids[0])
+            } else {
+                Ok(())
+            }
+        })
+        .map_err(repo_error)?;
+
+    let house = match merged {
+        Some(house) => house,
+        None => House {
+            id: applied[0],
+            name: match &changes[0] {
+                LayoutChange::AddHouse { name } => name.clone(),
+                _ => doc.name.clone(),
+            },
+        },
+    };
+
+    let mut res = Imported {
+        created,
+        house,
+        rooms_added: 0,
+        devices_added: 0,
+        devices_updated: 0,
+    };
+    for (change, id) in changes.iter().zip(&applied) {
+        match change {
+            LayoutChange::AddRoom { .. } => res.rooms_added += 1,
+            LayoutChange::AddDevice { .. } => res.devices_added += 1,
+            LayoutChange::UpdateDevice { .. } => res.devices_updated += 1,
+            _ => continue,
+        }
+
+        if let LayoutChange::AddDevice { .. } | LayoutChange::UpdateDevice { .. } = change
+            && let Ok(Some(device)) = app_state.repo.device(*id)
+        {
+            let _ = app_state.events.send(Event::Device {
+                house: res.house.id,
+                device,
+            });
+        }
+    }
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    put,
+    path = "/houses/{house_id}/config",
+    tag = "houses",
+    params(
+        ("house_id" = i32, Path),
+        ConfigQuery,
+    ),
+    request_body(
+        description = "The whole desired house, as JSON or YAML by its content type; \
+            rooms and devices with an id are renamed, the others matched by name",
+        content(
+            (HouseDoc = "application/json"),
+            (HouseDoc = "application/yaml"),
+        ),
+    ),
+    responses(
+        (status = 200, description = "Changes made, or only planned with `dry_run`", body = ConfigSync),
+        (status = 400, description = "Unreadable or inconsistent document"),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "No such house"),
+        (status = 409, description = "The changes clash on a name, or the house changed meanwhile"),
+    ),
+)]
+pub async fn put_config(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(house_id): Path<i32>,
+    Query(query): Query<ConfigQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<ConfigSync>, (StatusCode, String)> {
+    let doc = house_doc(&headers, &body)?;
+
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Owner,
+    )?;
+
+    let house = app_state
+        .repo
+        .house(house_id)
+        .map_err(repo_error)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no house {house_id}")))?;
+    let rooms = app_state.repo.rooms(house_id).map_err(repo_error)?;
+    let devices = app_state.repo.house_devices(house_id).map_err(repo_error)?;
+
+    let plan = doc
+        .sync(&house, &rooms, &devices)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if query.dry_run {
+        return Ok(Json(ConfigSync {
+            dry_run: true,
+            changes: plan.steps,
+        }));
+    }
+
+    let applied = app_state.repo.apply(&plan.changes).map_err(repo_error)?;
+
+    for (change, id) in plan.changes.iter().zip(&applied) {
+        if let LayoutChange::AddDevice { .. } | LayoutChange::UpdateDevice { .. } = change
+            && let Ok(Some(device)) = app_state.repo.device(*id)
+        {
+            let _ = app_state.events.send(Event::Device {
+                house: house_id,
+                device,
+            });
+        }
+    }
+
+    Ok(Json(ConfigSync {
+        dry_run: false,
+        changes: plan.applied(&applied),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/rooms",
+    tag = "rooms",
+    params(("house_id" = i32, Path)),
+    responses(
+        (status = 200, description = "Rooms of the house", body = Vec<Room>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
 pub async fn get_rooms(
-    State(state): State<Arc<AppState>>,
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
     Path(house_id): Path<i32>,
 ) -> Result<Json<Vec<Room>>, (StatusCode, String)> {
-    use crate::schema::room::dsl::*;
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    let mut dbh = state.pool.get().map_err(internal_error)?;
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Guest,
+    )?;
 
-    let res = room
-        .filter(house.eq(house_id))
-        .load::<Room>(&mut *dbh)
-        .map_err(internal_error)?;
+    let res = app_state.repo.rooms(house_id).map_err(repo_error)?;
 
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    post,
+    path = "/houses/{house_id}/rooms",
+    tag = "rooms",
+    params(("house_id" = i32, Path)),
+    request_body = RoomForm,
+    responses(
+        (status = 200, description = "The created room", body = Room),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 409, description = "Name already taken"),
+    ),
+)]
 pub async fn add_room(
-    State(state): State<Arc<AppState>>,
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
     Path(house_id): Path<i32>,
     Json(room_form): Json<RoomForm>,
 ) -> Result<Json<Room>, (StatusCode, String)> {
-    use schema::room::dsl::*;
-
-    let mut dbh = state.pool.get().map_err(internal_error)?;
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    let room_name = room_form.name;
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Member,
+    )?;
 
-    let _ = diesel::insert_into(room)
-        .values(&NewRoom {
-            house: house_id,
-            name: room_name.to_owned(),
-        })
-        .execute(&mut *dbh)
-        .map_err(internal_error)?;
-
-    let res = room
-        .filter(house.eq(house_id))
-        .filter(name.eq(room_name.to_owned()))
-        .select(Room::as_select())
-        .first(&mut dbh)
-        .map_err(internal_error)?;
+    let res = app_state
+        .repo
+        .add_room(house_id, &room_form.name)
+        .map_err(repo_error)?;
 
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    put,
+    path = "/houses/{house_id}/rooms/{room_id}",
+    tag = "rooms",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+    ),
+    request_body = RoomForm,
+    responses(
+        (status = 200, description = "The renamed room", body = Room),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+        (status = 409, description = "Name already taken"),
+    ),
+)]
 pub async fn upd_room(
     State(app_state): State<Arc<AppState>>,
+    caller: Caller,
     Path((house_id, room_id)): Path<(i32, i32)>,
     Json(room_form): Json<RoomForm>,
 ) -> Result<Json<Room>, (StatusCode, String)> {
     let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    let room_name = room_form.name;
-
-    use schema::room::dsl::*;
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id),
+        Role::Member,
+    )?;
 
-    let _ = diesel::update(room)
-        .filter(id.eq(room_id))
-        .set(name.eq(room_name.to_owned()))
-        .execute(&mut *dbh)
-        .map_err(internal_error)?;
-
-    let res = room
-        .filter(house.eq(house_id))
-        .filter(name.eq(room_name.to_owned()))
-        .select(Room::as_select())
-        .first(&mut dbh)
-        .map_err(internal_error)?;
+    let res = app_state
+        .repo
+        .rename_room(room_id, &room_form.name)
+        .map_err(repo_error)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no room {room_id}")))?;
 
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/houses/{house_id}/rooms/{room_id}",
+    tag = "rooms",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+    ),
+    responses(
+        (status = 200, description = "Number of rooms moved to the trash", body = String),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
 pub async fn del_room(
     State(app_state): State<Arc<AppState>>,
-    Path(room_id): Path<i32>,
+    caller: Caller,
+    Path((house_id, room_id)): Path<(i32, i32)>,
 ) -> Result<Json<String>, (StatusCode, String)> {
     let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    use schema::room::dsl::*;
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id),
+        Role::Member,
+    )?;
 
-    let res = diesel::delete(room.filter(id.eq(room_id)))
-        .execute(&mut *dbh)
-        .map_err(internal_error)?;
+    let res = app_state.repo.delete_room(room_id).map_err(repo_error)?;
 
     Ok(Json(res.to_string()))
 }
 
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/rooms/{room_id}/devices",
+    tag = "devices",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+    ),
+    responses(
+        (status = 200, description = "Devices in the room", body = Vec<Device>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
 pub async fn get_devices(
     State(app_state): State<Arc<AppState>>,
-    Path((_house_id, room_id)): Path<(i32, i32)>,
+    caller: Caller,
+    Path((house_id, room_id)): Path<(i32, i32)>,
 ) -> Result<Json<Vec<Device>>, (StatusCode, String)> {
     let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    use schema::device::dsl::*;
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id),
+        Role::Guest,
+    )?;
 
-    let res = device
-        .filter(room.eq(room_id))
-        .load::<Device>(&mut *dbh)
-        .map_err(internal_error)?;
+    let res = app_state.repo.devices(room_id).map_err(repo_error)?;
 
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    post,
+    path = "/houses/{house_id}/rooms/{room_id}/devices",
+    tag = "devices",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+    ),
+    request_body = DeviceForm,
+    responses(
+        (status = 200, description = "The created device", body = Device),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 400, description = "Invalid input"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+        (status = 409, description = "Name already taken"),
+    ),
+)]
 pub async fn add_device(
     State(app_state): State<Arc<AppState>>,
-    Path((_house_id, room_id)): Path<(i32, i32)>,
+    caller: Caller,
+    Path((house_id, room_id)): Path<(i32, i32)>,
     Json(new_device): Json<DeviceForm>,
 ) -> Result<Json<Device>, (StatusCode, String)> {
-    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+    new_device.check_power()?;
 
-    use schema::device::dsl::*;
-
-    let dev_name = new_device.name;
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    let _ = diesel::insert_into(device)
-        .values(&NewDevice {
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id),
+        Role::Member,
+    )?;
+
+    let res = app_state
+        .repo
+        .add_device(NewDevice {
             room: room_id,
-            name: dev_name.to_owned(),
+            name: new_device.name,
             //state: new_device.state,
             state: false,
             device_type: new_device.device,
+            power: new_device.power,
         })
-        .execute(&mut *dbh)
-        .map_err(internal_error)?;
+        .map_err(repo_error)?;
 
-    let res = device
-        .filter(room.eq(room_id))
-        .filter(name.eq(dev_name.to_owned()))
-        .select(Device::as_select())
-        .first(&mut dbh)
-        .map_err(internal_error)?;
+    let _ = app_state.events.send(Event::Device {
+        house: house_id,
+        device: res.clone(),
+    });
 
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    put,
+    path = "/houses/{house_id}/rooms/{room_id}/devices/{device_id}",
+    tag = "devices",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+        ("device_id" = i32, Path),
+    ),
+    request_body = DeviceForm,
+    responses(
+        (status = 200, description = "The updated device", body = Device),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 400, description = "Invalid input"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+        (status = 409, description = "Name already taken"),
+    ),
+)]
 pub async fn upd_device(
     State(app_state): State<Arc<AppState>>,
-    Path((_house_id, room_id, device_id)): Path<(i32, i32, i32)>,
+    caller: Caller,
+    Path((house_id, room_id, device_id)): Path<(i32, i32, i32)>,
     Json(form): Json<DeviceForm>,
 ) -> Result<Json<Device>, (StatusCode, String)> {
+    form.check_power()?;
+
     let mut dbh = app_state.pool.get().map_err(internal_error)?;
+    let resource = Resource::house(house_id).room(room_id).device(device_id);
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        resource,
+        Role::Operator,
+    )?;
+
+    // Switching a device is all an operator may do; anything else takes a member.
+    let current = app_state
+        .repo
+        .device(device_id)
+        .map_err(repo_error)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no device {device_id}")))?;
+    if (&current.name, &current.device_type, current.power)
+        != (&form.name, &form.device, form.power)
+    {
+        auth::authorize_role(&mut dbh, &caller, resource, Role::Member)?;
+    }
 
-    use schema::device::dsl::*;
+    let res = app_state
+        .repo
+        .update_device(
+            device_id,
+            DeviceChange {
+                name: form.name,
+                device_type: form.device,
+                state: form.state,
+                power: form.power,
+            },
+        )
+        .map_err(repo_error)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no device {device_id}")))?;
+
+    let _ = app_state.events.send(Event::Device {
+        house: house_id,
+        device: res.clone(),
+    });
 
-    let dev_name = form.name;
+    Ok(Json(res))
+}
 
-    let _ = diesel::update(device)
-        .filter(id.eq(device_id))
-        .filter(room.eq(room_id))
-        .set((
-            name.eq(dev_name.to_owned()),
-            state.eq(form.state),
-            device_type.eq(form.device),
-        ))
+#[utoipa::path(
+    delete,
+    path = "/houses/{house_id}/rooms/{room_id}/devices/{device_id}",
+    tag = "devices",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+        ("device_id" = i32, Path),
+    ),
+    responses(
+        (status = 200, description = "Number of devices moved to the trash", body = String),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn del_device(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, room_id, device_id)): Path<(i32, i32, i32)>,
+) -> Result<Json<String>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id).device(device_id),
+        Role::Member,
+    )?;
+
+    let res = app_state
+        .repo
+        .delete_device(device_id)
+        .map_err(repo_error)?;
+
+    Ok(Json(res.to_string()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/trash",
+    tag = "trash",
+    responses(
+        (status = 200, description = "Deleted houses, rooms and devices of the houses the caller \
+            is a member of; rows deleted along with their house or room come back with it", body = Vec<Trashed>),
+        (status = 401, description = "Missing, expired or revoked token"),
+    ),
+)]
+pub async fn list_trash(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+) -> Result<Json<Vec<Trashed>>, (StatusCode, String)> {
+    let trash = trash::top_level(app_state.repo.trash().map_err(repo_error)?);
+
+    if caller.admin {
+        return Ok(Json(trash));
+    }
+
+    // Invites never change the layout, so they see no trash.
+    let visible = match (caller.user, caller.invite) {
+        (Some(user_id), None) => {
+            let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+            schema::house_member::table
+                .filter(schema::house_member::user.eq(user_id))
+                .select(schema::house_member::house)
+                .load::<i32>(&mut dbh)
+                .counted()
+                .map_err(internal_error)?
+        }
+        _ => Vec::new(),
+    };
+
+    Ok(Json(
+        trash
+            .into_iter()
+            .filter(|t| visible.contains(&t.house))
+            .collect(),
+    ))
+}
+
+/// The entry of the row in the trash, if it is there and in `house_id`.
+fn trashed(
+    app_state: &AppState,
+    kind: Kind,
+    id: i32,
+    house_id: i32,
+) -> Result<Option<Trashed>, (StatusCode, String)> {
+    Ok(app_state
+        .repo
+        .trash()
+        .map_err(repo_error)?
+        .into_iter()
+        .find(|t| t.kind == kind && t.id == id && t.house == house_id))
+}
+
+#[utoipa::path(
+    post,
+    path = "/houses/{house_id}/restore",
+    tag = "trash",
+    params(("house_id" = i32, Path)),
+    responses(
+        (status = 200, description = "The house, back with the rooms and devices deleted along with it", body = House),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "The house is not in the trash"),
+        (status = 409, description = "Name taken in the meantime"),
+    ),
+)]
+pub async fn restore_house(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(house_id): Path<i32>,
+) -> Result<Json<House>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize_role(&mut dbh, &caller, Resource::house(house_id), Role::Owner)?;
+
+    let res = app_state
+        .repo
+        .restore_house(house_id)
+        .map_err(repo_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("house {house_id} is not in the trash"),
+        ))?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    post,
+    path = "/houses/{house_id}/rooms/{room_id}/restore",
+    tag = "trash",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+    ),
+    responses(
+        (status = 200, description = "The room, back with the devices deleted along with it", body = Room),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "The room is not in the trash of the house"),
+        (status = 409, description = "Name taken in the meantime, or the house is deleted too"),
+    ),
+)]
+pub async fn restore_room(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, room_id)): Path<(i32, i32)>,
+) -> Result<Json<Room>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize_role(&mut dbh, &caller, Resource::house(house_id), Role::Member)?;
+
+    if trashed(&app_state, Kind::Room, room_id, house_id)?.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("room {room_id} is not in the trash"),
+        ));
+    }
+    if trashed(&app_state, Kind::House, house_id, house_id)?.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("house {house_id} is deleted, restore it first"),
+        ));
+    }
+
+    let res = app_state
+        .repo
+        .restore_room(room_id)
+        .map_err(repo_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("room {room_id} is not in the trash"),
+        ))?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    post,
+    path = "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/restore",
+    tag = "trash",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+        ("device_id" = i32, Path),
+    ),
+    responses(
+        (status = 200, description = "The device, back in its room", body = Device),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "The device is not in the trash of the room"),
+        (status = 409, description = "Name taken in the meantime, or the room is deleted too"),
+    ),
+)]
+pub async fn restore_device(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, room_id, device_id)): Path<(i32, i32, i32)>,
+) -> Result<Json<Device>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize_role(&mut dbh, &caller, Resource::house(house_id), Role::Member)?;
+
+    let found = trashed(&app_state, Kind::Device, device_id, house_id)?;
+    if found.is_none_or(|t| t.room != Some(room_id)) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("device {device_id} is not in the trash"),
+        ));
+    }
+    if trashed(&app_state, Kind::Room, room_id, house_id)?.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("room {room_id} is deleted, restore it first"),
+        ));
+    }
+
+    let res = app_state
+        .repo
+        .restore_device(device_id)
+        .map_err(repo_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("device {device_id} is not in the trash"),
+        ))?;
+
+    let _ = app_state.events.send(Event::Device {
+        house: house_id,
+        device: res.clone(),
+    });
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/house/drop-token",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Single-use confirmation token", body = DropAllToken),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn drop_all_token(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+) -> Result<Json<DropAllToken>, (StatusCode, String)> {
+    drop_all_enabled(&app_state)?;
+
+    let token = DropAllToken {
+        token: random_token(),
+        expires_at: unix_now() + DROP_ALL_TOKEN_TTL,
+    };
+
+    *app_state.drop_all_token.lock().map_err(internal_error)? =
+        Some((token.token.clone(), token.expires_at));
+
+    Ok(Json(token))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/house",
+    tag = "admin",
+    params(DropAllQuery),
+    responses(
+        (status = 200, description = "Rows deleted per table", body = DroppedCounts),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 428, description = "Confirmation token missing or stale"),
+    ),
+)]
+pub async fn drop_all(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+    Query(query): Query<DropAllQuery>,
+) -> Result<Json<DroppedCounts>, (StatusCode, String)> {
+    drop_all_enabled(&app_state)?;
+
+    // The token is single use: taken out whether it matches or not.
+    let issued = app_state
+        .drop_all_token
+        .lock()
+        .map_err(internal_error)?
+        .take();
+
+    match (issued, query.confirm) {
+        (Some((token, expires_at)), Some(confirm))
+            if token == confirm && unix_now() < expires_at => {}
+        _ => {
+            return Err((
+                StatusCode::PRECONDITION_REQUIRED,
+                "confirm with a fresh token from POST /admin/house/drop-token".to_owned(),
+            ));
+        }
+    }
+
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    let (mut readings, mut alerts) = (0, 0);
+    let layout = app_state
+        .repo
+        .clear_with(&mut dbh, &mut |conn| {
+            readings = diesel::delete(schema::reading::table)
+                .execute(conn)
+                .counted()?;
+            alerts = diesel::delete(schema::alert::table)
+                .execute(conn)
+                .counted()?;
+            diesel::delete(schema::house_member::table)
+                .execute(conn)
+                .counted()?;
+            diesel::delete(schema::invite_device::table)
+                .execute(conn)
+                .counted()?;
+            diesel::delete(schema::invite_room::table)
+                .execute(conn)
+                .counted()?;
+            diesel::update(schema::api_token::table)
+                .filter(schema::api_token::invite.is_not_null())
+                .set((
+                    schema::api_token::invite.eq(None::<i32>),
+                    schema::api_token::revoked_at.eq(unix_now()),
+                ))
+                .execute(conn)
+                .counted()?;
+            diesel::delete(schema::invite::table)
+                .execute(conn)
+                .counted()?;
+
+            Ok(())
+        })
+        .map_err(repo_error)?;
+
+    Ok(Json(DroppedCounts {
+        houses: layout.houses,
+        rooms: layout.rooms,
+        devices: layout.devices,
+        readings,
+        alerts,
+        state_changes: layout.state_changes,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/backup",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Backup just written", body = Backup),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Backups are disabled on this server"),
+    ),
+)]
+pub async fn add_backup(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+) -> Result<Json<Backup>, (StatusCode, String)> {
+    Ok(Json(backup::create(&app_state).map_err(backup_error)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/backups",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Backups, newest first", body = [Backup]),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Backups are disabled on this server"),
+    ),
+)]
+pub async fn list_backups(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+) -> Result<Json<Vec<Backup>>, (StatusCode, String)> {
+    let settings = app_state
+        .backups
+        .as_ref()
+        .ok_or(BackupError::Disabled)
+        .map_err(backup_error)?;
+
+    Ok(Json(backup::list(&settings.dir).map_err(backup_error)?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/restore",
+    tag = "admin",
+    request_body = RestoreForm,
+    responses(
+        (status = 200, description = "Database replaced with the backup", body = Restored),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "No such backup, or backups are disabled"),
+        (status = 409, description = "Backup taken with other migrations applied, or inconsistent"),
+    ),
+)]
+pub async fn restore_backup(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+    Json(form): Json<RestoreForm>,
+) -> Result<Json<Restored>, (StatusCode, String)> {
+    Ok(Json(
+        backup::restore(&app_state, &form.name).map_err(backup_error)?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/energy",
+    tag = "houses",
+    params(
+        ("house_id" = i32, Path),
+        EnergyQuery,
+    ),
+    responses(
+        (status = 200, description = "Consumption of the sockets in the house", body = EnergyReport),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn get_energy(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(house_id): Path<i32>,
+    Query(query): Query<EnergyQuery>,
+) -> Result<Json<EnergyReport>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Guest,
+    )?;
+
+    let to = unix_now();
+    let from = to - query.period.seconds();
+
+    let rooms = app_state.repo.rooms(house_id).map_err(repo_error)?;
+
+    let devices = app_state
+        .repo
+        .house_devices(house_id)
+        .map_err(repo_error)?
+        .into_iter()
+        .filter(|d| d.device_type == SOCKET)
+        .collect::<Vec<_>>();
+
+    let logs = app_state
+        .repo
+        .state_history(&devices.iter().map(|d| d.id).collect::<Vec<_>>(), to)
+        .map_err(repo_error)?;
+
+    let rooms = rooms
+        .into_iter()
+        .map(|room| {
+            let devices = devices
+                .iter()
+                .filter(|device| device.room == room.id)
+                .map(|device| {
+                    let log = logs
+                        .iter()
+                        .filter(|l| l.device == device.id)
+                        .cloned()
+                        .collect::<Vec<_>>();
+
+                    DeviceEnergy {
+                        device: device.id,
+                        name: device.name.clone(),
+                        kwh: energy::consumption(&log, from, to),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            RoomEnergy {
+                room: room.id,
+                name: room.name,
+                kwh: devices.iter().map(|d| d.kwh).sum(),
+                devices,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(EnergyReport {
+        house: house_id,
+        period: query.period,
+        from,
+        to,
+        kwh: rooms.iter().map(|r| r.kwh).sum(),
+        rooms,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/readings",
+    tag = "readings",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+        ("device_id" = i32, Path),
+    ),
+    request_body = Vec<ReadingForm>,
+    responses(
+        (status = 200, description = "Number of stored readings", body = usize),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 400, description = "Invalid input"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn add_readings(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, room_id, device_id)): Path<(i32, i32, i32)>,
+    Json(batch): Json<Vec<ReadingForm>>,
+) -> Result<Json<usize>, (StatusCode, String)> {
+    if batch.iter().any(|r| !r.value.is_finite()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "reading values must be finite numbers".to_owned(),
+        ));
+    }
+
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id).device(device_id),
+        Role::Member,
+    )?;
+
+    let dev = sensor(app_state.repo.as_ref(), house_id, room_id, device_id)?;
+
+    let now = unix_now();
+    let rows = batch
+        .iter()
+        .map(|r| NewReading {
+            device: device_id,
+            value: r.value,
+            taken_at: r.taken_at.unwrap_or(now),
+        })
+        .collect::<Vec<_>>();
+
+    let Some(latest) = rows.iter().max_by_key(|r| r.taken_at) else {
+        return Ok(Json(0));
+    };
+
+    // Only readings newer than the last known one drive the alert state machine.
+    let mut fresh = rows
+        .iter()
+        .filter(|r| dev.last_reading_at.is_none_or(|last| r.taken_at >= last))
+        .collect::<Vec<_>>();
+    fresh.sort_by_key(|r| r.taken_at);
+
+    // The readings are stored together with the alert states they lead to.
+    let (res, fired) = dbh
+        .immediate_transaction(|conn| {
+            // SQLite inserts a batch one statement per row, so each is counted on its own.
+            let mut res = 0;
+            for row in &rows {
+                res += diesel::insert_into(schema::reading::table)
+                    .values(row)
+                    .execute(conn)
+                    .counted()?;
+            }
+
+            let rules = Alert::belonging_to(&dev)
+                .select(Alert::as_select())
+                .load(conn)
+                .counted()?;
+
+            let mut fired = Vec::new();
+            for mut rule in rules {
+                let mut changed = false;
+
+                for r in &fresh {
+                    if alerts::evaluate(&mut rule, r.value, r.taken_at) {
+                        changed = true;
+                    }
+                }
+
+                alerts::save(conn, &rule)?;
+                if changed {
+                    fired.push(rule);
+                }
+            }
+
+            Ok::<_, diesel::result::Error>((res, fired))
+        })
+        .map_err(internal_error)?;
+
+    for rule in fired {
+        alerts::notify(&app_state, house_id, rule);
+    }
+
+    app_state
+        .repo
+        .record_reading(device_id, latest.value, latest.taken_at)
+        .map_err(repo_error)?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/readings",
+    tag = "readings",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+        ("device_id" = i32, Path),
+        ReadingsQuery,
+    ),
+    responses(
+        (status = 200, description = "Readings in the time range", body = Vec<Reading>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 400, description = "Invalid input"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn get_readings(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, room_id, device_id)): Path<(i32, i32, i32)>,
+    Query(query): Query<ReadingsQuery>,
+) -> Result<Json<Vec<Reading>>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id).device(device_id),
+        Role::Guest,
+    )?;
+
+    sensor(app_state.repo.as_ref(), house_id, room_id, device_id)?;
+
+    let (from, to) = query.range(unix_now());
+
+    use schema::reading::dsl::*;
+
+    let res = reading
+        .filter(device.eq(device_id))
+        .filter(taken_at.ge(from))
+        .filter(taken_at.lt(to))
+        .order(taken_at)
+        .select(Reading::as_select())
+        .load(&mut dbh)
+        .counted()
+        .map_err(internal_error)?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/readings/stats",
+    tag = "readings",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+        ("device_id" = i32, Path),
+        ReadingsQuery,
+    ),
+    responses(
+        (status = 200, description = "Min, max and average per interval", body = Vec<ReadingStats>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 400, description = "Invalid input"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn get_reading_stats(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, room_id, device_id)): Path<(i32, i32, i32)>,
+    Query(query): Query<ReadingsQuery>,
+) -> Result<Json<Vec<ReadingStats>>, (StatusCode, String)> {
+    let (from, to) = query.range(unix_now());
+    let interval = query.interval.unwrap_or(to - from);
+
+    if interval <= 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "interval must be a positive number of seconds".to_owned(),
+        ));
+    }
+
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id).device(device_id),
+        Role::Guest,
+    )?;
+
+    sensor(app_state.repo.as_ref(), house_id, room_id, device_id)?;
+
+    let res = readings::stats(&mut dbh, device_id, from, to, interval).map_err(internal_error)?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    post,
+    path = "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/alerts",
+    tag = "alerts",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+        ("device_id" = i32, Path),
+    ),
+    request_body = AlertForm,
+    responses(
+        (status = 200, description = "The created rule", body = Alert),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 400, description = "Invalid input"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn add_alert(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, room_id, device_id)): Path<(i32, i32, i32)>,
+    Json(form): Json<AlertForm>,
+) -> Result<Json<Alert>, (StatusCode, String)> {
+    if !form.threshold.is_finite() || form.for_secs < 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "threshold must be finite and for_secs non-negative".to_owned(),
+        ));
+    }
+
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id).device(device_id),
+        Role::Member,
+    )?;
+
+    sensor(app_state.repo.as_ref(), house_id, room_id, device_id)?;
+
+    use schema::alert::dsl::*;
+
+    let res = dbh
+        .immediate_transaction(|conn| {
+            diesel::insert_into(alert)
+                .values(&NewAlert {
+                    device: device_id,
+                    kind: form.kind.as_str().to_owned(),
+                    threshold: form.threshold,
+                    for_secs: form.for_secs,
+                    changed_at: unix_now(),
+                })
+                .execute(conn)
+                .counted()?;
+
+            alert
+                .filter(device.eq(device_id))
+                .order(id.desc())
+                .select(Alert::as_select())
+                .first(conn)
+                .counted_row()
+        })
+        .map_err(internal_error)?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/alerts",
+    tag = "alerts",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+        ("device_id" = i32, Path),
+    ),
+    responses(
+        (status = 200, description = "Alert rules of the device", body = Vec<Alert>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn get_alerts(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, room_id, device_id)): Path<(i32, i32, i32)>,
+) -> Result<Json<Vec<Alert>>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id).device(device_id),
+        Role::Guest,
+    )?;
+
+    let dev = sensor(app_state.repo.as_ref(), house_id, room_id, device_id)?;
+
+    let res = Alert::belonging_to(&dev)
+        .select(Alert::as_select())
+        .load(&mut dbh)
+        .counted()
+        .map_err(internal_error)?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/alerts/{alert_id}",
+    tag = "alerts",
+    params(
+        ("house_id" = i32, Path),
+        ("room_id" = i32, Path),
+        ("device_id" = i32, Path),
+        ("alert_id" = i32, Path),
+    ),
+    responses(
+        (status = 200, description = "Number of deleted rules", body = String),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn del_alert(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, room_id, device_id, alert_id)): Path<(i32, i32, i32, i32)>,
+) -> Result<Json<String>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id).room(room_id).device(device_id),
+        Role::Member,
+    )?;
+
+    sensor(app_state.repo.as_ref(), house_id, room_id, device_id)?;
+
+    use schema::alert::dsl::*;
+
+    let res = diesel::delete(alert.filter(id.eq(alert_id)).filter(device.eq(device_id)))
         .execute(&mut *dbh)
+        .counted()
         .map_err(internal_error)?;
 
-    let res = device
-        .filter(id.eq(device_id))
-        .filter(room.eq(room_id))
-        .filter(name.eq(dev_name.to_owned()))
-        .select(Device::as_select())
-        .first(&mut dbh)
+    Ok(Json(res.to_string()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/alerts",
+    tag = "alerts",
+    params(
+        ("house_id" = i32, Path),
+        AlertsQuery,
+    ),
+    responses(
+        (status = 200, description = "Alert rules of every device in the house", body = Vec<Alert>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn house_alerts(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(house_id): Path<i32>,
+    Query(query): Query<AlertsQuery>,
+) -> Result<Json<Vec<Alert>>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Guest,
+    )?;
+
+    let devices = app_state
+        .repo
+        .house_devices(house_id)
+        .map_err(repo_error)?
+        .into_iter()
+        .map(|d| d.id)
+        .collect::<Vec<_>>();
+
+    let mut select = schema::alert::table
+        .filter(schema::alert::device.eq_any(devices))
+        .select(Alert::as_select())
+        .order(schema::alert::id)
+        .into_boxed();
+
+    if let Some(wanted) = query.state {
+        select = select.filter(schema::alert::state.eq(wanted.as_str()));
+    }
+
+    let res = select.load(&mut dbh).counted().map_err(internal_error)?;
+
+    Ok(Json(res))
+}
+
+/// Server-sent stream of device and alert changes in the house.
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/events",
+    tag = "houses",
+    params(("house_id" = i32, Path)),
+    responses(
+        (status = 200, description = "Server-sent stream of device and alert changes", body = Event, content_type = "text/event-stream"),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn events(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(house_id): Path<i32>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, axum::Error>>>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Guest,
+    )?;
+
+    // The stream ends with the server, or graceful shutdown would wait on it forever.
+    let stream =
+        BroadcastStream::new(app_state.events.subscribe()).filter_map(move |event| match event {
+            Ok(event) if event.house() == house_id => Some(SseEvent::default().json_data(event)),
+            _ => None,
+        });
+    let stream =
+        futures_util::StreamExt::take_until(stream, app_state.shutdown.clone().cancelled_owned());
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Liveness probe: answers as long as the process serves requests.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses(
+        (status = 200, description = "The process is alive", body = Object),
+    ),
+    security(()),
+)]
+pub async fn healthz() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness probe, unauthenticated like `/healthz`; 503 unless every check passes.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every check passed", body = Readiness),
+        (status = 503, description = "Some check failed"),
+    ),
+    security(()),
+)]
+pub async fn readyz(State(app_state): State<Arc<AppState>>) -> (StatusCode, Json<Readiness>) {
+    let res = health::readiness(&app_state);
+
+    let status = if res.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(res))
+}
+
+/// OpenAPI document describing every route.
+///
+/// When the API is nested under a prefix the document names it as its server,
+/// so the paths resolve wherever the router is mounted.
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "health",
+    responses((status = 200, description = "This document", body = Object)),
+    security(()),
+)]
+pub async fn openapi_json(OriginalUri(uri): OriginalUri) -> Json<utoipa::openapi::OpenApi> {
+    let mut doc = ApiDoc::openapi();
+
+    let prefix = uri.path().strip_suffix("/openapi.json").unwrap_or_default();
+    if !prefix.is_empty() {
+        doc.servers = Some(vec![Server::new(prefix)]);
+    }
+
+    Json(doc)
+}
+
+/// Swagger UI for the document at `/openapi.json`.
+#[utoipa::path(
+    get,
+    path = "/docs",
+    tag = "health",
+    responses((status = 200, description = "Swagger UI", body = String, content_type = "text/html")),
+    security(()),
+)]
+pub async fn docs() -> Html<&'static str> {
+    Html(DOCS_HTML)
+}
+
+/// Script, stylesheet or image of the Swagger UI page, built into the server.
+#[utoipa::path(
+    get,
+    path = "/docs/{file}",
+    tag = "health",
+    params(("file" = String, Path)),
+    responses(
+        (status = 200, description = "The asset"),
+        (status = 404, description = "No such asset"),
+    ),
+    security(()),
+)]
+pub async fn docs_asset(
+    Path(file): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let config = Arc::new(utoipa_swagger_ui::Config::default());
+
+    match utoipa_swagger_ui::serve(&file, config).map_err(|e| internal_error(&*e))? {
+        // The page has its own initializer, so only the static files are served.
+        Some(asset) if file != "index.html" && file != "swagger-initializer.js" => Ok((
+            [(header::CONTENT_TYPE, asset.content_type)],
+            asset.bytes.into_owned(),
+        )),
+        _ => Err((StatusCode::NOT_FOUND, format!("no asset {file}"))),
+    }
+}
+
+/// Prometheus scrape endpoint.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn get_metrics(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let res = METRICS
+        .render(&app_state.pool, app_state.repo.as_ref())
+        .map_err(internal_error)?;
+
+    Ok(([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Newest audit entries first", body = Vec<AuditEntry>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn list_audit(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    use schema::audit_log::dsl::*;
+
+    let mut select = audit_log
+        .select(AuditLog::as_select())
+        .order(id.desc())
+        .limit(query.limit.unwrap_or(100).clamp(1, 1000))
+        .into_boxed();
+
+    if let Some(wanted) = query.actor {
+        select = select.filter(actor.eq(wanted));
+    }
+    if let Some(wanted) = query.route {
+        select = select.filter(route.eq(wanted));
+    }
+    if let Some(wanted) = query.house {
+        select = select.filter(house.eq(wanted));
+    }
+    if let Some(wanted) = query.room {
+        select = select.filter(room.eq(wanted));
+    }
+    if let Some(wanted) = query.device {
+        select = select.filter(device.eq(wanted));
+    }
+    if let Some(since) = query.since {
+        select = select.filter(at.ge(since));
+    }
+    if let Some(until) = query.until {
+        select = select.filter(at.lt(until));
+    }
+
+    let res = select.load(&mut dbh).counted().map_err(internal_error)?;
+
+    Ok(Json(res.into_iter().map(AuditEntry::from).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/tokens",
+    tag = "admin",
+    request_body = TokenForm,
+    responses(
+        (status = 200, description = "The token and its secret", body = IssuedToken),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn add_token(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+    Json(form): Json<TokenForm>,
+) -> Result<Json<IssuedToken>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    let owner = form
+        .user
+        .as_deref()
+        .map(|login| user_id(&mut dbh, login))
+        .transpose()?;
+
+    let secret = random_token();
+
+    use schema::api_token::dsl::*;
+
+    let res = dbh
+        .immediate_transaction(|conn| {
+            diesel::insert_into(api_token)
+                .values(&NewApiToken {
+                    name: form.name,
+                    token_hash: auth::hash_token(&secret),
+                    admin: form.admin,
+                    created_at: unix_now(),
+                    user: owner,
+                    expires_at: None,
+                    invite: None,
+                })
+                .execute(conn)
+                .counted()?;
+
+            api_token
+                .filter(token_hash.eq(auth::hash_token(&secret)))
+                .select(ApiToken::as_select())
+                .first(conn)
+                .counted_row()
+        })
+        .map_err(internal_error)?;
+
+    Ok(Json(IssuedToken {
+        id: res.id,
+        name: res.name,
+        admin: res.admin,
+        token: secret,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/tokens",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Every issued token", body = Vec<ApiToken>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn list_tokens(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+) -> Result<Json<Vec<ApiToken>>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    use schema::api_token::dsl::*;
+
+    let res = api_token
+        .order(id)
+        .select(ApiToken::as_select())
+        .load(&mut dbh)
+        .counted()
         .map_err(internal_error)?;
 
     Ok(Json(res))
 }
 
-pub async fn del_device(
+#[utoipa::path(
+    delete,
+    path = "/admin/tokens/{token_id}",
+    tag = "admin",
+    params(("token_id" = i32, Path)),
+    responses(
+        (status = 200, description = "Number of revoked tokens", body = String),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn revoke_token(
     State(app_state): State<Arc<AppState>>,
-    Path((_house_id, room_id, device_id)): Path<(i32, i32, i32)>,
+    _admin: Admin,
+    Path(token_id): Path<i32>,
 ) -> Result<Json<String>, (StatusCode, String)> {
     let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    use schema::device::dsl::*;
+    use schema::api_token::dsl::*;
 
-    let res = diesel::delete(device.filter(id.eq(device_id)).filter(room.eq(room_id)))
+    let res = diesel::update(
+        api_token
+            .filter(id.eq(token_id))
+            .filter(revoked_at.is_null()),
+    )
+    .set(revoked_at.eq(unix_now()))
+    .execute(&mut *dbh)
+    .counted()
+    .map_err(internal_error)?;
+
+    Ok(Json(res.to_string()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users",
+    tag = "admin",
+    request_body = UserForm,
+    responses(
+        (status = 200, description = "The created user", body = User),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 400, description = "Invalid input"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 409, description = "Already exists"),
+    ),
+)]
+pub async fn add_user(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+    Json(form): Json<UserForm>,
+) -> Result<Json<User>, (StatusCode, String)> {
+    if form.login.trim().is_empty() || form.password.len() < MIN_PASSWORD_LEN {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("login must not be empty and password at least {MIN_PASSWORD_LEN} characters"),
+        ));
+    }
+
+    let hashed = auth::hash_password(&form.password)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    use schema::user::dsl::*;
+
+    let res = dbh
+        .immediate_transaction(|conn| {
+            let added = diesel::insert_or_ignore_into(user)
+                .values(&NewUser {
+                    login: form.login.clone(),
+                    password_hash: hashed,
+                    created_at: unix_now(),
+                })
+                .execute(conn)
+                .counted()?;
+
+            let res = user
+                .filter(login.eq(&form.login))
+                .select(User::as_select())
+                .first(conn)
+                .counted_row()?;
+
+            Ok::<_, diesel::result::Error>((added, res))
+        })
+        .map_err(internal_error)?;
+
+    match res {
+        (0, _) => Err((
+            StatusCode::CONFLICT,
+            format!("login {} is taken", form.login),
+        )),
+        (_, res) => Ok(Json(res)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Every user", body = Vec<User>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn list_users(
+    State(app_state): State<Arc<AppState>>,
+    _admin: Admin,
+) -> Result<Json<Vec<User>>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    use schema::user::dsl::*;
+
+    let res = user
+        .order(id)
+        .select(User::as_select())
+        .load(&mut dbh)
+        .counted()
+        .map_err(internal_error)?;
+
+    Ok(Json(res))
+}
+
+/// Trades a login and password for a session token.
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "access",
+    request_body = UserForm,
+    responses(
+        (status = 200, description = "A session token", body = Session),
+        (status = 401, description = "Wrong login or password"),
+    ),
+    security(()),
+)]
+pub async fn login(
+    State(app_state): State<Arc<AppState>>,
+    Json(form): Json<UserForm>,
+) -> Result<Json<Session>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    let found = schema::user::table
+        .filter(schema::user::login.eq(&form.login))
+        .select(User::as_select())
+        .first(&mut dbh)
+        .optional()
+        .counted()
+        .map_err(internal_error)?
+        .filter(|u| auth::verify_password(&form.password, &u.password_hash))
+        .ok_or((
+            StatusCode::UNAUTHORIZED,
+            "wrong login or password".to_owned(),
+        ))?;
+
+    let session = Session {
+        token: random_token(),
+        expires_at: unix_now() + SESSION_TTL,
+    };
+
+    diesel::insert_into(schema::api_token::table)
+        .values(&NewApiToken {
+            name: found.login,
+            token_hash: auth::hash_token(&session.token),
+            admin: false,
+            created_at: unix_now(),
+            user: Some(found.id),
+            expires_at: Some(session.expires_at),
+            invite: None,
+        })
+        .execute(&mut dbh)
+        .counted()
+        .map_err(internal_error)?;
+
+    Ok(Json(session))
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    tag = "access",
+    responses(
+        (status = 200, description = "Number of revoked sessions", body = String),
+        (status = 401, description = "Missing, expired or revoked token"),
+    ),
+)]
+pub async fn logout(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+) -> Result<Json<String>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    use schema::api_token::dsl::*;
+
+    let res = diesel::update(api_token.filter(id.eq(caller.token)))
+        .set(revoked_at.eq(unix_now()))
         .execute(&mut *dbh)
+        .counted()
         .map_err(internal_error)?;
 
     Ok(Json(res.to_string()))
 }
 
-pub async fn drop_all(
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/members",
+    tag = "access",
+    params(("house_id" = i32, Path)),
+    responses(
+        (status = 200, description = "Users with a role in the house", body = Vec<Member>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn get_members(
     State(app_state): State<Arc<AppState>>,
-) -> Result<Json<bool>, (StatusCode, String)> {
+    caller: Caller,
+    Path(house_id): Path<i32>,
+) -> Result<Json<Vec<Member>>, (StatusCode, String)> {
     let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-    {
-        use schema::device::dsl::*;
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Guest,
+    )?;
+
+    let res = schema::house_member::table
+        .inner_join(schema::user::table)
+        .filter(schema::house_member::house.eq(house_id))
+        .order(schema::user::id)
+        .select((
+            schema::user::id,
+            schema::user::login,
+            schema::house_member::role,
+        ))
+        .load::<(i32, String, String)>(&mut dbh)
+        .counted()
+        .map_err(internal_error)?;
+
+    Ok(Json(
+        res.into_iter()
+            .filter_map(|(user, login, role)| {
+                Role::parse(&role).map(|role| Member { user, login, role })
+            })
+            .collect(),
+    ))
+}
+
+/// Grants a user a role in the house, replacing the one they had.
+#[utoipa::path(
+    post,
+    path = "/houses/{house_id}/members",
+    tag = "access",
+    params(("house_id" = i32, Path)),
+    request_body = MemberForm,
+    responses(
+        (status = 200, description = "The granted role", body = Member),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn add_member(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(house_id): Path<i32>,
+    Json(form): Json<MemberForm>,
+) -> Result<Json<Member>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Owner,
+    )?;
+
+    let member = user_id(&mut dbh, &form.login)?;
+
+    diesel::replace_into(schema::house_member::table)
+        .values(&HouseMember {
+            house: house_id,
+            user: member,
+            role: form.role.as_str().to_owned(),
+        })
+        .execute(&mut dbh)
+        .counted()
+        .map_err(internal_error)?;
+
+    Ok(Json(Member {
+        user: member,
+        login: form.login,
+        role: form.role,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/houses/{house_id}/members/{user_id}",
+    tag = "access",
+    params(
+        ("house_id" = i32, Path),
+        ("user_id" = i32, Path),
+    ),
+    responses(
+        (status = 200, description = "Number of removed members", body = String),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn del_member(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, user_id)): Path<(i32, i32)>,
+) -> Result<Json<String>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Owner,
+    )?;
+
+    use schema::house_member::dsl::*;
+
+    let res = diesel::delete(
+        house_member
+            .filter(house.eq(house_id))
+            .filter(user.eq(user_id)),
+    )
+    .execute(&mut *dbh)
+    .counted()
+    .map_err(internal_error)?;
+
+    Ok(Json(res.to_string()))
+}
 
-        let _ = diesel::delete(device)
-            .execute(&mut *dbh)
-            //.expect("cant execute");
-            .map_err(internal_error)?;
+#[utoipa::path(
+    post,
+    path = "/houses/{house_id}/invites",
+    tag = "access",
+    params(("house_id" = i32, Path)),
+    request_body = InviteForm,
+    responses(
+        (status = 200, description = "The invite and its token", body = IssuedInvite),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 400, description = "Invalid input"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn add_invite(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(house_id): Path<i32>,
+    Json(form): Json<InviteForm>,
+) -> Result<Json<IssuedInvite>, (StatusCode, String)> {
+    if !(1..=MAX_INVITE_TTL).contains(&form.ttl_secs) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("ttl_secs must be between 1 and {MAX_INVITE_TTL}"),
+        ));
     }
-    {
-        use schema::room::dsl::*;
 
-        let _ = diesel::delete(room)
-            .execute(&mut *dbh)
-            .map_err(internal_error)?;
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Owner,
+    )?;
+
+    let rooms = app_state
+        .repo
+        .rooms(house_id)
+        .map_err(repo_error)?
+        .iter()
+        .filter(|r| form.rooms.contains(&r.id))
+        .count();
+    let devices = app_state
+        .repo
+        .house_devices(house_id)
+        .map_err(repo_error)?
+        .iter()
+        .filter(|d| form.devices.contains(&d.id))
+        .count();
+
+    if rooms != form.rooms.len() || devices != form.devices.len() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("invited rooms and devices must be distinct and belong to house {house_id}"),
+        ));
     }
 
-    {
-        use schema::house::dsl::*;
+    let secret = random_token();
+    let now = unix_now();
+
+    let res = dbh
+        .immediate_transaction(|conn| {
+            diesel::insert_into(schema::invite::table)
+                .values(&NewInvite {
+                    house: house_id,
+                    name: form.name.clone(),
+                    permission: form.permission.as_str().to_owned(),
+                    whole_house: form.rooms.is_empty() && form.devices.is_empty(),
+                    created_at: now,
+                    expires_at: now + form.ttl_secs,
+                })
+                .execute(conn)
+                .counted()?;
+
+            let res = schema::invite::table
+                .filter(schema::invite::house.eq(house_id))
+                .order(schema::invite::id.desc())
+                .select(Invite::as_select())
+                .first(conn)
+                .counted_row()?;
+
+            for &room in &form.rooms {
+                diesel::insert_into(schema::invite_room::table)
+                    .values(InviteRoom {
+                        invite: res.id,
+                        room,
+                    })
+                    .execute(conn)
+                    .counted()?;
+            }
+            for &device in &form.devices {
+                diesel::insert_into(schema::invite_device::table)
+                    .values(InviteDevice {
+                        invite: res.id,
+                        device,
+                    })
+                    .execute(conn)
+                    .counted()?;
+            }
+
+            diesel::insert_into(schema::api_token::table)
+                .values(&NewApiToken {
+                    name: format!("invite:{}", res.name),
+                    token_hash: auth::hash_token(&secret),
+                    admin: false,
+                    created_at: now,
+                    user: None,
+                    expires_at: Some(res.expires_at),
+                    invite: Some(res.id),
+                })
+                .execute(conn)
+                .counted()?;
+
+            Ok::<_, diesel::result::Error>(res)
+        })
+        .map_err(internal_error)?;
+
+    Ok(Json(IssuedInvite {
+        id: res.id,
+        token: secret,
+        expires_at: res.expires_at,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/houses/{house_id}/invites",
+    tag = "access",
+    params(("house_id" = i32, Path)),
+    responses(
+        (status = 200, description = "Invites to the house", body = Vec<InviteScope>),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn list_invites(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path(house_id): Path<i32>,
+) -> Result<Json<Vec<InviteScope>>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
 
-        let _ = diesel::delete(house)
-            .execute(&mut *dbh)
-            .map_err(internal_error)?;
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Owner,
+    )?;
+
+    let invites = schema::invite::table
+        .filter(schema::invite::house.eq(house_id))
+        .order(schema::invite::id)
+        .select(Invite::as_select())
+        .load(&mut dbh)
+        .counted()
+        .map_err(internal_error)?;
+
+    Ok(Json(invite_scopes(&mut dbh, invites)?))
+}
+
+/// The invite the caller's token was issued for.
+#[utoipa::path(
+    get,
+    path = "/invite",
+    tag = "access",
+    responses(
+        (status = 200, description = "The invite the token was issued for", body = InviteScope),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 404, description = "Addressed resource does not exist"),
+    ),
+)]
+pub async fn my_invite(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+) -> Result<Json<InviteScope>, (StatusCode, String)> {
+    let invite_id = caller.invite.ok_or((
+        StatusCode::NOT_FOUND,
+        "the token was not issued for an invite".to_owned(),
+    ))?;
+
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    let invites = schema::invite::table
+        .filter(schema::invite::id.eq(invite_id))
+        .select(Invite::as_select())
+        .load(&mut dbh)
+        .counted()
+        .map_err(internal_error)?;
+
+    invite_scopes(&mut dbh, invites)?
+        .pop()
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, format!("no invite {invite_id}")))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/houses/{house_id}/invites/{invite_id}",
+    tag = "access",
+    params(
+        ("house_id" = i32, Path),
+        ("invite_id" = i32, Path),
+    ),
+    responses(
+        (status = 200, description = "Number of revoked invites", body = String),
+        (status = 401, description = "Missing, expired or revoked token"),
+        (status = 403, description = "Not allowed for the caller"),
+    ),
+)]
+pub async fn del_invite(
+    State(app_state): State<Arc<AppState>>,
+    caller: Caller,
+    Path((house_id, invite_id)): Path<(i32, i32)>,
+) -> Result<Json<String>, (StatusCode, String)> {
+    let mut dbh = app_state.pool.get().map_err(internal_error)?;
+
+    auth::authorize(
+        app_state.repo.as_ref(),
+        &mut dbh,
+        &caller,
+        Resource::house(house_id),
+        Role::Owner,
+    )?;
+
+    let now = unix_now();
+
+    let res = dbh
+        .immediate_transaction(|conn| {
+            let res = diesel::update(schema::invite::table)
+                .filter(schema::invite::id.eq(invite_id))
+                .filter(schema::invite::house.eq(house_id))
+                .filter(schema::invite::revoked_at.is_null())
+                .set(schema::invite::revoked_at.eq(now))
+                .execute(conn)
+                .counted()?;
+
+            diesel::update(schema::api_token::table)
+                .filter(schema::api_token::invite.eq(invite_id))
+                .filter(schema::api_token::revoked_at.is_null())
+                .set(schema::api_token::revoked_at.eq(now))
+                .execute(conn)
+                .counted()?;
+
+            Ok::<_, diesel::result::Error>(res)
+        })
+        .map_err(internal_error)?;
+
+    Ok(Json(res.to_string()))
+}
+
+fn invite_scopes(
+    dbh: &mut diesel::SqliteConnection,
+    invites: Vec<Invite>,
+) -> Result<Vec<InviteScope>, (StatusCode, String)> {
+    let ids = invites.iter().map(|i| i.id).collect::<Vec<_>>();
+
+    let rooms = schema::invite_room::table
+        .filter(schema::invite_room::invite.eq_any(&ids))
+        .select(InviteRoom::as_select())
+        .load(dbh)
+        .counted()
+        .map_err(internal_error)?
+        .grouped_by(&invites);
+    let devices = schema::invite_device::table
+        .filter(schema::invite_device::invite.eq_any(&ids))
+        .select(InviteDevice::as_select())
+        .load(dbh)
+        .counted()
+        .map_err(internal_error)?
+        .grouped_by(&invites);
+
+    Ok(invites
+        .into_iter()
+        .zip(rooms.into_iter().zip(devices))
+        .map(|(invite, (rooms, devices))| InviteScope {
+            id: invite.id,
+            house: invite.house,
+            name: invite.name,
+            permission: Permission::parse(&invite.permission).unwrap_or(Permission::View),
+            whole_house: invite.whole_house,
+            rooms: rooms.into_iter().map(|r| r.room).collect(),
+            devices: devices.into_iter().map(|d| d.device).collect(),
+            created_at: invite.created_at,
+            expires_at: invite.expires_at,
+            revoked_at: invite.revoked_at,
+        })
+        .collect())
+}
+
+/// The id of the user with the login, 404 if there is none.
+fn user_id(dbh: &mut diesel::SqliteConnection, login: &str) -> Result<i32, (StatusCode, String)> {
+    schema::user::table
+        .filter(schema::user::login.eq(login))
+        .select(schema::user::id)
+        .first(dbh)
+        .optional()
+        .counted()
+        .map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no user {login}")))
+}
+
+/// Loads the device, making sure it lives in the room of the house and reports readings.
+fn sensor(
+    repo: &dyn HomeRepository,
+    house_id: i32,
+    room_id: i32,
+    device_id: i32,
+) -> Result<Device, (StatusCode, String)> {
+    let in_room = repo
+        .room(room_id)
+        .map_err(repo_error)?
+        .is_some_and(|r| r.house == house_id);
+
+    let res = repo
+        .device(device_id)
+        .map_err(repo_error)?
+        .filter(|d| in_room && d.room == room_id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no device {device_id}")))?;
+
+    if !reports_readings(&res.device_type) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{} devices do not report readings", res.device_type),
+        ));
     }
 
-    Ok(Json(true))
+    Ok(res)
+}
+
+/// The house document in `body`, JSON or YAML as its content type says.
+fn house_doc(headers: &HeaderMap, body: &str) -> Result<HouseDoc, (StatusCode, String)> {
+    let format = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(Format::Json, Format::from_content_type);
+    let doc = format
+        .parse(body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let problems = doc.problems();
+    if !problems.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, problems.join("; ")));
+    }
+
+    Ok(doc)
+}
+
+fn backup_error(e: BackupError) -> (StatusCode, String) {
+    match e {
+        BackupError::Disabled | BackupError::NotFound(_) => (StatusCode::NOT_FOUND, e.to_string()),
+        BackupError::Incompatible(_) | BackupError::Inconsistent(_) => {
+            (StatusCode::CONFLICT, e.to_string())
+        }
+        e => internal_error(e),
+    }
+}
+
+fn drop_all_enabled(app_state: &AppState) -> Result<(), (StatusCode, String)> {
+    if app_state.allow_drop_all {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::FORBIDDEN,
+            "dropping all houses is disabled on this server".to_owned(),
+        ))
+    }
 }
 
 fn internal_error<E>(error: E) -> (StatusCode, String)
@@ -325,3 +2787,10 @@ where
 {
     (StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
 }
+
+fn repo_error(error: RepoError) -> (StatusCode, String) {
+    match error {
+        RepoError::Conflict(_) | RepoError::Missing(_) => (StatusCode::CONFLICT, error.to_string()),
+        _ => internal_error(error),
+    }
+}
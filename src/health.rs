@@ -0,0 +1,83 @@
+use diesel::{Connection, RunQueryDsl, SqliteConnection, result::Error::RollbackTransaction};
+use diesel_migrations::MigrationHarness;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{AppState, MIGRATIONS, telemetry::Counted};
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+/// Runs every readiness check; later checks are skipped when no connection can be had.
+pub fn readiness(app_state: &AppState) -> Readiness {
+    let mut checks = Vec::new();
+
+    match app_state.pool.get() {
+        Ok(mut dbh) => {
+            checks.push(check("database", select_one(&mut dbh)));
+            checks.push(check("migrations", migrations(&mut dbh)));
+            checks.push(check("writable", writable(&mut dbh)));
+        }
+        Err(e) => checks.push(check("database", Err(e.to_string()))),
+    }
+
+    checks.push(check(
+        "layout",
+        app_state.repo.ping().map_err(|e| e.to_string()),
+    ));
+
+    Readiness {
+        ready: checks.iter().all(|c| c.ok),
+        checks,
+    }
+}
+
+fn check(name: &str, res: Result<(), String>) -> Check {
+    Check {
+        name: name.to_owned(),
+        ok: res.is_ok(),
+        error: res.err(),
+    }
+}
+
+fn select_one(dbh: &mut SqliteConnection) -> Result<(), String> {
+    diesel::sql_query("SELECT 1")
+        .execute(dbh)
+        .counted()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn migrations(dbh: &mut SqliteConnection) -> Result<(), String> {
+    match dbh.has_pending_migration(MIGRATIONS) {
+        Ok(false) => Ok(()),
+        Ok(true) => Err("migrations are pending".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Creates a table inside a transaction that is always rolled back, which needs the write lock.
+fn writable(dbh: &mut SqliteConnection) -> Result<(), String> {
+    let res = dbh.transaction::<(), _, _>(|conn| {
+        diesel::sql_query("CREATE TABLE __readyz_probe (id INTEGER)")
+            .execute(conn)
+            .counted()?;
+        Err(RollbackTransaction)
+    });
+
+    match res {
+        Ok(()) | Err(RollbackTransaction) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,583 @@
+//! Portable documents of a house: its rooms and devices by name, without
+//! readings or state, to move a house from one server to another or to keep
+//! its desired layout under version control.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    models::{Device, House, Room, check_power},
+    repository::{DeviceChange, HomeRepository, LayoutChange, RepoResult, Target},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HouseDoc {
+    pub name: String,
+    #[serde(default)]
+    pub rooms: Vec<RoomDoc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RoomDoc {
+    /// Id of the stored room, so configuration syncs can tell a rename from a
+    /// new room; imports ignore it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    pub name: String,
+    #[serde(default)]
+    pub devices: Vec<DeviceDoc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceDoc {
+    /// Like [`RoomDoc::id`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    pub name: String,
+    /// Device type, as in `DeviceForm`.
+    pub device: String,
+    /// Power draw in watts, only for sockets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power: Option<f64>,
+}
+
+/// Serialization of a [`HouseDoc`].
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Yaml,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Yaml => "application/yaml",
+        }
+    }
+
+    /// The format of a body sent with `content_type`; JSON unless it names YAML.
+    pub fn from_content_type(content_type: &str) -> Self {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+
+        match mime {
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => Format::Yaml,
+            _ => Format::Json,
+        }
+    }
+
+    pub fn render(self, doc: &HouseDoc) -> Result<String, String> {
+        match self {
+            Format::Json => serde_json::to_string_pretty(doc).map_err(|e| e.to_string()),
+            Format::Yaml => serde_yaml::to_string(doc).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Result<HouseDoc, String> {
+        match self {
+            Format::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            Format::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, IntoParams)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: Format,
+    /// Includes the ids of rooms and devices, for `PUT /houses/{house_id}/config`.
+    #[serde(default)]
+    pub ids: bool,
+}
+
+/// What an import does when a house of the same name already exists.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Refuse the import.
+    #[default]
+    Fail,
+    /// Import as a new house with a free name, like `Casa (2)`.
+    Rename,
+    /// Add the missing rooms and devices to the existing house and update
+    /// the type and power of the devices it already has.
+    Merge,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, IntoParams)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub on_conflict: ImportMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Imported {
+    pub house: House,
+    /// Whether the house was created rather than merged into.
+    pub created: bool,
+    pub rooms_added: usize,
+    pub devices_added: usize,
+    pub devices_updated: usize,
+}
+
+impl HouseDoc {
+    /// The document of a stored house, rooms and devices in id order.
+    pub fn export(repo: &dyn HomeRepository, house: &House, ids: bool) -> RepoResult<Self> {
+        let rooms = repo
+            .rooms(house.id)?
+            .into_iter()
+            .map(|room| {
+                let devices = repo
+                    .devices(room.id)?
+                    .iter()
+                    .map(|d| DeviceDoc {
+                        id: ids.then_some(d.id),
+                        ..DeviceDoc::from(d)
+                    })
+                    .collect();
+
+                Ok(RoomDoc {
+                    id: ids.then_some(room.id),
+                    name: room.name,
+                    devices,
+                })
+            })
+            .collect::<RepoResult<_>>()?;
+
+        Ok(Self {
+            name: house.name.clone(),
+            rooms,
+        })
+    }
+
+    /// Problems that would make the document fail halfway through an import.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.name.trim().is_empty() {
+            problems.push("the house needs a name".to_owned());
+        }
+
+        let mut rooms = HashSet::new();
+        for room in &self.rooms {
+            if room.name.trim().is_empty() {
+                problems.push("every room needs a name".to_owned());
+            }
+            if !rooms.insert(&room.name) {
+                problems.push(format!("room {:?} is listed twice", room.name));
+            }
+
+            let mut devices = HashSet::new();
+            for device in &room.devices {
+                if device.name.trim().is_empty() || device.device.trim().is_empty() {
+                    problems.push(format!(
+                        "every device in room {:?} needs a name and a type",
+                        room.name
+                    ));
+                }
+                if !devices.insert(&device.name) {
+                    problems.push(format!(
+                        "device {:?} is listed twice in room {:?}",
+                        device.name, room.name
+                    ));
+                }
+                if let Err(e) = check_power(&device.device, device.power) {
+                    problems.push(format!("device {:?}: {e}", device.name));
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Changes creating the house under `name`, with every room and device.
+    pub fn create(&self, name: &str) -> Vec<LayoutChange> {
+        let mut changes = vec![LayoutChange::AddHouse {
+            name: name.to_owned(),
+        }];
+
+        for room in &self.rooms {
+            let room_index = changes.len();
+            changes.push(LayoutChange::AddRoom {
+                house: Target::Added(0),
+                name: room.name.clone(),
+            });
+            changes.extend(
+                room.devices
+                    .iter()
+                    .map(|d| d.add(Target::Added(room_index))),
+            );
+        }
+
+        changes
+    }
+
+    /// Changes bringing the rooms and devices of the document into a stored
+    /// house, leaving what the document does not mention alone.
+    pub fn merge(&self, house: i32, rooms: &[Room], devices: &[Device]) -> Vec<LayoutChange> {
+        let mut changes = Vec::new();
+
+        for room_doc in &self.rooms {
+            let Some(room) = rooms.iter().find(|r| r.name == room_doc.name) else {
+                let room_index = changes.len();
+                changes.push(LayoutChange::AddRoom {
+                    house: Target::Id(house),
+                    name: room_doc.name.clone(),
+                });
+                changes.extend(
+                    room_doc
+                        .devices
+                        .iter()
+                        .map(|d| d.add(Target::Added(room_index))),
+                );
+                continue;
+            };
+
+            for device_doc in &room_doc.devices {
+                match devices
+                    .iter()
+                    .find(|d| d.room == room.id && d.name == device_doc.name)
+                {
+                    None => changes.push(device_doc.add(Target::Id(room.id))),
+                    Some(device) if !device_doc.matches(device) => {
+                        changes.push(device_doc.update(device))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        changes
+    }
+}
+
+impl From<&Device> for DeviceDoc {
+    fn from(device: &Device) -> Self {
+        Self {
+            id: None,
+            name: device.name.clone(),
+            device: device.device_type.clone(),
+            power: device.power,
+        }
+    }
+}
+
+impl DeviceDoc {
+    fn add(&self, room: Target) -> LayoutChange {
+        LayoutChange::AddDevice {
+            room,
+            name: self.name.clone(),
+            device_type: self.device.clone(),
+            power: self.power,
+        }
+    }
+
+    /// Whether the stored device already has the type and power of the document.
+    fn matches(&self, device: &Device) -> bool {
+        device.device_type == self.device && device.power == self.power
+    }
+
+    /// Changes the device to the document, keeping it switched as it is.
+    fn update(&self, device: &Device) -> LayoutChange {
+        LayoutChange::UpdateDevice {
+            id: device.id,
+            change: DeviceChange {
+                name: self.name.clone(),
+                device_type: self.device.clone(),
+                state: device.state,
+                power: self.power,
+            },
+        }
+    }
+}
+
+/// The first of `name`, `name (2)`, `name (3)`... that no house in `taken` has.
+pub fn free_name(name: &str, taken: &[House]) -> String {
+    (1..)
+        .map(|n| match n {
+            1 => name.to_owned(),
+            n => format!("{name} ({n})"),
+        })
+        .find(|candidate| taken.iter().all(|h| &h.name != candidate))
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, IntoParams)]
+pub struct ConfigQuery {
+    /// Returns the plan without applying it.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Rename,
+    /// A device gets another name, type or power.
+    Update,
+    Delete,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    House,
+    Room,
+    Device,
+}
+
+/// One change of a configuration sync, as reported to the caller.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Step {
+    pub action: Action,
+    pub kind: Kind,
+    /// Id of the row; of created rows only once the plan is applied.
+    pub id: Option<i32>,
+    pub name: String,
+    /// Name of the room the device is in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    /// Name before a rename or update.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub was: Option<String>,
+}
+
+/// Changes bringing a house to a desired document, and what they mean.
+#[derive(Default, Debug)]
+pub struct Plan {
+    pub changes: Vec<LayoutChange>,
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    /// Adds the change, returning its index for [`Target::Added`].
+    fn push(&mut self, change: LayoutChange, step: Step) -> usize {
+        self.changes.push(change);
+        self.steps.push(step);
+        self.changes.len() - 1
+    }
+
+    /// Fills in the ids of created rows from what [`HomeRepository::apply`] returned.
+    pub fn applied(mut self, ids: &[i32]) -> Vec<Step> {
+        for (step, id) in self.steps.iter_mut().zip(ids) {
+            step.id = Some(*id);
+        }
+
+        self.steps
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ConfigSync {
+    /// Whether the changes were only planned.
+    pub dry_run: bool,
+    pub changes: Vec<Step>,
+}
+
+fn step(action: Action, kind: Kind, id: Option<i32>, name: &str) -> Step {
+    Step {
+        action,
+        kind,
+        id,
+        name: name.to_owned(),
+        room: None,
+        was: None,
+    }
+}
+
+/// Pairs every document entry with the stored row it describes: the one with
+/// its id if it has one, otherwise the one with its name. Returns the matches
+/// in document order and the stored rows nothing matched.
+type Paired<'d, 'r, D, R> = (Vec<(&'d D, Option<&'r R>)>, Vec<&'r R>);
+
+fn pair<'d, 'r, D, R>(
+    docs: &'d [D],
+    rows: &'r [R],
+    doc_key: impl Fn(&D) -> (Option<i32>, &str),
+    row_key: impl Fn(&R) -> (i32, &str),
+    what: &str,
+) -> Result<Paired<'d, 'r, D, R>, String> {
+    let mut claimed = HashSet::new();
+    let mut pairs = Vec::with_capacity(docs.len());
+
+    // Ids first, so a named entry cannot take a row another entry claims by id.
+    for doc in docs {
+        if let (Some(id), _) = doc_key(doc) {
+            if !rows.iter().any(|r| row_key(r).0 == id) {
+                return Err(format!("{what} {id} is not where the document puts it"));
+            }
+            if !claimed.insert(id) {
+                return Err(format!("{what} {id} is listed twice"));
+            }
+        }
+    }
+
+    for doc in docs {
+        let row = match doc_key(doc) {
+            (Some(id), _) => rows.iter().find(|r| row_key(r).0 == id),
+            (None, name) => rows.iter().find(|r| {
+                let (id, row_name) = row_key(r);
+                row_name == name && claimed.insert(id)
+            }),
+        };
+        pairs.push((doc, row));
+    }
+
+    let unpaired = rows
+        .iter()
+        .filter(|r| !claimed.contains(&row_key(r).0))
+        .collect();
+
+    Ok((pairs, unpaired))
+}
+
+impl HouseDoc {
+    /// Changes turning the stored house into the document: what it lacks is
+    /// created, what it names differently renamed, what it leaves out deleted.
+    ///
+    /// Deletes go first and creates last, so names freed by the former can be
+    /// reused by the latter; swapping two names in one sync still conflicts.
+    pub fn sync(&self, house: &House, rooms: &[Room], devices: &[Device]) -> Result<Plan, String> {
+        let (room_pairs, gone_rooms) = pair(
+            &self.rooms,
+            rooms,
+            |d| (d.id, &d.name),
+            |r| (r.id, &r.name),
+            "room",
+        )?;
+
+        let mut deletes = Plan::default();
+        let mut renames = Plan::default();
+        let mut new_devices = Vec::new();
+
+        for room in &gone_rooms {
+            deletes.push(
+                LayoutChange::DeleteRoom { id: room.id },
+                step(Action::Delete, Kind::Room, Some(room.id), &room.name),
+            );
+        }
+
+        if house.name != self.name {
+            renames.push(
+                LayoutChange::RenameHouse {
+                    id: house.id,
+                    name: self.name.clone(),
+                },
+                Step {
+                    was: Some(house.name.clone()),
+                    ..step(Action::Rename, Kind::House, Some(house.id), &self.name)
+                },
+            );
+        }
+
+        for (room_doc, room) in &room_pairs {
+            let Some(room) = room else {
+                continue;
+            };
+
+            if room.name != room_doc.name {
+                renames.push(
+                    LayoutChange::RenameRoom {
+                        id: room.id,
+                        name: room_doc.name.clone(),
+                    },
+                    Step {
+                        was: Some(room.name.clone()),
+                        ..step(Action::Rename, Kind::Room, Some(room.id), &room_doc.name)
+                    },
+                );
+            }
+
+            let in_room = devices
+                .iter()
+                .filter(|d| d.room == room.id)
+                .cloned()
+                .collect::<Vec<_>>();
+            let (device_pairs, gone_devices) = pair(
+                &room_doc.devices,
+                &in_room,
+                |d| (d.id, &d.name),
+                |d| (d.id, &d.name),
+                "device",
+            )?;
+
+            for device in gone_devices {
+                deletes.push(
+                    LayoutChange::DeleteDevice { id: device.id },
+                    Step {
+                        room: Some(room_doc.name.clone()),
+                        ..step(Action::Delete, Kind::Device, Some(device.id), &device.name)
+                    },
+                );
+            }
+
+            for (device_doc, device) in device_pairs {
+                match device {
+                    None => new_devices.push((Target::Id(room.id), &room_doc.name, device_doc)),
+                    Some(device)
+                        if device.name != device_doc.name || !device_doc.matches(device) =>
+                    {
+                        renames.push(
+                            device_doc.update(device),
+                            Step {
+                                room: Some(room_doc.name.clone()),
+                                was: Some(device.name.clone()),
+                                ..step(
+                                    Action::Update,
+                                    Kind::Device,
+                                    Some(device.id),
+                                    &device_doc.name,
+                                )
+                            },
+                        );
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        let mut plan = deletes;
+        plan.changes.extend(renames.changes);
+        plan.steps.extend(renames.steps);
+
+        for (room, name, device_doc) in new_devices {
+            plan.push(
+                device_doc.add(room),
+                Step {
+                    room: Some(name.clone()),
+                    ..step(Action::Create, Kind::Device, None, &device_doc.name)
+                },
+            );
+        }
+
+        for (room_doc, _) in room_pairs.iter().filter(|(_, room)| room.is_none()) {
+            let index = plan.push(
+                LayoutChange::AddRoom {
+                    house: Target::Id(house.id),
+                    name: room_doc.name.clone(),
+                },
+                step(Action::Create, Kind::Room, None, &room_doc.name),
+            );
+
+            for device_doc in &room_doc.devices {
+                plan.push(
+                    device_doc.add(Target::Added(index)),
+                    Step {
+                        room: Some(room_doc.name.clone()),
+                        ..step(Action::Create, Kind::Device, None, &device_doc.name)
+                    },
+                );
+            }
+        }
+
+        Ok(plan)
+    }
+}
@@ -1,11 +1,117 @@
+use std::sync::{Arc, Mutex};
+
 use diesel::r2d2::{ConnectionManager, Pool};
+use diesel_migrations::{EmbeddedMigrations, embed_migrations};
+use rand::{Rng, distr::Alphanumeric};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 
+pub mod alerts;
+pub mod audit;
+pub mod auth;
+pub mod backup;
+pub mod client;
+pub mod config;
+pub mod energy;
+pub mod events;
 pub mod handlers;
+pub mod health;
+pub mod layout;
+pub mod metrics;
 pub mod models;
+pub mod openapi;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod readings;
+pub mod repository;
+pub mod router;
 pub mod schema;
+pub mod sqlite;
+pub mod telemetry;
+pub mod trash;
+
+pub use router::router;
+
+use repository::{HomeRepository, SqliteRepository};
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
-type DbPool = Pool<ConnectionManager<diesel::SqliteConnection>>;
+pub type DbPool = Pool<ConnectionManager<diesel::SqliteConnection>>;
 
 pub struct AppState {
+    /// Access control, readings, alerts and the audit log.
     pub pool: DbPool,
+    /// Houses, rooms and devices.
+    pub repo: Arc<dyn HomeRepository>,
+    pub events: broadcast::Sender<events::Event>,
+    /// Enables `DELETE /admin/house`, which wipes every house.
+    pub allow_drop_all: bool,
+    /// Pending drop-all confirmation token and its expiry.
+    pub drop_all_token: Mutex<Option<(String, i64)>>,
+    /// Cancelled once the server starts shutting down; background work and streams stop on it.
+    pub shutdown: CancellationToken,
+    /// Enables `/admin/backup` and `/admin/restore`.
+    pub backups: Option<backup::BackupSettings>,
+    /// How long deleted houses, rooms and devices can be restored.
+    pub trash: trash::TrashSettings,
+}
+
+impl AppState {
+    pub fn new(pool: DbPool) -> Self {
+        let (events, _) = broadcast::channel(256);
+
+        Self {
+            repo: Arc::new(SqliteRepository::new(pool.clone())),
+            pool,
+            events,
+            allow_drop_all: false,
+            drop_all_token: Mutex::new(None),
+            shutdown: CancellationToken::new(),
+            backups: None,
+            trash: trash::TrashSettings::default(),
+        }
+    }
+
+    pub fn allow_drop_all(self, allow: bool) -> Self {
+        Self {
+            allow_drop_all: allow,
+            ..self
+        }
+    }
+
+    /// Keeps the layout in `repo` instead of the database of the pool.
+    pub fn repository(self, repo: Arc<dyn HomeRepository>) -> Self {
+        Self { repo, ..self }
+    }
+
+    pub fn backups(self, settings: backup::BackupSettings) -> Self {
+        Self {
+            backups: Some(settings),
+            ..self
+        }
+    }
+
+    pub fn trash(self, settings: trash::TrashSettings) -> Self {
+        Self {
+            trash: settings,
+            ..self
+        }
+    }
+}
+
+/// Seconds since the unix epoch, the unit of every timestamp column.
+pub fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Random alphanumeric secret for confirmations and credentials.
+pub fn random_token() -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
 }
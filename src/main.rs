@@ -1,57 +1,143 @@
-use axum::routing::{get, put};
-use diesel::r2d2::{self, ConnectionManager};
-use otus_axum::handlers;
-use std::sync::Arc;
+use clap::Parser;
+use otus_axum::{
+    alerts, auth, backup,
+    config::{Cli, Config},
+    sqlite, telemetry, trash,
+};
+use std::{sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
 
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let manager = ConnectionManager::<diesel::SqliteConnection>::new(database_url);
-    let pool = r2d2::Pool::builder()
-        .build(manager)
-        .expect("Failed to create pool.");
-    let app_state = Arc::new(otus_axum::AppState { pool });
-
-    let app = axum::Router::new()
-        .route(
-            "/house",
-            get(handlers::list_houses)
-                .post(handlers::add_house)
-                .delete(handlers::drop_all),
-        )
-        .route(
-            "/houses/{house_id}",
-            put(handlers::upd_house).delete(handlers::del_house),
-        )
-        .route(
-            "/houses/{house_id}/rooms",
-            get(handlers::get_rooms).post(handlers::add_room),
-        )
-        .route(
-            "/houses/{house_id}/rooms/{room_id}",
-            put(handlers::upd_room).delete(handlers::del_room),
-        )
-        .route(
-            "/houses/{house_id}/rooms/{room_id}/devices",
-            get(handlers::get_devices).post(handlers::add_device),
-        )
-        .route(
-            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}",
-            put(handlers::upd_device).delete(handlers::del_device),
-        )
-        .with_state(Arc::clone(&app_state));
-
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-        .await
-        .unwrap();
-
-    println!("Server started at http://localhost:3000");
+    let cli = Cli::parse();
+    let config = Config::load(&cli).unwrap_or_else(|e| {
+        eprintln!("invalid configuration: {e}");
+        std::process::exit(2);
+    });
+
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        return;
+    }
+
+    telemetry::init(&config);
+
+    let pool = config.pool().expect("Failed to create pool.");
+
+    sqlite::migrate(config.sqlite_url(), &config.sqlite_settings())
+        .expect("Failed to run migrations.");
+
+    if let Ok(token) = std::env::var("BOOTSTRAP_TOKEN") {
+        auth::bootstrap(&pool, &token).expect("Failed to register bootstrap token.");
+    }
+
+    let app_state = otus_axum::AppState::new(pool)
+        .allow_drop_all(config.features.drop_all)
+        .backups(config.backup.clone())
+        .trash(config.trash.clone());
+
+    #[cfg(feature = "postgres")]
+    let app_state = match config.backend() {
+        otus_axum::config::Backend::Postgres => app_state.repository(Arc::new(
+            otus_axum::postgres::PgRepository::connect(&config.database_url, config.pool_size)
+                .expect("Failed to connect to Postgres."),
+        )),
+        otus_axum::config::Backend::Sqlite => app_state,
+    };
+
+    let app_state = Arc::new(app_state);
+
+    let sweeper = config
+        .features
+        .alert_sweeper
+        .then(|| alerts::spawn_sweeper(Arc::clone(&app_state), Duration::from_secs(5)));
+
+    let backups = config
+        .backup_interval()
+        .map(|period| backup::spawn_scheduler(Arc::clone(&app_state), period));
+
+    let purger = config
+        .purge_interval()
+        .map(|period| trash::spawn_purger(Arc::clone(&app_state), period));
+
+    let app = otus_axum::router(Arc::clone(&app_state));
+
+    let app = match config.cors() {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+
+    let listener = tokio::net::TcpListener::bind(config.addr()).await.unwrap();
+
+    tracing::info!("Server started at http://{}", config.addr());
     println!("Run example in terminal");
-    println!("> cargo run --example requests ");
+    println!("> SMARTHOME_TOKEN=<admin token> cargo run --example requests ");
+    if !config.features.drop_all {
+        println!("(the example starts by wiping all houses, set ALLOW_DROP_ALL=true to allow it)");
+    }
+
+    let shutdown = app_state.shutdown.clone();
+    let server = axum::serve(listener, app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal(shutdown.clone()));
+
+    // Once shutdown starts, connections still open after the drain timeout are dropped.
+    let drain = async {
+        shutdown.cancelled().await;
+        tokio::time::sleep(config.drain_timeout()).await;
+    };
+
+    tokio::select! {
+        res = server => res.unwrap(),
+        _ = drain => tracing::warn!(
+            "requests still running after {}s, closing their connections",
+            config.drain_timeout_secs
+        ),
+    }
+
+    if let Some(sweeper) = sweeper {
+        let _ = sweeper.await;
+    }
+    if let Some(backups) = backups {
+        let _ = backups.await;
+    }
+    if let Some(purger) = purger {
+        let _ = purger.await;
+    }
+
+    // The router is gone by now, so this is the last handle on the pool.
+    match Arc::try_unwrap(app_state) {
+        Ok(app_state) => drop(app_state.pool),
+        Err(_) => tracing::warn!("database pool still in use at exit"),
+    }
+
+    tracing::info!("Server stopped");
+}
+
+/// Resolves on Ctrl+C or SIGTERM, telling everything watching `shutdown` to stop.
+async fn shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl+C.");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM.")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    tracing::info!("Shutting down");
+    shutdown.cancel();
 }
@@ -0,0 +1,159 @@
+use std::{sync::LazyLock, time::Duration};
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::{
+    DbPool,
+    repository::{HomeRepository, RepoResult},
+};
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Everything `/metrics` exposes; gauges are refreshed on each scrape.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    db_queries: HistogramVec,
+    pool_connections: IntGauge,
+    pool_idle: IntGauge,
+    pool_max: IntGauge,
+    houses: IntGauge,
+    rooms: IntGauge,
+    devices: IntGauge,
+    devices_on: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status."),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time to answer an HTTP request.",
+            ),
+            &["method", "route"],
+        )
+        .unwrap();
+        let db_queries = HistogramVec::new(
+            HistogramOpts::new("db_query_duration_seconds", "Time to run a SQL statement.")
+                .buckets(vec![
+                    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25,
+                    0.5, 1.0,
+                ]),
+            &["statement"],
+        )
+        .unwrap();
+        let pool_connections = IntGauge::new(
+            "db_pool_connections",
+            "Connections currently open in the pool.",
+        )
+        .unwrap();
+        let pool_idle =
+            IntGauge::new("db_pool_idle_connections", "Open connections not in use.").unwrap();
+        let pool_max = IntGauge::new(
+            "db_pool_max_connections",
+            "Most connections the pool opens.",
+        )
+        .unwrap();
+        let houses = IntGauge::new("smarthome_houses", "Houses on the server.").unwrap();
+        let rooms = IntGauge::new("smarthome_rooms", "Rooms in all houses.").unwrap();
+        let devices = IntGauge::new("smarthome_devices", "Devices in all rooms.").unwrap();
+        let devices_on = IntGaugeVec::new(
+            Opts::new("smarthome_devices_on", "Devices switched on, by type."),
+            &["device_type"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry.register(Box::new(http_duration.clone())).unwrap();
+        registry.register(Box::new(db_queries.clone())).unwrap();
+        registry
+            .register(Box::new(pool_connections.clone()))
+            .unwrap();
+        registry.register(Box::new(pool_idle.clone())).unwrap();
+        registry.register(Box::new(pool_max.clone())).unwrap();
+        registry.register(Box::new(houses.clone())).unwrap();
+        registry.register(Box::new(rooms.clone())).unwrap();
+        registry.register(Box::new(devices.clone())).unwrap();
+        registry.register(Box::new(devices_on.clone())).unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_duration,
+            db_queries,
+            pool_connections,
+            pool_idle,
+            pool_max,
+            houses,
+            rooms,
+            devices,
+            devices_on,
+        }
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_duration
+            .with_label_values(&[method, route])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a statement under its leading keyword, keeping the label set small.
+    pub fn observe_query(&self, sql: &str, elapsed: Duration) {
+        let verb = sql
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        let statement = match verb.as_str() {
+            "SELECT" | "INSERT" | "UPDATE" | "DELETE" | "BEGIN" | "COMMIT" | "ROLLBACK" => {
+                verb.as_str()
+            }
+            _ => "OTHER",
+        };
+
+        self.db_queries
+            .with_label_values(&[statement])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Refreshes the gauges and renders every metric in the Prometheus text format.
+    pub fn render(&self, pool: &DbPool, repo: &dyn HomeRepository) -> RepoResult<String> {
+        let state = pool.state();
+        self.pool_connections.set(state.connections.into());
+        self.pool_idle.set(state.idle_connections.into());
+        self.pool_max.set(pool.max_size().into());
+
+        let counts = repo.counts()?;
+        self.houses.set(counts.houses);
+        self.rooms.set(counts.rooms);
+        self.devices.set(counts.devices);
+
+        self.devices_on.reset();
+        for (device_type, count) in counts.devices_on {
+            self.devices_on
+                .with_label_values(&[&device_type])
+                .set(count);
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("metrics encode as text");
+
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
@@ -1,7 +1,19 @@
-use crate::schema::{device, house, room};
+use crate::schema::{
+    alert, api_token, audit_log, device, device_state_log, house, house_member, invite,
+    invite_device, invite_room, reading, room, user,
+};
 use diesel::prelude::*;
 
-#[derive(serde::Serialize, serde::Deserialize, Queryable, Selectable, Identifiable, Debug)]
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Queryable,
+    Selectable,
+    Identifiable,
+    Clone,
+    Debug,
+    utoipa::ToSchema,
+)]
 #[diesel(table_name = house)]
 #[diesel(primary_key(id))]
 pub struct House {
@@ -16,7 +28,15 @@ pub struct NewHouse {
 }
 
 #[derive(
-    serde::Serialize, serde::Deserialize, Queryable, Selectable, Identifiable, Associations, Debug,
+    serde::Serialize,
+    serde::Deserialize,
+    Queryable,
+    Selectable,
+    Identifiable,
+    Associations,
+    Clone,
+    Debug,
+    utoipa::ToSchema,
 )]
 #[diesel(table_name = room)]
 #[diesel(primary_key(id))]
@@ -35,7 +55,15 @@ pub struct NewRoom {
 }
 
 #[derive(
-    serde::Serialize, serde::Deserialize, Queryable, Selectable, Identifiable, Associations, Debug,
+    serde::Serialize,
+    serde::Deserialize,
+    Queryable,
+    Selectable,
+    Identifiable,
+    Associations,
+    Clone,
+    Debug,
+    utoipa::ToSchema,
 )]
 #[diesel(table_name = device)]
 #[diesel(primary_key(id))]
@@ -46,6 +74,9 @@ pub struct Device {
     pub name: String,
     pub device_type: String,
     pub state: bool,
+    pub power: Option<f64>,
+    pub last_reading: Option<f64>,
+    pub last_reading_at: Option<i64>,
 }
 
 #[derive(Insertable)]
@@ -55,4 +86,252 @@ pub struct NewDevice {
     pub room: i32,
     pub device_type: String,
     pub state: bool,
+    pub power: Option<f64>,
+}
+
+/// Device type of smart sockets, the only devices that report power draw.
+pub const SOCKET: &str = "socket";
+
+/// Whether devices of the type push numeric readings.
+pub fn reports_readings(device_type: &str) -> bool {
+    matches!(device_type, "termometro" | "thermometer" | "sensor")
+}
+
+/// Checks that only sockets have a power draw, and that it is a sane one.
+pub fn check_power(device_type: &str, power: Option<f64>) -> Result<(), String> {
+    match power {
+        Some(_) if device_type != SOCKET => {
+            Err(format!("power draw is only recorded for {SOCKET} devices"))
+        }
+        Some(power) if !(power >= 0.0 && power.is_finite()) => {
+            Err("power draw must be a non-negative number of watts".to_owned())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Queryable, Selectable, Identifiable, Associations, Clone, Debug)]
+#[diesel(table_name = device_state_log)]
+#[diesel(primary_key(id))]
+#[diesel(belongs_to(Device, foreign_key=device))]
+pub struct DeviceStateLog {
+    pub id: i32,
+    pub device: i32,
+    pub state: bool,
+    pub power: Option<f64>,
+    pub changed_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = device_state_log)]
+pub struct NewDeviceStateLog {
+    pub device: i32,
+    pub state: bool,
+    pub power: Option<f64>,
+    pub changed_at: i64,
+}
+
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Queryable,
+    Selectable,
+    Associations,
+    Debug,
+    utoipa::ToSchema,
+)]
+#[diesel(table_name = reading)]
+#[diesel(belongs_to(Device, foreign_key=device))]
+pub struct Reading {
+    pub id: i32,
+    pub device: i32,
+    pub value: f64,
+    pub taken_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = reading)]
+pub struct NewReading {
+    pub device: i32,
+    pub value: f64,
+    pub taken_at: i64,
+}
+
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Queryable,
+    Selectable,
+    Identifiable,
+    Associations,
+    Clone,
+    Debug,
+    utoipa::ToSchema,
+)]
+#[diesel(table_name = alert)]
+#[diesel(primary_key(id))]
+#[diesel(belongs_to(Device, foreign_key=device))]
+pub struct Alert {
+    pub id: i32,
+    pub device: i32,
+    pub kind: String,
+    pub threshold: f64,
+    pub for_secs: i64,
+    pub state: String,
+    pub breached_since: Option<i64>,
+    pub changed_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = alert)]
+pub struct NewAlert {
+    pub device: i32,
+    pub kind: String,
+    pub threshold: f64,
+    pub for_secs: i64,
+    pub changed_at: i64,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = audit_log)]
+pub struct AuditLog {
+    pub id: i32,
+    pub at: i64,
+    pub actor: String,
+    pub method: String,
+    pub route: String,
+    pub uri: String,
+    pub status: i32,
+    pub house: Option<i32>,
+    pub room: Option<i32>,
+    pub device: Option<i32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = audit_log)]
+pub struct NewAuditLog {
+    pub at: i64,
+    pub actor: String,
+    pub method: String,
+    pub route: String,
+    pub uri: String,
+    pub status: i32,
+    pub house: Option<i32>,
+    pub room: Option<i32>,
+    pub device: Option<i32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// An issued API token; only the hash of the secret is stored.
+#[derive(serde::Serialize, serde::Deserialize, Queryable, Selectable, Debug, utoipa::ToSchema)]
+#[diesel(table_name = api_token)]
+pub struct ApiToken {
+    pub id: i32,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub admin: bool,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+    pub user: Option<i32>,
+    pub expires_at: Option<i64>,
+    pub invite: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = api_token)]
+pub struct NewApiToken {
+    pub name: String,
+    pub token_hash: String,
+    pub admin: bool,
+    pub created_at: i64,
+    pub user: Option<i32>,
+    pub expires_at: Option<i64>,
+    pub invite: Option<i32>,
+}
+
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Queryable,
+    Selectable,
+    Identifiable,
+    Debug,
+    utoipa::ToSchema,
+)]
+#[diesel(table_name = user)]
+#[diesel(primary_key(id))]
+pub struct User {
+    pub id: i32,
+    pub login: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user)]
+pub struct NewUser {
+    pub login: String,
+    pub password_hash: String,
+    pub created_at: i64,
+}
+
+#[derive(
+    serde::Serialize, serde::Deserialize, Queryable, Selectable, Insertable, Associations, Debug,
+)]
+#[diesel(table_name = house_member)]
+#[diesel(belongs_to(House, foreign_key=house))]
+#[diesel(belongs_to(User, foreign_key=user))]
+pub struct HouseMember {
+    pub house: i32,
+    pub user: i32,
+    pub role: String,
+}
+
+/// Time-limited access to a house, or to some of its rooms and devices.
+#[derive(
+    serde::Serialize, serde::Deserialize, Queryable, Selectable, Identifiable, Associations, Debug,
+)]
+#[diesel(table_name = invite)]
+#[diesel(belongs_to(House, foreign_key=house))]
+pub struct Invite {
+    pub id: i32,
+    pub house: i32,
+    pub name: String,
+    pub permission: String,
+    pub whole_house: bool,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = invite)]
+pub struct NewInvite {
+    pub house: i32,
+    pub name: String,
+    pub permission: String,
+    pub whole_house: bool,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+#[derive(Queryable, Selectable, Insertable, Associations, Debug)]
+#[diesel(table_name = invite_room)]
+#[diesel(belongs_to(Invite, foreign_key=invite))]
+pub struct InviteRoom {
+    pub invite: i32,
+    pub room: i32,
+}
+
+#[derive(Queryable, Selectable, Insertable, Associations, Debug)]
+#[diesel(table_name = invite_device)]
+#[diesel(belongs_to(Invite, foreign_key=invite))]
+pub struct InviteDevice {
+    pub invite: i32,
+    pub device: i32,
 }
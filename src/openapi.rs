@@ -0,0 +1,116 @@
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+};
+
+use crate::handlers;
+
+/// OpenAPI 3 document of the whole HTTP API, served at `/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Smart home API"),
+    paths(
+        handlers::list_houses,
+        handlers::add_house,
+        handlers::upd_house,
+        handlers::del_house,
+        handlers::export_house,
+        handlers::import_house,
+        handlers::put_config,
+        handlers::house_alerts,
+        handlers::events,
+        handlers::get_energy,
+        handlers::get_rooms,
+        handlers::add_room,
+        handlers::upd_room,
+        handlers::del_room,
+        handlers::get_devices,
+        handlers::add_device,
+        handlers::upd_device,
+        handlers::del_device,
+        handlers::list_trash,
+        handlers::restore_house,
+        handlers::restore_room,
+        handlers::restore_device,
+        handlers::get_readings,
+        handlers::add_readings,
+        handlers::get_reading_stats,
+        handlers::get_alerts,
+        handlers::add_alert,
+        handlers::del_alert,
+        handlers::get_members,
+        handlers::add_member,
+        handlers::del_member,
+        handlers::list_invites,
+        handlers::add_invite,
+        handlers::del_invite,
+        handlers::my_invite,
+        handlers::login,
+        handlers::logout,
+        handlers::healthz,
+        handlers::readyz,
+        handlers::get_metrics,
+        handlers::openapi_json,
+        handlers::docs,
+        handlers::docs_asset,
+        handlers::list_audit,
+        handlers::add_backup,
+        handlers::list_backups,
+        handlers::restore_backup,
+        handlers::drop_all,
+        handlers::drop_all_token,
+        handlers::list_tokens,
+        handlers::add_token,
+        handlers::revoke_token,
+        handlers::list_users,
+        handlers::add_user,
+    ),
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+    tags(
+        (name = "houses"),
+        (name = "rooms"),
+        (name = "devices"),
+        (name = "trash", description = "Deleted houses, rooms and devices until they are purged"),
+        (name = "readings"),
+        (name = "alerts"),
+        (name = "access", description = "Users, sessions, members and invites"),
+        (name = "admin", description = "Needs an admin token"),
+        (name = "health"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the `bearer` scheme every operation but the public ones requires.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_default()
+            .add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+    }
+}
+
+/// Swagger UI page loading the document and the assets next to it, so it works
+/// under a prefix too.
+pub const DOCS_HTML: &str = r##"<!doctype html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>Smart home API</title>
+  <link rel="stylesheet" href="docs/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="docs/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
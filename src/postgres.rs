@@ -0,0 +1,747 @@
+//! PostgreSQL storage of the house layout, built with the `postgres` feature.
+//!
+//! Only the layout moves to Postgres: access control, readings, alerts and
+//! the audit log stay in the SQLite database of [`AppState::pool`](crate::AppState).
+
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, PgConnection,
+    QueryDsl, RunQueryDsl, SelectableHelper,
+    dsl::count_star,
+    r2d2::{ConnectionManager, Pool, PooledConnection},
+};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
+
+use crate::{
+    layout::Kind,
+    models::{
+        Device, DeviceStateLog, House, NewDevice, NewDeviceStateLog, NewHouse, NewRoom, Room,
+    },
+    repository::{
+        Cleared, Deletion, DeviceChange, HomeRepository, LayoutChange, LayoutCounts, Purged,
+        RepoError, RepoResult, missing, trashed,
+    },
+    schema::{device, device_state_log, house, room},
+    telemetry::{Counted, CountedRow},
+    trash::Trashed,
+    unix_now,
+};
+
+/// Postgres dialect of the layout migrations in `migrations`.
+pub const PG_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations_pg");
+
+pub type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+/// The layout in the Postgres database the pool connects to.
+pub struct PgRepository {
+    pool: PgPool,
+}
+
+impl PgRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Connects to `url` and brings the layout tables up to date.
+    pub fn connect(url: &str, pool_size: u32) -> RepoResult<Self> {
+        let pool = Pool::builder()
+            .max_size(pool_size)
+            .build(ConnectionManager::<PgConnection>::new(url))?;
+
+        pool.get()?
+            .run_pending_migrations(PG_MIGRATIONS)
+            .map_err(|e| RepoError::Unavailable(e.to_string()))?;
+
+        Ok(Self::new(pool))
+    }
+
+    fn conn(&self) -> RepoResult<PooledConnection<ConnectionManager<PgConnection>>> {
+        Ok(self.pool.get()?)
+    }
+}
+
+fn log_state(conn: &mut PgConnection, dev: &Device) -> diesel::QueryResult<usize> {
+    diesel::insert_into(device_state_log::table)
+        .values(&NewDeviceStateLog {
+            device: dev.id,
+            state: dev.state,
+            power: dev.power,
+            changed_at: unix_now(),
+        })
+        .execute(conn)
+        .counted()
+}
+
+/// Deletes the devices and their state history.
+fn drop_devices(conn: &mut PgConnection, ids: &[i32]) -> diesel::QueryResult<usize> {
+    diesel::delete(device_state_log::table.filter(device_state_log::device.eq_any(ids)))
+        .execute(conn)
+        .counted()?;
+    diesel::delete(device::table.filter(device::id.eq_any(ids)))
+        .execute(conn)
+        .counted()
+}
+
+// The statements behind single writes and batches alike; callers hold the transaction.
+
+fn insert_house(conn: &mut PgConnection, name: &str) -> diesel::QueryResult<House> {
+    diesel::insert_into(house::table)
+        .values(NewHouse {
+            name: name.to_owned(),
+        })
+        .returning(House::as_returning())
+        .get_result(conn)
+        .counted_row()
+}
+
+fn update_house_name(
+    conn: &mut PgConnection,
+    id: i32,
+    name: &str,
+) -> diesel::QueryResult<Option<House>> {
+    diesel::update(house::table.filter(house::id.eq(id)))
+        .filter(house::deleted_at.is_null())
+        .set(house::name.eq(name))
+        .returning(House::as_returning())
+        .get_result(conn)
+        .optional()
+        .counted()
+}
+
+fn insert_room(conn: &mut PgConnection, house_id: i32, name: &str) -> diesel::QueryResult<Room> {
+    diesel::insert_into(room::table)
+        .values(&NewRoom {
+            house: house_id,
+            name: name.to_owned(),
+        })
+        .returning(Room::as_returning())
+        .get_result(conn)
+        .counted_row()
+}
+
+fn update_room_name(
+    conn: &mut PgConnection,
+    id: i32,
+    name: &str,
+) -> diesel::QueryResult<Option<Room>> {
+    diesel::update(room::table.filter(room::id.eq(id)))
+        .filter(room::deleted_at.is_null())
+        .set(room::name.eq(name))
+        .returning(Room::as_returning())
+        .get_result(conn)
+        .optional()
+        .counted()
+}
+
+fn trash_house(conn: &mut PgConnection, id: i32, del: &Deletion) -> diesel::QueryResult<usize> {
+    let rooms = room::table
+        .filter(room::house.eq(id))
+        .filter(room::deleted_at.is_null())
+        .select(room::id)
+        .load::<i32>(conn)
+        .counted()?;
+    trash_rooms(conn, &rooms, del)?;
+
+    diesel::update(house::table.filter(house::id.eq(id)))
+        .filter(house::deleted_at.is_null())
+        .set((
+            house::deleted_at.eq(del.at),
+            house::deleted_with.eq(&del.with),
+        ))
+        .execute(conn)
+        .counted()
+}
+
+fn trash_rooms(conn: &mut PgConnection, ids: &[i32], del: &Deletion) -> diesel::QueryResult<usize> {
+    diesel::update(device::table.filter(device::room.eq_any(ids)))
+        .filter(device::deleted_at.is_null())
+        .set((
+            device::deleted_at.eq(del.at),
+            device::deleted_with.eq(&del.with),
+        ))
+        .execute(conn)
+        .counted()?;
+
+    diesel::update(room::table.filter(room::id.eq_any(ids)))
+        .filter(room::deleted_at.is_null())
+        .set((
+            room::deleted_at.eq(del.at),
+            room::deleted_with.eq(&del.with),
+        ))
+        .execute(conn)
+        .counted()
+}
+
+fn trash_device(conn: &mut PgConnection, id: i32, del: &Deletion) -> diesel::QueryResult<usize> {
+    diesel::update(device::table.filter(device::id.eq(id)))
+        .filter(device::deleted_at.is_null())
+        .set((
+            device::deleted_at.eq(del.at),
+            device::deleted_with.eq(&del.with),
+        ))
+        .execute(conn)
+        .counted()
+}
+
+/// Takes the rooms out of the trash with their devices deleted along with `with`.
+fn untrash_rooms(conn: &mut PgConnection, ids: &[i32], with: &str) -> diesel::QueryResult<()> {
+    diesel::update(room::table.filter(room::id.eq_any(ids)))
+        .filter(room::deleted_with.eq(with))
+        .set((
+            room::deleted_at.eq(None::<i64>),
+            room::deleted_with.eq(None::<String>),
+        ))
+        .execute(conn)
+        .counted()?;
+    diesel::update(device::table.filter(device::room.eq_any(ids)))
+        .filter(device::deleted_with.eq(with))
+        .set((
+            device::deleted_at.eq(None::<i64>),
+            device::deleted_with.eq(None::<String>),
+        ))
+        .execute(conn)
+        .counted()?;
+
+    Ok(())
+}
+
+fn insert_device(conn: &mut PgConnection, new: &NewDevice) -> diesel::QueryResult<Device> {
+    let res = diesel::insert_into(device::table)
+        .values(new)
+        .returning(Device::as_returning())
+        .get_result(conn)
+        .counted_row()?;
+
+    log_state(conn, &res)?;
+
+    Ok(res)
+}
+
+fn change_device(
+    conn: &mut PgConnection,
+    id: i32,
+    change: &DeviceChange,
+) -> diesel::QueryResult<Option<Device>> {
+    let Some(before) = device::table
+        .filter(device::id.eq(id))
+        .filter(device::deleted_at.is_null())
+        .select(Device::as_select())
+        .for_update()
+        .first(conn)
+        .optional()
+        .counted()?
+    else {
+        return Ok(None);
+    };
+
+    let res = diesel::update(device::table.filter(device::id.eq(id)))
+        .set((
+            device::name.eq(&change.name),
+            device::state.eq(change.state),
+            device::device_type.eq(&change.device_type),
+            device::power.eq(change.power),
+        ))
+        .returning(Device::as_returning())
+        .get_result(conn)
+        .counted_row()?;
+
+    if before.state != res.state || before.power != res.power {
+        log_state(conn, &res)?;
+    }
+
+    Ok(Some(res))
+}
+
+impl HomeRepository for PgRepository {
+    fn ping(&self) -> RepoResult<()> {
+        diesel::sql_query("SELECT 1")
+            .execute(&mut self.conn()?)
+            .counted()?;
+
+        Ok(())
+    }
+
+    fn houses(&self) -> RepoResult<Vec<House>> {
+        Ok(house::table
+            .filter(house::deleted_at.is_null())
+            .order(house::id)
+            .select(House::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn houses_in(&self, ids: &[i32]) -> RepoResult<Vec<House>> {
+        Ok(house::table
+            .filter(house::id.eq_any(ids))
+            .filter(house::deleted_at.is_null())
+            .order(house::id)
+            .select(House::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn house(&self, id: i32) -> RepoResult<Option<House>> {
+        Ok(house::table
+            .filter(house::id.eq(id))
+            .filter(house::deleted_at.is_null())
+            .select(House::as_select())
+            .first(&mut self.conn()?)
+            .optional()
+            .counted()?)
+    }
+
+    fn add_house(&self, name: &str) -> RepoResult<House> {
+        Ok(insert_house(&mut *self.conn()?, name)?)
+    }
+
+    fn rename_house(&self, id: i32, name: &str) -> RepoResult<Option<House>> {
+        Ok(update_house_name(&mut *self.conn()?, id, name)?)
+    }
+
+    fn delete_house(&self, id: i32) -> RepoResult<usize> {
+        Ok(self
+            .conn()?
+            .transaction(|conn| trash_house(conn, id, &Deletion::of("house", id)))?)
+    }
+
+    fn rooms(&self, house_id: i32) -> RepoResult<Vec<Room>> {
+        Ok(room::table
+            .filter(room::house.eq(house_id))
+            .filter(room::deleted_at.is_null())
+            .order(room::id)
+            .select(Room::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn room(&self, id: i32) -> RepoResult<Option<Room>> {
+        Ok(room::table
+            .filter(room::id.eq(id))
+            .filter(room::deleted_at.is_null())
+            .select(Room::as_select())
+            .first(&mut self.conn()?)
+            .optional()
+            .counted()?)
+    }
+
+    fn add_room(&self, house_id: i32, name: &str) -> RepoResult<Room> {
+        Ok(insert_room(&mut *self.conn()?, house_id, name)?)
+    }
+
+    fn rename_room(&self, id: i32, name: &str) -> RepoResult<Option<Room>> {
+        Ok(update_room_name(&mut *self.conn()?, id, name)?)
+    }
+
+    fn delete_room(&self, id: i32) -> RepoResult<usize> {
+        Ok(self
+            .conn()?
+            .transaction(|conn| trash_rooms(conn, &[id], &Deletion::of("room", id)))?)
+    }
+
+    fn devices(&self, room_id: i32) -> RepoResult<Vec<Device>> {
+        Ok(device::table
+            .filter(device::room.eq(room_id))
+            .filter(device::deleted_at.is_null())
+            .order(device::id)
+            .select(Device::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn house_devices(&self, house_id: i32) -> RepoResult<Vec<Device>> {
+        Ok(device::table
+            .inner_join(room::table)
+            .filter(room::house.eq(house_id))
+            .filter(device::deleted_at.is_null())
+            .order(device::id)
+            .select(Device::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn device(&self, id: i32) -> RepoResult<Option<Device>> {
+        Ok(device::table
+            .filter(device::id.eq(id))
+            .filter(device::deleted_at.is_null())
+            .select(Device::as_select())
+            .first(&mut self.conn()?)
+            .optional()
+            .counted()?)
+    }
+
+    fn add_device(&self, new: NewDevice) -> RepoResult<Device> {
+        Ok(self.conn()?.transaction(|conn| insert_device(conn, &new))?)
+    }
+
+    fn update_device(&self, id: i32, change: DeviceChange) -> RepoResult<Option<Device>> {
+        Ok(self
+            .conn()?
+            .transaction(|conn| change_device(conn, id, &change))?)
+    }
+
+    fn record_reading(&self, id: i32, value: f64, taken_at: i64) -> RepoResult<()> {
+        diesel::update(device::table)
+            .filter(device::id.eq(id))
+            .filter(device::deleted_at.is_null())
+            .filter(
+                device::last_reading_at
+                    .is_null()
+                    .or(device::last_reading_at.le(taken_at)),
+            )
+            .set((
+                device::last_reading.eq(value),
+                device::last_reading_at.eq(taken_at),
+            ))
+            .execute(&mut self.conn()?)
+            .counted()?;
+
+        Ok(())
+    }
+
+    fn delete_device(&self, id: i32) -> RepoResult<usize> {
+        Ok(self
+            .conn()?
+            .transaction(|conn| trash_device(conn, id, &Deletion::of("device", id)))?)
+    }
+
+    fn trash(&self) -> RepoResult<Vec<Trashed>> {
+        let mut conn = self.conn()?;
+
+        let houses = house::table
+            .filter(house::deleted_at.is_not_null())
+            .select((house::id, house::name, house::deleted_at))
+            .load::<(i32, String, Option<i64>)>(&mut conn)
+            .counted()?;
+        let rooms = room::table
+            .filter(room::deleted_at.is_not_null())
+            .select((room::id, room::name, room::house, room::deleted_at))
+            .load::<(i32, String, i32, Option<i64>)>(&mut conn)
+            .counted()?;
+        let devices = device::table
+            .inner_join(room::table)
+            .filter(device::deleted_at.is_not_null())
+            .select((
+                device::id,
+                device::name,
+                room::house,
+                device::room,
+                device::deleted_at,
+            ))
+            .load::<(i32, String, i32, i32, Option<i64>)>(&mut conn)
+            .counted()?;
+
+        Ok(trashed(houses, rooms, devices))
+    }
+
+    fn restore_house(&self, id: i32) -> RepoResult<Option<House>> {
+        self.conn()?.transaction(|conn| {
+            let Some(with) = house::table
+                .filter(house::id.eq(id))
+                .select(house::deleted_with)
+                .for_update()
+                .first::<Option<String>>(conn)
+                .optional()
+                .counted()?
+                .flatten()
+            else {
+                return Ok(None);
+            };
+
+            let res = diesel::update(house::table.filter(house::id.eq(id)))
+                .set((
+                    house::deleted_at.eq(None::<i64>),
+                    house::deleted_with.eq(None::<String>),
+                ))
+                .returning(House::as_returning())
+                .get_result(conn)
+                .counted_row()?;
+            let rooms = room::table
+                .filter(room::house.eq(id))
+                .filter(room::deleted_with.eq(&with))
+                .select(room::id)
+                .load::<i32>(conn)
+                .counted()?;
+            untrash_rooms(conn, &rooms, &with)?;
+
+            Ok(Some(res))
+        })
+    }
+
+    fn restore_room(&self, id: i32) -> RepoResult<Option<Room>> {
+        self.conn()?.transaction(|conn| {
+            let Some((house_id, Some(with))) = room::table
+                .filter(room::id.eq(id))
+                .select((room::house, room::deleted_with))
+                .for_update()
+                .first::<(i32, Option<String>)>(conn)
+                .optional()
+                .counted()?
+            else {
+                return Ok(None);
+            };
+
+            let house_live = house::table
+                .filter(house::id.eq(house_id))
+                .filter(house::deleted_at.is_null())
+                .count()
+                .get_result::<i64>(conn)
+                .counted_row()?;
+            if house_live == 0 {
+                return Err(missing("house", house_id));
+            }
+
+            untrash_rooms(conn, &[id], &with)?;
+
+            Ok(Some(
+                room::table
+                    .filter(room::id.eq(id))
+                    .select(Room::as_select())
+                    .first(conn)
+                    .counted_row()?,
+            ))
+        })
+    }
+
+    fn restore_device(&self, id: i32) -> RepoResult<Option<Device>> {
+        self.conn()?.transaction(|conn| {
+            let Some((room_id, Some(_))) = device::table
+                .filter(device::id.eq(id))
+                .select((device::room, device::deleted_at))
+                .for_update()
+                .first::<(i32, Option<i64>)>(conn)
+                .optional()
+                .counted()?
+            else {
+                return Ok(None);
+            };
+
+            let room_live = room::table
+                .filter(room::id.eq(room_id))
+                .filter(room::deleted_at.is_null())
+                .count()
+                .get_result::<i64>(conn)
+                .counted_row()?;
+            if room_live == 0 {
+                return Err(missing("room", room_id));
+            }
+
+            Ok(Some(
+                diesel::update(device::table.filter(device::id.eq(id)))
+                    .set((
+                        device::deleted_at.eq(None::<i64>),
+                        device::deleted_with.eq(None::<String>),
+                    ))
+                    .returning(Device::as_returning())
+                    .get_result(conn)
+                    .counted_row()?,
+            ))
+        })
+    }
+
+    fn purge(&self, before: i64) -> RepoResult<Purged> {
+        Ok(self.conn()?.transaction(|conn| {
+            let houses = house::table
+                .filter(house::deleted_at.le(before))
+                .select(house::id)
+                .load::<i32>(conn)
+                .counted()?;
+            let rooms = room::table
+                .filter(room::deleted_at.le(before).or(room::house.eq_any(&houses)))
+                .select(room::id)
+                .load::<i32>(conn)
+                .counted()?;
+            let devices = device::table
+                .filter(
+                    device::deleted_at
+                        .le(before)
+                        .or(device::room.eq_any(&rooms)),
+                )
+                .select(device::id)
+                .load::<i32>(conn)
+                .counted()?;
+
+            let cleared = Cleared {
+                state_changes: diesel::delete(
+                    device_state_log::table.filter(device_state_log::device.eq_any(&devices)),
+                )
+                .execute(conn)
+                .counted()?,
+                devices: drop_devices(conn, &devices)?,
+                rooms: diesel::delete(room::table.filter(room::id.eq_any(&rooms)))
+                    .execute(conn)
+                    .counted()?,
+                houses: diesel::delete(house::table.filter(house::id.eq_any(&houses)))
+                    .execute(conn)
+                    .counted()?,
+            };
+
+            Ok::<_, diesel::result::Error>(Purged {
+                houses,
+                rooms,
+                devices,
+                cleared,
+            })
+        })?)
+    }
+
+    fn gone(&self, kind: Kind, ids: &[i32]) -> RepoResult<Vec<i32>> {
+        let mut conn = self.conn()?;
+
+        let known = match kind {
+            Kind::House => house::table
+                .filter(house::id.eq_any(ids))
+                .select(house::id)
+                .load::<i32>(&mut conn),
+            Kind::Room => room::table
+                .filter(room::id.eq_any(ids))
+                .select(room::id)
+                .load(&mut conn),
+            Kind::Device => device::table
+                .filter(device::id.eq_any(ids))
+                .select(device::id)
+                .load(&mut conn),
+        }
+        .counted()?;
+
+        Ok(ids
+            .iter()
+            .copied()
+            .filter(|id| !known.contains(id))
+            .collect())
+    }
+
+    fn discard_house(&self, id: i32) -> RepoResult<usize> {
+        Ok(self.conn()?.transaction(|conn| {
+            let rooms = room::table
+                .filter(room::house.eq(id))
+                .select(room::id)
+                .load::<i32>(conn)
+                .counted()?;
+            let devices = device::table
+                .filter(device::room.eq_any(&rooms))
+                .select(device::id)
+                .load::<i32>(conn)
+                .counted()?;
+
+            drop_devices(conn, &devices)?;
+            diesel::delete(room::table.filter(room::id.eq_any(&rooms)))
+                .execute(conn)
+                .counted()?;
+            diesel::delete(house::table.filter(house::id.eq(id)))
+                .execute(conn)
+                .counted()
+        })?)
+    }
+
+    fn state_history(&self, devices: &[i32], until: i64) -> RepoResult<Vec<DeviceStateLog>> {
+        Ok(device_state_log::table
+            .filter(device_state_log::device.eq_any(devices))
+            .filter(device_state_log::changed_at.lt(until))
+            .order((device_state_log::changed_at, device_state_log::id))
+            .select(DeviceStateLog::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn counts(&self) -> RepoResult<LayoutCounts> {
+        let mut conn = self.conn()?;
+
+        Ok(LayoutCounts {
+            houses: house::table
+                .filter(house::deleted_at.is_null())
+                .count()
+                .get_result(&mut conn)
+                .counted_row()?,
+            rooms: room::table
+                .filter(room::deleted_at.is_null())
+                .count()
+                .get_result(&mut conn)
+                .counted_row()?,
+            devices: device::table
+                .filter(device::deleted_at.is_null())
+                .count()
+                .get_result(&mut conn)
+                .counted_row()?,
+            devices_on: device::table
+                .filter(device::deleted_at.is_null())
+                .filter(device::state.eq(true))
+                .group_by(device::device_type)
+                .select((device::device_type, count_star()))
+                .load(&mut conn)
+                .counted()?,
+        })
+    }
+
+    fn clear(&self) -> RepoResult<Cleared> {
+        Ok(self.conn()?.transaction(|conn| {
+            Ok::<_, diesel::result::Error>(Cleared {
+                state_changes: diesel::delete(device_state_log::table)
+                    .execute(conn)
+                    .counted()?,
+                devices: diesel::delete(device::table).execute(conn).counted()?,
+                rooms: diesel::delete(room::table).execute(conn).counted()?,
+                houses: diesel::delete(house::table).execute(conn).counted()?,
+            })
+        })?)
+    }
+
+    fn apply(&self, changes: &[LayoutChange]) -> RepoResult<Vec<i32>> {
+        self.conn()?.transaction(|conn| {
+            let mut applied = Vec::with_capacity(changes.len());
+
+            for change in changes {
+                let id = match change {
+                    LayoutChange::AddHouse { name } => insert_house(conn, name)?.id,
+                    LayoutChange::RenameHouse { id, name } => {
+                        update_house_name(conn, *id, name)?
+                            .ok_or_else(|| missing("house", *id))?
+                            .id
+                    }
+                    LayoutChange::AddRoom { house, name } => {
+                        insert_room(conn, house.resolve(&applied), name)?.id
+                    }
+                    LayoutChange::RenameRoom { id, name } => {
+                        update_room_name(conn, *id, name)?
+                            .ok_or_else(|| missing("room", *id))?
+                            .id
+                    }
+                    LayoutChange::DeleteRoom { id } => {
+                        match trash_rooms(conn, &[*id], &Deletion::of("room", *id))? {
+                            0 => return Err(missing("room", *id)),
+                            _ => *id,
+                        }
+                    }
+                    LayoutChange::AddDevice {
+                        room,
+                        name,
+                        device_type,
+                        power,
+                    } => {
+                        let new = NewDevice {
+                            name: name.clone(),
+                            room: room.resolve(&applied),
+                            device_type: device_type.clone(),
+                            state: false,
+                            power: *power,
+                        };
+                        insert_device(conn, &new)?.id
+                    }
+                    LayoutChange::UpdateDevice { id, change } => {
+                        change_device(conn, *id, change)?
+                            .ok_or_else(|| missing("device", *id))?
+                            .id
+                    }
+                    LayoutChange::DeleteDevice { id } => {
+                        match trash_device(conn, *id, &Deletion::of("device", *id))? {
+                            0 => return Err(missing("device", *id)),
+                            _ => *id,
+                        }
+                    }
+                };
+
+                applied.push(id);
+            }
+
+            Ok(applied)
+        })
+    }
+}
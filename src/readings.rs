@@ -0,0 +1,88 @@
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection, dsl};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{schema::reading, telemetry::Counted};
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct ReadingForm {
+    pub value: f64,
+    /// Unix seconds; the time of ingestion when omitted.
+    #[serde(default)]
+    pub taken_at: Option<i64>,
+}
+
+/// Time range of a readings query. Defaults to the last 24 hours;
+/// without `interval` the stats cover the whole range in one bucket.
+#[derive(Deserialize, Serialize, Debug, Default, IntoParams)]
+pub struct ReadingsQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub interval: Option<i64>,
+}
+
+impl ReadingsQuery {
+    /// Resolves the `[from, to)` range relative to `now`.
+    pub fn range(&self, now: i64) -> (i64, i64) {
+        let to = self.to.unwrap_or(now);
+        let from = self.from.unwrap_or(to - 24 * 60 * 60);
+
+        (from, to)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ReadingStats {
+    pub from: i64,
+    pub to: i64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub count: i64,
+}
+
+/// Min/max/avg of the device's readings in `[from, to)`, grouped into
+/// `interval` buckets starting at `from`; empty buckets are left out.
+pub(crate) fn stats(
+    conn: &mut SqliteConnection,
+    device: i32,
+    from: i64,
+    to: i64,
+    interval: i64,
+) -> diesel::QueryResult<Vec<ReadingStats>> {
+    let bucket = (reading::taken_at - from) / interval;
+
+    let rows = reading::table
+        .filter(reading::device.eq(device))
+        .filter(reading::taken_at.ge(from))
+        .filter(reading::taken_at.lt(to))
+        .group_by(bucket)
+        .select((
+            dsl::min(bucket),
+            dsl::min(reading::value),
+            dsl::max(reading::value),
+            dsl::avg(reading::value),
+            dsl::count_star(),
+        ))
+        .load::<(Option<i64>, Option<f64>, Option<f64>, Option<f64>, i64)>(conn)
+        .counted()?;
+
+    let mut res = rows
+        .into_iter()
+        .map(|(bucket, min, max, avg, count)| {
+            let bucket = bucket.unwrap_or_default();
+
+            ReadingStats {
+                from: from + bucket * interval,
+                to: from + (bucket + 1) * interval,
+                min: min.unwrap_or_default(),
+                max: max.unwrap_or_default(),
+                avg: avg.unwrap_or_default(),
+                count,
+            }
+        })
+        .collect::<Vec<_>>();
+    res.sort_by_key(|s| s.from);
+
+    Ok(res)
+}
@@ -0,0 +1,1877 @@
+//! Storage of the house layout: houses, their rooms and devices, and the
+//! state history of the devices.
+//!
+//! Deleted rows go to the trash (see [`crate::trash`]) and stay out of every
+//! lookup, listing and change until restored or purged.
+//!
+//! Handlers reach the layout only through [`HomeRepository`], so it can live
+//! in SQLite ([`SqliteRepository`]) or in memory ([`MemoryRepository`]).
+//! Access control, readings, alerts and the audit log stay in the database
+//! of the pool and refer to layout rows by id.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Mutex, PoisonError},
+};
+
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
+    SelectableHelper, SqliteConnection,
+    dsl::count_star,
+    r2d2::{ConnectionManager, PooledConnection},
+    result::DatabaseErrorKind,
+};
+
+use crate::{
+    DbPool,
+    layout::Kind,
+    models::{
+        Device, DeviceStateLog, House, NewDevice, NewDeviceStateLog, NewHouse, NewRoom, Room,
+    },
+    schema::{device, device_state_log, house, room},
+    telemetry::{Counted, CountedRow},
+    trash::Trashed,
+    unix_now,
+};
+
+#[derive(Debug)]
+pub enum RepoError {
+    /// The store cannot be reached.
+    Unavailable(String),
+    /// The name is already taken where the row was put.
+    Conflict(String),
+    /// A row a batch of changes refers to is gone.
+    Missing(String),
+    Query(diesel::result::Error),
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoError::Unavailable(e) => write!(f, "storage unavailable: {e}"),
+            RepoError::Conflict(e) => write!(f, "{e}"),
+            RepoError::Missing(e) => write!(f, "{e} no longer exists"),
+            RepoError::Query(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for RepoError {}
+
+impl From<diesel::result::Error> for RepoError {
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                RepoError::Conflict(info.message().to_owned())
+            }
+            e => RepoError::Query(e),
+        }
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for RepoError {
+    fn from(e: diesel::r2d2::PoolError) -> Self {
+        RepoError::Unavailable(e.to_string())
+    }
+}
+
+impl<T> From<PoisonError<T>> for RepoError {
+    fn from(e: PoisonError<T>) -> Self {
+        RepoError::Unavailable(e.to_string())
+    }
+}
+
+pub type RepoResult<T> = Result<T, RepoError>;
+
+/// New values of the editable fields of a device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceChange {
+    pub name: String,
+    pub device_type: String,
+    pub state: bool,
+    pub power: Option<f64>,
+}
+
+/// A row addressed by a [`LayoutChange`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A row already stored.
+    Id(i32),
+    /// The row added by the change at this index of the same batch.
+    Added(usize),
+}
+
+impl Target {
+    /// The id of the row, given the ids of the changes applied so far.
+    pub fn resolve(self, applied: &[i32]) -> i32 {
+        match self {
+            Target::Id(id) => id,
+            Target::Added(index) => applied[index],
+        }
+    }
+}
+
+/// One step of a batch applied by [`HomeRepository::apply`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayoutChange {
+    AddHouse {
+        name: String,
+    },
+    RenameHouse {
+        id: i32,
+        name: String,
+    },
+    AddRoom {
+        house: Target,
+        name: String,
+    },
+    RenameRoom {
+        id: i32,
+        name: String,
+    },
+    DeleteRoom {
+        id: i32,
+    },
+    /// Adds a switched-off device.
+    AddDevice {
+        room: Target,
+        name: String,
+        device_type: String,
+        power: Option<f64>,
+    },
+    UpdateDevice {
+        id: i32,
+        change: DeviceChange,
+    },
+    DeleteDevice {
+        id: i32,
+    },
+}
+
+pub(crate) fn missing(what: &str, id: i32) -> RepoError {
+    RepoError::Missing(format!("{what} {id}"))
+}
+
+/// What a delete leaves on every row it takes: when, and the row it was
+/// asked for, so that a restore brings back only what went with that row.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Deletion {
+    pub at: i64,
+    pub with: String,
+}
+
+impl Deletion {
+    pub(crate) fn of(what: &str, id: i32) -> Self {
+        Self {
+            at: unix_now(),
+            with: format!("{what} {id}"),
+        }
+    }
+}
+
+/// Size of the layout, for the metrics.
+#[derive(Default, Debug)]
+pub struct LayoutCounts {
+    pub houses: i64,
+    pub rooms: i64,
+    pub devices: i64,
+    /// Switched-on devices per device type.
+    pub devices_on: Vec<(String, i64)>,
+}
+
+/// Rows removed by [`HomeRepository::clear`] or [`HomeRepository::purge`].
+#[derive(Default, Debug)]
+pub struct Cleared {
+    pub houses: usize,
+    pub rooms: usize,
+    pub devices: usize,
+    pub state_changes: usize,
+}
+
+/// What [`HomeRepository::purge`] deleted for good, by id.
+#[derive(Default, Debug)]
+pub struct Purged {
+    pub houses: Vec<i32>,
+    pub rooms: Vec<i32>,
+    pub devices: Vec<i32>,
+    pub cleared: Cleared,
+}
+
+/// Houses, rooms and devices, whatever stores them.
+///
+/// Lookups return `None` for missing or deleted rows; renames and updates
+/// return the row as stored, or `None` if it does not exist. Deletes move the
+/// row and what is below it to the trash, stamped with the same time, and
+/// return the number of rows of the addressed kind deleted.
+pub trait HomeRepository: Send + Sync {
+    /// Checks that the store answers.
+    fn ping(&self) -> RepoResult<()> {
+        Ok(())
+    }
+
+    /// Every house, by id.
+    fn houses(&self) -> RepoResult<Vec<House>>;
+    fn houses_in(&self, ids: &[i32]) -> RepoResult<Vec<House>>;
+    fn house(&self, id: i32) -> RepoResult<Option<House>>;
+    fn add_house(&self, name: &str) -> RepoResult<House>;
+    fn rename_house(&self, id: i32, name: &str) -> RepoResult<Option<House>>;
+    /// Deletes the house with its rooms and devices.
+    fn delete_house(&self, id: i32) -> RepoResult<usize>;
+
+    /// Rooms of the house, by id.
+    fn rooms(&self, house: i32) -> RepoResult<Vec<Room>>;
+    fn room(&self, id: i32) -> RepoResult<Option<Room>>;
+    fn add_room(&self, house: i32, name: &str) -> RepoResult<Room>;
+    fn rename_room(&self, id: i32, name: &str) -> RepoResult<Option<Room>>;
+    /// Deletes the room with its devices.
+    fn delete_room(&self, id: i32) -> RepoResult<usize>;
+
+    /// Devices in the room, by id.
+    fn devices(&self, room: i32) -> RepoResult<Vec<Device>>;
+    /// Devices in every room of the house, by id.
+    fn house_devices(&self, house: i32) -> RepoResult<Vec<Device>>;
+    fn device(&self, id: i32) -> RepoResult<Option<Device>>;
+    /// Adds the device and starts its state history.
+    fn add_device(&self, new: NewDevice) -> RepoResult<Device>;
+    /// Updates the device, extending its state history when state or power change.
+    fn update_device(&self, id: i32, change: DeviceChange) -> RepoResult<Option<Device>>;
+    /// Stores `value` as the last reading, unless a later one is already known.
+    fn record_reading(&self, id: i32, value: f64, taken_at: i64) -> RepoResult<()>;
+    fn delete_device(&self, id: i32) -> RepoResult<usize>;
+
+    /// Every deleted row, those deleted along with their house or room
+    /// included, oldest first.
+    fn trash(&self) -> RepoResult<Vec<Trashed>>;
+    /// Takes the house out of the trash with the rooms and devices deleted
+    /// along with it; `None` if it is not in the trash.
+    fn restore_house(&self, id: i32) -> RepoResult<Option<House>>;
+    /// Like [`restore_house`](Self::restore_house), as long as the house is not deleted.
+    fn restore_room(&self, id: i32) -> RepoResult<Option<Room>>;
+    /// Takes the device out of the trash, as long as its room is not deleted.
+    fn restore_device(&self, id: i32) -> RepoResult<Option<Device>>;
+    /// Deletes for good the rows deleted at or before `before`, with the
+    /// state history of the devices.
+    fn purge(&self, before: i64) -> RepoResult<Purged>;
+    /// Of the ids, those of rows that are not in the layout, not even in the trash.
+    fn gone(&self, kind: Kind, ids: &[i32]) -> RepoResult<Vec<i32>>;
+
+    /// Like [`purge`](Self::purge), then runs `then` with the purged ids on `dbh`.
+    ///
+    /// Backends keeping the layout in that SQLite database run both in one
+    /// transaction, `then` before the deletes. The others purge first and give
+    /// `then` a transaction of its own, so that what it misses can be found
+    /// with [`gone`](Self::gone) later.
+    fn purge_with(
+        &self,
+        before: i64,
+        dbh: &mut SqliteConnection,
+        then: &mut dyn FnMut(&mut SqliteConnection, &Purged) -> diesel::QueryResult<()>,
+    ) -> RepoResult<Cleared> {
+        let purged = self.purge(before)?;
+        dbh.immediate_transaction(|conn| then(conn, &purged))?;
+
+        Ok(purged.cleared)
+    }
+
+    /// State changes of the devices before `until`, oldest first.
+    fn state_history(&self, devices: &[i32], until: i64) -> RepoResult<Vec<DeviceStateLog>>;
+    fn counts(&self) -> RepoResult<LayoutCounts>;
+    /// Deletes every house for good, the trash included.
+    fn clear(&self) -> RepoResult<Cleared>;
+    /// Like [`clear`](Self::clear), then runs `then` on `dbh`.
+    ///
+    /// Backends keeping the layout in that SQLite database run both in one
+    /// transaction, `then` first. The others clear first and give `then` a
+    /// transaction of its own, so that what it misses can be found with
+    /// [`gone`](Self::gone) later.
+    fn clear_with(
+        &self,
+        dbh: &mut SqliteConnection,
+        then: &mut dyn FnMut(&mut SqliteConnection) -> diesel::QueryResult<()>,
+    ) -> RepoResult<Cleared> {
+        let cleared = self.clear()?;
+        dbh.immediate_transaction(|conn| then(conn))?;
+
+        Ok(cleared)
+    }
+
+    /// Deletes the house for good with its rooms, devices and their state
+    /// history, in the trash or not.
+    fn discard_house(&self, id: i32) -> RepoResult<usize>;
+
+    /// Applies the changes in order, all of them or none, returning the id of
+    /// the row each one added, changed or deleted.
+    fn apply(&self, changes: &[LayoutChange]) -> RepoResult<Vec<i32>>;
+
+    /// Like [`apply`](Self::apply), then runs `then` with the ids on `dbh`.
+    ///
+    /// Backends keeping the layout in that SQLite database run both in one
+    /// transaction. The others give `then` a transaction of its own once the
+    /// changes are in and, when it fails, discard the houses the changes added.
+    fn apply_with(
+        &self,
+        changes: &[LayoutChange],
+        dbh: &mut SqliteConnection,
+        then: &mut dyn FnMut(&mut SqliteConnection, &[i32]) -> diesel::QueryResult<()>,
+    ) -> RepoResult<Vec<i32>> {
+        let applied = self.apply(changes)?;
+
+        if let Err(e) = dbh.immediate_transaction(|conn| then(conn, &applied)) {
+            for (change, id) in changes.iter().zip(&applied) {
+                if let LayoutChange::AddHouse { .. } = change {
+                    self.discard_house(*id)?;
+                }
+            }
+            return Err(e.into());
+        }
+
+        Ok(applied)
+    }
+}
+
+/// The house of the device, if both still exist.
+pub fn house_of(repo: &dyn HomeRepository, device: i32) -> RepoResult<Option<i32>> {
+    let Some(dev) = repo.device(device)? else {
+        return Ok(None);
+    };
+
+    Ok(repo.room(dev.room)?.map(|r| r.house))
+}
+
+/// Trash entries from the `(id, name, [house,] [room,] deleted_at)` rows of
+/// deleted houses, rooms and devices, oldest first.
+pub(crate) fn trashed(
+    houses: Vec<(i32, String, Option<i64>)>,
+    rooms: Vec<(i32, String, i32, Option<i64>)>,
+    devices: Vec<(i32, String, i32, i32, Option<i64>)>,
+) -> Vec<Trashed> {
+    let entry = |kind, id, name, house, room, deleted_at: Option<i64>| Trashed {
+        kind,
+        id,
+        name,
+        house,
+        room,
+        deleted_at: deleted_at.unwrap_or_default(),
+    };
+
+    let mut res = houses
+        .into_iter()
+        .map(|(id, name, at)| entry(Kind::House, id, name, id, None, at))
+        .chain(
+            rooms
+                .into_iter()
+                .map(|(id, name, house, at)| entry(Kind::Room, id, name, house, None, at)),
+        )
+        .chain(devices.into_iter().map(|(id, name, house, room, at)| {
+            entry(Kind::Device, id, name, house, Some(room), at)
+        }))
+        .collect::<Vec<_>>();
+    res.sort_by_key(|t| (t.deleted_at, t.id));
+
+    res
+}
+
+/// The layout in the tables of the database the pool connects to.
+pub struct SqliteRepository {
+    pool: DbPool,
+}
+
+impl SqliteRepository {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    fn conn(&self) -> RepoResult<PooledConnection<ConnectionManager<SqliteConnection>>> {
+        Ok(self.pool.get()?)
+    }
+}
+
+fn log_state(conn: &mut SqliteConnection, dev: &Device) -> diesel::QueryResult<usize> {
+    diesel::insert_into(device_state_log::table)
+        .values(&NewDeviceStateLog {
+            device: dev.id,
+            state: dev.state,
+            power: dev.power,
+            changed_at: unix_now(),
+        })
+        .execute(conn)
+        .counted()
+}
+
+/// Deletes the devices and their state history.
+fn drop_devices(conn: &mut SqliteConnection, ids: &[i32]) -> diesel::QueryResult<usize> {
+    diesel::delete(device_state_log::table.filter(device_state_log::device.eq_any(ids)))
+        .execute(conn)
+        .counted()?;
+    diesel::delete(device::table.filter(device::id.eq_any(ids)))
+        .execute(conn)
+        .counted()
+}
+
+// The statements behind single writes and batches alike; callers hold the transaction.
+
+fn insert_house(conn: &mut SqliteConnection, name: &str) -> diesel::QueryResult<House> {
+    diesel::insert_into(house::table)
+        .values(NewHouse {
+            name: name.to_owned(),
+        })
+        .execute(conn)
+        .counted()?;
+
+    house::table
+        .filter(house::name.eq(name))
+        .filter(house::deleted_at.is_null())
+        .select(House::as_select())
+        .first(conn)
+        .counted_row()
+}
+
+fn update_house_name(
+    conn: &mut SqliteConnection,
+    id: i32,
+    name: &str,
+) -> diesel::QueryResult<Option<House>> {
+    diesel::update(house::table.filter(house::id.eq(id)))
+        .filter(house::deleted_at.is_null())
+        .set(house::name.eq(name))
+        .execute(conn)
+        .counted()?;
+
+    house::table
+        .filter(house::id.eq(id))
+        .filter(house::deleted_at.is_null())
+        .select(House::as_select())
+        .first(conn)
+        .optional()
+        .counted()
+}
+
+fn insert_room(
+    conn: &mut SqliteConnection,
+    house_id: i32,
+    name: &str,
+) -> diesel::QueryResult<Room> {
+    diesel::insert_into(room::table)
+        .values(&NewRoom {
+            house: house_id,
+            name: name.to_owned(),
+        })
+        .execute(conn)
+        .counted()?;
+
+    room::table
+        .filter(room::house.eq(house_id))
+        .filter(room::name.eq(name))
+        .filter(room::deleted_at.is_null())
+        .select(Room::as_select())
+        .first(conn)
+        .counted_row()
+}
+
+fn update_room_name(
+    conn: &mut SqliteConnection,
+    id: i32,
+    name: &str,
+) -> diesel::QueryResult<Option<Room>> {
+    diesel::update(room::table.filter(room::id.eq(id)))
+        .filter(room::deleted_at.is_null())
+        .set(room::name.eq(name))
+        .execute(conn)
+        .counted()?;
+
+    room::table
+        .filter(room::id.eq(id))
+        .filter(room::deleted_at.is_null())
+        .select(Room::as_select())
+        .first(conn)
+        .optional()
+        .counted()
+}
+
+fn trash_house(conn: &mut SqliteConnection, id: i32, del: &Deletion) -> diesel::QueryResult<usize> {
+    let rooms = room::table
+        .filter(room::house.eq(id))
+        .filter(room::deleted_at.is_null())
+        .select(room::id)
+        .load::<i32>(conn)
+        .counted()?;
+    trash_rooms(conn, &rooms, del)?;
+
+    diesel::update(house::table.filter(house::id.eq(id)))
+        .filter(house::deleted_at.is_null())
+        .set((
+            house::deleted_at.eq(del.at),
+            house::deleted_with.eq(&del.with),
+        ))
+        .execute(conn)
+        .counted()
+}
+
+fn trash_rooms(
+    conn: &mut SqliteConnection,
+    ids: &[i32],
+    del: &Deletion,
+) -> diesel::QueryResult<usize> {
+    diesel::update(device::table.filter(device::room.eq_any(ids)))
+        .filter(device::deleted_at.is_null())
+        .set((
+            device::deleted_at.eq(del.at),
+            device::deleted_with.eq(&del.with),
+        ))
+        .execute(conn)
+        .counted()?;
+
+    diesel::update(room::table.filter(room::id.eq_any(ids)))
+        .filter(room::deleted_at.is_null())
+        .set((
+            room::deleted_at.eq(del.at),
+            room::deleted_with.eq(&del.with),
+        ))
+        .execute(conn)
+        .counted()
+}
+
+fn trash_device(
+    conn: &mut SqliteConnection,
+    id: i32,
+    del: &Deletion,
+) -> diesel::QueryResult<usize> {
+    diesel::update(device::table.filter(device::id.eq(id)))
+        .filter(device::deleted_at.is_null())
+        .set((
+            device::deleted_at.eq(del.at),
+            device::deleted_with.eq(&del.with),
+        ))
+        .execute(conn)
+        .counted()
+}
+
+/// Takes the rooms out of the trash with their devices deleted along with `with`.
+fn untrash_rooms(conn: &mut SqliteConnection, ids: &[i32], with: &str) -> diesel::QueryResult<()> {
+    diesel::update(room::table.filter(room::id.eq_any(ids)))
+        .filter(room::deleted_with.eq(with))
+        .set((
+            room::deleted_at.eq(None::<i64>),
+            room::deleted_with.eq(None::<String>),
+        ))
+        .execute(conn)
+        .counted()?;
+    diesel::update(device::table.filter(device::room.eq_any(ids)))
+        .filter(device::deleted_with.eq(with))
+        .set((
+            device::deleted_at.eq(None::<i64>),
+            device::deleted_with.eq(None::<String>),
+        ))
+        .execute(conn)
+        .counted()?;
+
+    Ok(())
+}
+
+fn insert_device(conn: &mut SqliteConnection, new: &NewDevice) -> diesel::QueryResult<Device> {
+    diesel::insert_into(device::table)
+        .values(new)
+        .execute(conn)
+        .counted()?;
+
+    let res = device::table
+        .filter(device::room.eq(new.room))
+        .filter(device::name.eq(&new.name))
+        .filter(device::deleted_at.is_null())
+        .select(Device::as_select())
+        .first(conn)
+        .counted_row()?;
+
+    log_state(conn, &res)?;
+
+    Ok(res)
+}
+
+fn change_device(
+    conn: &mut SqliteConnection,
+    id: i32,
+    change: &DeviceChange,
+) -> diesel::QueryResult<Option<Device>> {
+    let Some(before) = device::table
+        .filter(device::id.eq(id))
+        .filter(device::deleted_at.is_null())
+        .select(Device::as_select())
+        .first(conn)
+        .optional()
+        .counted()?
+    else {
+        return Ok(None);
+    };
+
+    diesel::update(device::table.filter(device::id.eq(id)))
+        .set((
+            device::name.eq(&change.name),
+            device::state.eq(change.state),
+            device::device_type.eq(&change.device_type),
+            device::power.eq(change.power),
+        ))
+        .execute(conn)
+        .counted()?;
+
+    let res = device::table
+        .filter(device::id.eq(id))
+        .select(Device::as_select())
+        .first(conn)
+        .counted_row()?;
+
+    if before.state != res.state || before.power != res.power {
+        log_state(conn, &res)?;
+    }
+
+    Ok(Some(res))
+}
+
+impl HomeRepository for SqliteRepository {
+    fn ping(&self) -> RepoResult<()> {
+        diesel::sql_query("SELECT 1")
+            .execute(&mut self.conn()?)
+            .counted()?;
+
+        Ok(())
+    }
+
+    fn houses(&self) -> RepoResult<Vec<House>> {
+        Ok(house::table
+            .filter(house::deleted_at.is_null())
+            .order(house::id)
+            .select(House::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn houses_in(&self, ids: &[i32]) -> RepoResult<Vec<House>> {
+        Ok(house::table
+            .filter(house::id.eq_any(ids))
+            .filter(house::deleted_at.is_null())
+            .order(house::id)
+            .select(House::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn house(&self, id: i32) -> RepoResult<Option<House>> {
+        Ok(house::table
+            .filter(house::id.eq(id))
+            .filter(house::deleted_at.is_null())
+            .select(House::as_select())
+            .first(&mut self.conn()?)
+            .optional()
+            .counted()?)
+    }
+
+    fn add_house(&self, name: &str) -> RepoResult<House> {
+        Ok(self
+            .conn()?
+            .immediate_transaction(|conn| insert_house(conn, name))?)
+    }
+
+    fn rename_house(&self, id: i32, name: &str) -> RepoResult<Option<House>> {
+        Ok(update_house_name(&mut *self.conn()?, id, name)?)
+    }
+
+    fn delete_house(&self, id: i32) -> RepoResult<usize> {
+        Ok(self
+            .conn()?
+            .immediate_transaction(|conn| trash_house(conn, id, &Deletion::of("house", id)))?)
+    }
+
+    fn rooms(&self, house_id: i32) -> RepoResult<Vec<Room>> {
+        Ok(room::table
+            .filter(room::house.eq(house_id))
+            .filter(room::deleted_at.is_null())
+            .order(room::id)
+            .select(Room::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn room(&self, id: i32) -> RepoResult<Option<Room>> {
+        Ok(room::table
+            .filter(room::id.eq(id))
+            .filter(room::deleted_at.is_null())
+            .select(Room::as_select())
+            .first(&mut self.conn()?)
+            .optional()
+            .counted()?)
+    }
+
+    fn add_room(&self, house_id: i32, name: &str) -> RepoResult<Room> {
+        Ok(self
+            .conn()?
+            .immediate_transaction(|conn| insert_room(conn, house_id, name))?)
+    }
+
+    fn rename_room(&self, id: i32, name: &str) -> RepoResult<Option<Room>> {
+        Ok(update_room_name(&mut *self.conn()?, id, name)?)
+    }
+
+    fn delete_room(&self, id: i32) -> RepoResult<usize> {
+        Ok(self
+            .conn()?
+            .immediate_transaction(|conn| trash_rooms(conn, &[id], &Deletion::of("room", id)))?)
+    }
+
+    fn devices(&self, room_id: i32) -> RepoResult<Vec<Device>> {
+        Ok(device::table
+            .filter(device::room.eq(room_id))
+            .filter(device::deleted_at.is_null())
+            .order(device::id)
+            .select(Device::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn house_devices(&self, house_id: i32) -> RepoResult<Vec<Device>> {
+        Ok(device::table
+            .inner_join(room::table)
+            .filter(room::house.eq(house_id))
+            .filter(device::deleted_at.is_null())
+            .order(device::id)
+            .select(Device::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn device(&self, id: i32) -> RepoResult<Option<Device>> {
+        Ok(device::table
+            .filter(device::id.eq(id))
+            .filter(device::deleted_at.is_null())
+            .select(Device::as_select())
+            .first(&mut self.conn()?)
+            .optional()
+            .counted()?)
+    }
+
+    fn add_device(&self, new: NewDevice) -> RepoResult<Device> {
+        Ok(self
+            .conn()?
+            .immediate_transaction(|conn| insert_device(conn, &new))?)
+    }
+
+    fn update_device(&self, id: i32, change: DeviceChange) -> RepoResult<Option<Device>> {
+        Ok(self
+            .conn()?
+            .immediate_transaction(|conn| change_device(conn, id, &change))?)
+    }
+
+    fn record_reading(&self, id: i32, value: f64, taken_at: i64) -> RepoResult<()> {
+        diesel::update(device::table)
+            .filter(device::id.eq(id))
+            .filter(device::deleted_at.is_null())
+            .filter(
+                device::last_reading_at
+                    .is_null()
+                    .or(device::last_reading_at.le(taken_at)),
+            )
+            .set((
+                device::last_reading.eq(value),
+                device::last_reading_at.eq(taken_at),
+            ))
+            .execute(&mut self.conn()?)
+            .counted()?;
+
+        Ok(())
+    }
+
+    fn delete_device(&self, id: i32) -> RepoResult<usize> {
+        Ok(self
+            .conn()?
+            .immediate_transaction(|conn| trash_device(conn, id, &Deletion::of("device", id)))?)
+    }
+
+    fn trash(&self) -> RepoResult<Vec<Trashed>> {
+        let mut conn = self.conn()?;
+
+        let houses = house::table
+            .filter(house::deleted_at.is_not_null())
+            .select((house::id, house::name, house::deleted_at))
+            .load::<(i32, String, Option<i64>)>(&mut conn)
+            .counted()?;
+        let rooms = room::table
+            .filter(room::deleted_at.is_not_null())
+            .select((room::id, room::name, room::house, room::deleted_at))
+            .load::<(i32, String, i32, Option<i64>)>(&mut conn)
+            .counted()?;
+        let devices = device::table
+            .inner_join(room::table)
+            .filter(device::deleted_at.is_not_null())
+            .select((
+                device::id,
+                device::name,
+                room::house,
+                device::room,
+                device::deleted_at,
+            ))
+            .load::<(i32, String, i32, i32, Option<i64>)>(&mut conn)
+            .counted()?;
+
+        Ok(trashed(houses, rooms, devices))
+    }
+
+    fn restore_house(&self, id: i32) -> RepoResult<Option<House>> {
+        self.conn()?.immediate_transaction(|conn| {
+            let Some(with) = house::table
+                .filter(house::id.eq(id))
+                .select(house::deleted_with)
+                .first::<Option<String>>(conn)
+                .optional()
+                .counted()?
+                .flatten()
+            else {
+                return Ok(None);
+            };
+
+            diesel::update(house::table.filter(house::id.eq(id)))
+                .set((
+                    house::deleted_at.eq(None::<i64>),
+                    house::deleted_with.eq(None::<String>),
+                ))
+                .execute(conn)
+                .counted()?;
+            let rooms = room::table
+                .filter(room::house.eq(id))
+                .filter(room::deleted_with.eq(&with))
+                .select(room::id)
+                .load::<i32>(conn)
+                .counted()?;
+            untrash_rooms(conn, &rooms, &with)?;
+
+            Ok(Some(
+                house::table
+                    .filter(house::id.eq(id))
+                    .select(House::as_select())
+                    .first(conn)
+                    .counted_row()?,
+            ))
+        })
+    }
+
+    fn restore_room(&self, id: i32) -> RepoResult<Option<Room>> {
+        self.conn()?.immediate_transaction(|conn| {
+            let Some((house_id, Some(with))) = room::table
+                .filter(room::id.eq(id))
+                .select((room::house, room::deleted_with))
+                .first::<(i32, Option<String>)>(conn)
+                .optional()
+                .counted()?
+            else {
+                return Ok(None);
+            };
+
+            let house_live = house::table
+                .filter(house::id.eq(house_id))
+                .filter(house::deleted_at.is_null())
+                .count()
+                .get_result::<i64>(conn)
+                .counted_row()?;
+            if house_live == 0 {
+                return Err(missing("house", house_id));
+            }
+
+            untrash_rooms(conn, &[id], &with)?;
+
+            Ok(Some(
+                room::table
+                    .filter(room::id.eq(id))
+                    .select(Room::as_select())
+                    .first(conn)
+                    .counted_row()?,
+            ))
+        })
+    }
+
+    fn restore_device(&self, id: i32) -> RepoResult<Option<Device>> {
+        self.conn()?.immediate_transaction(|conn| {
+            let Some((room_id, Some(_))) = device::table
+                .filter(device::id.eq(id))
+                .select((device::room, device::deleted_at))
+                .first::<(i32, Option<i64>)>(conn)
+                .optional()
+                .counted()?
+            else {
+                return Ok(None);
+            };
+
+            let room_live = room::table
+                .filter(room::id.eq(room_id))
+                .filter(room::deleted_at.is_null())
+                .count()
+                .get_result::<i64>(conn)
+                .counted_row()?;
+            if room_live == 0 {
+                return Err(missing("room", room_id));
+            }
+
+            diesel::update(device::table.filter(device::id.eq(id)))
+                .set((
+                    device::deleted_at.eq(None::<i64>),
+                    device::deleted_with.eq(None::<String>),
+                ))
+                .execute(conn)
+                .counted()?;
+
+            Ok(Some(
+                device::table
+                    .filter(device::id.eq(id))
+                    .select(Device::as_select())
+                    .first(conn)
+                    .counted_row()?,
+            ))
+        })
+    }
+
+    fn purge(&self, before: i64) -> RepoResult<Purged> {
+        Ok(self.conn()?.immediate_transaction(|conn| {
+            let mut purged = expired(conn, before)?;
+            drop_expired(conn, &mut purged)?;
+
+            Ok::<_, diesel::result::Error>(purged)
+        })?)
+    }
+
+    fn gone(&self, kind: Kind, ids: &[i32]) -> RepoResult<Vec<i32>> {
+        let mut conn = self.conn()?;
+
+        let known = match kind {
+            Kind::House => house::table
+                .filter(house::id.eq_any(ids))
+                .select(house::id)
+                .load::<i32>(&mut conn),
+            Kind::Room => room::table
+                .filter(room::id.eq_any(ids))
+                .select(room::id)
+                .load(&mut conn),
+            Kind::Device => device::table
+                .filter(device::id.eq_any(ids))
+                .select(device::id)
+                .load(&mut conn),
+        }
+        .counted()?;
+
+        Ok(ids
+            .iter()
+            .copied()
+            .filter(|id| !known.contains(id))
+            .collect())
+    }
+
+    fn purge_with(
+        &self,
+        before: i64,
+        _dbh: &mut SqliteConnection,
+        then: &mut dyn FnMut(&mut SqliteConnection, &Purged) -> diesel::QueryResult<()>,
+    ) -> RepoResult<Cleared> {
+        // What refers to the rows goes first, for the foreign keys.
+        Ok(self.conn()?.immediate_transaction(|conn| {
+            let mut purged = expired(conn, before)?;
+            then(conn, &purged)?;
+            drop_expired(conn, &mut purged)?;
+
+            Ok::<_, diesel::result::Error>(purged.cleared)
+        })?)
+    }
+
+    fn state_history(&self, devices: &[i32], until: i64) -> RepoResult<Vec<DeviceStateLog>> {
+        Ok(device_state_log::table
+            .filter(device_state_log::device.eq_any(devices))
+            .filter(device_state_log::changed_at.lt(until))
+            .order((device_state_log::changed_at, device_state_log::id))
+            .select(DeviceStateLog::as_select())
+            .load(&mut self.conn()?)
+            .counted()?)
+    }
+
+    fn counts(&self) -> RepoResult<LayoutCounts> {
+        let mut conn = self.conn()?;
+
+        Ok(LayoutCounts {
+            houses: house::table
+                .filter(house::deleted_at.is_null())
+                .count()
+                .get_result(&mut conn)
+                .counted_row()?,
+            rooms: room::table
+                .filter(room::deleted_at.is_null())
+                .count()
+                .get_result(&mut conn)
+                .counted_row()?,
+            devices: device::table
+                .filter(device::deleted_at.is_null())
+                .count()
+                .get_result(&mut conn)
+                .counted_row()?,
+            devices_on: device::table
+                .filter(device::deleted_at.is_null())
+                .filter(device::state.eq(true))
+                .group_by(device::device_type)
+                .select((device::device_type, count_star()))
+                .load(&mut conn)
+                .counted()?,
+        })
+    }
+
+    fn clear(&self) -> RepoResult<Cleared> {
+        Ok(self.conn()?.immediate_transaction(clear_layout)?)
+    }
+
+    fn clear_with(
+        &self,
+        _dbh: &mut SqliteConnection,
+        then: &mut dyn FnMut(&mut SqliteConnection) -> diesel::QueryResult<()>,
+    ) -> RepoResult<Cleared> {
+        // What refers to the rows goes first, for the foreign keys.
+        Ok(self.conn()?.immediate_transaction(|conn| {
+            then(conn)?;
+            clear_layout(conn)
+        })?)
+    }
+
+    fn discard_house(&self, id: i32) -> RepoResult<usize> {
+        Ok(self.conn()?.immediate_transaction(|conn| {
+            let rooms = room::table
+                .filter(room::house.eq(id))
+                .select(room::id)
+                .load::<i32>(conn)
+                .counted()?;
+            let devices = device::table
+                .filter(device::room.eq_any(&rooms))
+                .select(device::id)
+                .load::<i32>(conn)
+                .counted()?;
+
+            drop_devices(conn, &devices)?;
+            diesel::delete(room::table.filter(room::id.eq_any(&rooms)))
+                .execute(conn)
+                .counted()?;
+            diesel::delete(house::table.filter(house::id.eq(id)))
+                .execute(conn)
+                .counted()
+        })?)
+    }
+
+    fn apply(&self, changes: &[LayoutChange]) -> RepoResult<Vec<i32>> {
+        self.conn()?
+            .immediate_transaction(|conn| apply_changes(conn, changes))
+    }
+
+    fn apply_with(
+        &self,
+        changes: &[LayoutChange],
+        _dbh: &mut SqliteConnection,
+        then: &mut dyn FnMut(&mut SqliteConnection, &[i32]) -> diesel::QueryResult<()>,
+    ) -> RepoResult<Vec<i32>> {
+        self.conn()?.immediate_transaction(|conn| {
+            let applied = apply_changes(conn, changes)?;
+            then(conn, &applied)?;
+
+            Ok(applied)
+        })
+    }
+}
+
+fn apply_changes(conn: &mut SqliteConnection, changes: &[LayoutChange]) -> RepoResult<Vec<i32>> {
+    let mut applied = Vec::with_capacity(changes.len());
+
+    for change in changes {
+        let id = match change {
+            LayoutChange::AddHouse { name } => insert_house(conn, name)?.id,
+            LayoutChange::RenameHouse { id, name } => {
+                update_house_name(conn, *id, name)?
+                    .ok_or_else(|| missing("house", *id))?
+                    .id
+            }
+            LayoutChange::AddRoom { house, name } => {
+                insert_room(conn, house.resolve(&applied), name)?.id
+            }
+            LayoutChange::RenameRoom { id, name } => {
+                update_room_name(conn, *id, name)?
+                    .ok_or_else(|| missing("room", *id))?
+                    .id
+            }
+            LayoutChange::DeleteRoom { id } => {
+                match trash_rooms(conn, &[*id], &Deletion::of("room", *id))? {
+                    0 => return Err(missing("room", *id)),
+                    _ => *id,
+                }
+            }
+            LayoutChange::AddDevice {
+                room,
+                name,
+                device_type,
+                power,
+            } => {
+                let new = NewDevice {
+                    name: name.clone(),
+                    room: room.resolve(&applied),
+                    device_type: device_type.clone(),
+                    state: false,
+                    power: *power,
+                };
+                insert_device(conn, &new)?.id
+            }
+            LayoutChange::UpdateDevice { id, change } => {
+                change_device(conn, *id, change)?
+                    .ok_or_else(|| missing("device", *id))?
+                    .id
+            }
+            LayoutChange::DeleteDevice { id } => {
+                match trash_device(conn, *id, &Deletion::of("device", *id))? {
+                    0 => return Err(missing("device", *id)),
+                    _ => *id,
+                }
+            }
+        };
+
+        applied.push(id);
+    }
+
+    Ok(applied)
+}
+
+/// Deletes every house, room and device, the trash included.
+fn clear_layout(conn: &mut SqliteConnection) -> diesel::QueryResult<Cleared> {
+    Ok(Cleared {
+        state_changes: diesel::delete(device_state_log::table)
+            .execute(conn)
+            .counted()?,
+        devices: diesel::delete(device::table).execute(conn).counted()?,
+        rooms: diesel::delete(room::table).execute(conn).counted()?,
+        houses: diesel::delete(house::table).execute(conn).counted()?,
+    })
+}
+
+/// The rows deleted at or before `before`, with everything in them.
+fn expired(conn: &mut SqliteConnection, before: i64) -> diesel::QueryResult<Purged> {
+    let houses = house::table
+        .filter(house::deleted_at.le(before))
+        .select(house::id)
+        .load::<i32>(conn)
+        .counted()?;
+    let rooms = room::table
+        .filter(room::deleted_at.le(before).or(room::house.eq_any(&houses)))
+        .select(room::id)
+        .load::<i32>(conn)
+        .counted()?;
+    let devices = device::table
+        .filter(
+            device::deleted_at
+                .le(before)
+                .or(device::room.eq_any(&rooms)),
+        )
+        .select(device::id)
+        .load::<i32>(conn)
+        .counted()?;
+
+    Ok(Purged {
+        houses,
+        rooms,
+        devices,
+        cleared: Cleared::default(),
+    })
+}
+
+/// Deletes the rows for good, filling in what was cleared.
+fn drop_expired(conn: &mut SqliteConnection, purged: &mut Purged) -> diesel::QueryResult<()> {
+    purged.cleared = Cleared {
+        state_changes: diesel::delete(
+            device_state_log::table.filter(device_state_log::device.eq_any(&purged.devices)),
+        )
+        .execute(conn)
+        .counted()?,
+        devices: drop_devices(conn, &purged.devices)?,
+        rooms: diesel::delete(room::table.filter(room::id.eq_any(&purged.rooms)))
+            .execute(conn)
+            .counted()?,
+        houses: diesel::delete(house::table.filter(house::id.eq_any(&purged.houses)))
+            .execute(conn)
+            .counted()?,
+    };
+
+    Ok(())
+}
+
+/// The layout in process memory, lost on restart; for tests and demos.
+#[derive(Default)]
+pub struct MemoryRepository {
+    layout: Mutex<Layout>,
+}
+
+#[derive(Default, Clone)]
+struct Layout {
+    houses: BTreeMap<i32, House>,
+    rooms: BTreeMap<i32, Room>,
+    devices: BTreeMap<i32, Device>,
+    state_log: Vec<DeviceStateLog>,
+    trash: Trash,
+    last_id: i32,
+}
+
+/// Deleted rows, out of the maps of [`Layout`], with the delete that took them.
+#[derive(Default, Clone)]
+struct Trash {
+    houses: BTreeMap<i32, (House, Deletion)>,
+    rooms: BTreeMap<i32, (Room, Deletion)>,
+    devices: BTreeMap<i32, (Device, Deletion)>,
+}
+
+// Conflicts mirror the unique constraints of the SQLite schema.
+fn conflict(what: &str) -> RepoError {
+    RepoError::Conflict(format!("{what} name already taken"))
+}
+
+impl Layout {
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn log_state(&mut self, dev: &Device) {
+        let id = self.next_id();
+
+        self.state_log.push(DeviceStateLog {
+            id,
+            device: dev.id,
+            state: dev.state,
+            power: dev.power,
+            changed_at: unix_now(),
+        });
+    }
+
+    fn trash_devices(&mut self, ids: &[i32], del: &Deletion) -> usize {
+        ids.iter()
+            .filter_map(|id| self.devices.remove(id))
+            .map(|d| self.trash.devices.insert(d.id, (d, del.clone())))
+            .count()
+    }
+
+    fn trash_rooms(&mut self, ids: &[i32], del: &Deletion) -> usize {
+        let devices = self
+            .devices
+            .values()
+            .filter(|d| ids.contains(&d.room))
+            .map(|d| d.id)
+            .collect::<Vec<_>>();
+        self.trash_devices(&devices, del);
+
+        ids.iter()
+            .filter_map(|id| self.rooms.remove(id))
+            .map(|r| self.trash.rooms.insert(r.id, (r, del.clone())))
+            .count()
+    }
+
+    fn trash_house(&mut self, id: i32, del: &Deletion) -> usize {
+        let rooms = self
+            .rooms
+            .values()
+            .filter(|r| r.house == id)
+            .map(|r| r.id)
+            .collect::<Vec<_>>();
+        self.trash_rooms(&rooms, del);
+
+        self.houses
+            .remove(&id)
+            .map(|h| self.trash.houses.insert(id, (h, del.clone())))
+            .map_or(0, |_| 1)
+    }
+
+    /// Takes the rooms back with the devices deleted along with `with`, failing
+    /// on the first name taken in the meantime.
+    fn untrash_rooms(&mut self, ids: &[i32], with: &str) -> RepoResult<()> {
+        for id in ids {
+            let Some((room, _)) = self.trash.rooms.remove(id) else {
+                continue;
+            };
+            if self
+                .rooms
+                .values()
+                .any(|r| r.house == room.house && r.name == room.name)
+            {
+                return Err(conflict("room"));
+            }
+            self.rooms.insert(room.id, room);
+
+            let devices = self
+                .trash
+                .devices
+                .values()
+                .filter(|(d, del)| d.room == *id && del.with == with)
+                .map(|(d, _)| d.id)
+                .collect::<Vec<_>>();
+            for device in devices {
+                self.untrash_device(device)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn untrash_device(&mut self, id: i32) -> RepoResult<Option<Device>> {
+        let Some((device, _)) = self.trash.devices.remove(&id) else {
+            return Ok(None);
+        };
+        if self
+            .devices
+            .values()
+            .any(|d| d.room == device.room && d.name == device.name)
+        {
+            return Err(conflict("device"));
+        }
+        self.devices.insert(id, device.clone());
+
+        Ok(Some(device))
+    }
+
+    fn restore_house(&mut self, id: i32) -> RepoResult<Option<House>> {
+        let Some((house, del)) = self.trash.houses.remove(&id) else {
+            return Ok(None);
+        };
+        if self.houses.values().any(|h| h.name == house.name) {
+            return Err(conflict("house"));
+        }
+        self.houses.insert(id, house.clone());
+
+        let rooms = self
+            .trash
+            .rooms
+            .values()
+            .filter(|(r, deleted)| r.house == id && deleted.with == del.with)
+            .map(|(r, _)| r.id)
+            .collect::<Vec<_>>();
+        self.untrash_rooms(&rooms, &del.with)?;
+
+        Ok(Some(house))
+    }
+
+    fn restore_room(&mut self, id: i32) -> RepoResult<Option<Room>> {
+        let Some((room, del)) = self.trash.rooms.get(&id).cloned() else {
+            return Ok(None);
+        };
+        if !self.houses.contains_key(&room.house) {
+            return Err(missing("house", room.house));
+        }
+        self.untrash_rooms(&[id], &del.with)?;
+
+        Ok(Some(room))
+    }
+
+    fn restore_device(&mut self, id: i32) -> RepoResult<Option<Device>> {
+        let Some(room) = self.trash.devices.get(&id).map(|(d, _)| d.room) else {
+            return Ok(None);
+        };
+        if !self.rooms.contains_key(&room) {
+            return Err(missing("room", room));
+        }
+
+        self.untrash_device(id)
+    }
+
+    fn discard_house(&mut self, id: i32) -> usize {
+        let in_house = |r: &Room| r.house == id;
+        let rooms = self
+            .rooms
+            .values()
+            .chain(self.trash.rooms.values().map(|(r, _)| r))
+            .filter(|r| in_house(r))
+            .map(|r| r.id)
+            .collect::<Vec<_>>();
+        let devices = self
+            .devices
+            .values()
+            .chain(self.trash.devices.values().map(|(d, _)| d))
+            .filter(|d| rooms.contains(&d.room))
+            .map(|d| d.id)
+            .collect::<Vec<_>>();
+
+        for device in &devices {
+            self.devices.remove(device);
+            self.trash.devices.remove(device);
+        }
+        self.state_log.retain(|l| !devices.contains(&l.device));
+        for room in &rooms {
+            self.rooms.remove(room);
+            self.trash.rooms.remove(room);
+        }
+
+        let live = self.houses.remove(&id).is_some();
+        let trashed = self.trash.houses.remove(&id).is_some();
+        usize::from(live || trashed)
+    }
+
+    fn purge(&mut self, before: i64) -> Purged {
+        fn expired<T>(trash: &BTreeMap<i32, (T, Deletion)>, before: i64) -> Vec<i32> {
+            trash
+                .iter()
+                .filter(|(_, (_, del))| del.at <= before)
+                .map(|(id, _)| *id)
+                .collect()
+        }
+
+        let houses = expired(&self.trash.houses, before);
+        let rooms = expired(&self.trash.rooms, before);
+        let devices = expired(&self.trash.devices, before);
+        for id in &houses {
+            self.trash.houses.remove(id);
+        }
+        for id in &rooms {
+            self.trash.rooms.remove(id);
+        }
+        for id in &devices {
+            self.trash.devices.remove(id);
+        }
+        let state_log = self.state_log.len();
+        self.state_log.retain(|l| !devices.contains(&l.device));
+
+        Purged {
+            cleared: Cleared {
+                houses: houses.len(),
+                rooms: rooms.len(),
+                devices: devices.len(),
+                state_changes: state_log - self.state_log.len(),
+            },
+            houses,
+            rooms,
+            devices,
+        }
+    }
+
+    fn add_house(&mut self, name: &str) -> RepoResult<House> {
+        if self.houses.values().any(|h| h.name == name) {
+            return Err(conflict("house"));
+        }
+
+        let res = House {
+            id: self.next_id(),
+            name: name.to_owned(),
+        };
+        self.houses.insert(res.id, res.clone());
+
+        Ok(res)
+    }
+
+    fn rename_house(&mut self, id: i32, name: &str) -> RepoResult<Option<House>> {
+        if self.houses.values().any(|h| h.id != id && h.name == name) {
+            return Err(conflict("house"));
+        }
+
+        Ok(self.houses.get_mut(&id).map(|h| {
+            h.name = name.to_owned();
+            h.clone()
+        }))
+    }
+
+    fn add_room(&mut self, house_id: i32, name: &str) -> RepoResult<Room> {
+        if self
+            .rooms
+            .values()
+            .any(|r| r.house == house_id && r.name == name)
+        {
+            return Err(conflict("room"));
+        }
+
+        let res = Room {
+            id: self.next_id(),
+            house: house_id,
+            name: name.to_owned(),
+        };
+        self.rooms.insert(res.id, res.clone());
+
+        Ok(res)
+    }
+
+    fn rename_room(&mut self, id: i32, name: &str) -> RepoResult<Option<Room>> {
+        let Some(house_id) = self.rooms.get(&id).map(|r| r.house) else {
+            return Ok(None);
+        };
+
+        if self
+            .rooms
+            .values()
+            .any(|r| r.id != id && r.house == house_id && r.name == name)
+        {
+            return Err(conflict("room"));
+        }
+
+        Ok(self.rooms.get_mut(&id).map(|r| {
+            r.name = name.to_owned();
+            r.clone()
+        }))
+    }
+
+    fn add_device(&mut self, new: NewDevice) -> RepoResult<Device> {
+        if self
+            .devices
+            .values()
+            .any(|d| d.room == new.room && d.name == new.name)
+        {
+            return Err(conflict("device"));
+        }
+
+        let res = Device {
+            id: self.next_id(),
+            room: new.room,
+            name: new.name,
+            device_type: new.device_type,
+            state: new.state,
+            power: new.power,
+            last_reading: None,
+            last_reading_at: None,
+        };
+        self.devices.insert(res.id, res.clone());
+        self.log_state(&res);
+
+        Ok(res)
+    }
+
+    fn update_device(&mut self, id: i32, change: DeviceChange) -> RepoResult<Option<Device>> {
+        let Some(before) = self.devices.get(&id).cloned() else {
+            return Ok(None);
+        };
+
+        if self
+            .devices
+            .values()
+            .any(|d| d.id != id && d.room == before.room && d.name == change.name)
+        {
+            return Err(conflict("device"));
+        }
+
+        let res = Device {
+            name: change.name,
+            device_type: change.device_type,
+            state: change.state,
+            power: change.power,
+            ..before.clone()
+        };
+        self.devices.insert(id, res.clone());
+
+        if before.state != res.state || before.power != res.power {
+            self.log_state(&res);
+        }
+
+        Ok(Some(res))
+    }
+
+    fn apply(&mut self, change: &LayoutChange, applied: &[i32]) -> RepoResult<i32> {
+        Ok(match change {
+            LayoutChange::AddHouse { name } => self.add_house(name)?.id,
+            LayoutChange::RenameHouse { id, name } => {
+                self.rename_house(*id, name)?
+                    .ok_or_else(|| missing("house", *id))?
+                    .id
+            }
+            LayoutChange::AddRoom { house, name } => {
+                self.add_room(house.resolve(applied), name)?.id
+            }
+            LayoutChange::RenameRoom { id, name } => {
+                self.rename_room(*id, name)?
+                    .ok_or_else(|| missing("room", *id))?
+                    .id
+            }
+            LayoutChange::DeleteRoom { id } => {
+                match self.trash_rooms(&[*id], &Deletion::of("room", *id)) {
+                    0 => return Err(missing("room", *id)),
+                    _ => *id,
+                }
+            }
+            LayoutChange::AddDevice {
+                room,
+                name,
+                device_type,
+                power,
+            } => {
+                self.add_device(NewDevice {
+                    name: name.clone(),
+                    room: room.resolve(applied),
+                    device_type: device_type.clone(),
+                    state: false,
+                    power: *power,
+                })?
+                .id
+            }
+            LayoutChange::UpdateDevice { id, change } => {
+                self.update_device(*id, change.clone())?
+                    .ok_or_else(|| missing("device", *id))?
+                    .id
+            }
+            LayoutChange::DeleteDevice { id } => {
+                match self.trash_devices(&[*id], &Deletion::of("device", *id)) {
+                    0 => return Err(missing("device", *id)),
+                    _ => *id,
+                }
+            }
+        })
+    }
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs a restore on a copy of the layout, kept only if it succeeds.
+    fn restoring<T>(&self, restore: impl FnOnce(&mut Layout) -> RepoResult<T>) -> RepoResult<T> {
+        let mut layout = self.layout.lock()?;
+
+        let mut draft = layout.clone();
+        let res = restore(&mut draft)?;
+        *layout = draft;
+
+        Ok(res)
+    }
+}
+
+impl HomeRepository for MemoryRepository {
+    fn houses(&self) -> RepoResult<Vec<House>> {
+        Ok(self.layout.lock()?.houses.values().cloned().collect())
+    }
+
+    fn houses_in(&self, ids: &[i32]) -> RepoResult<Vec<House>> {
+        Ok(self
+            .layout
+            .lock()?
+            .houses
+            .values()
+            .filter(|h| ids.contains(&h.id))
+            .cloned()
+            .collect())
+    }
+
+    fn house(&self, id: i32) -> RepoResult<Option<House>> {
+        Ok(self.layout.lock()?.houses.get(&id).cloned())
+    }
+
+    fn add_house(&self, name: &str) -> RepoResult<House> {
+        self.layout.lock()?.add_house(name)
+    }
+
+    fn rename_house(&self, id: i32, name: &str) -> RepoResult<Option<House>> {
+        self.layout.lock()?.rename_house(id, name)
+    }
+
+    fn delete_house(&self, id: i32) -> RepoResult<usize> {
+        Ok(self
+            .layout
+            .lock()?
+            .trash_house(id, &Deletion::of("house", id)))
+    }
+
+    fn rooms(&self, house_id: i32) -> RepoResult<Vec<Room>> {
+        Ok(self
+            .layout
+            .lock()?
+            .rooms
+            .values()
+            .filter(|r| r.house == house_id)
+            .cloned()
+            .collect())
+    }
+
+    fn room(&self, id: i32) -> RepoResult<Option<Room>> {
+        Ok(self.layout.lock()?.rooms.get(&id).cloned())
+    }
+
+    fn add_room(&self, house_id: i32, name: &str) -> RepoResult<Room> {
+        self.layout.lock()?.add_room(house_id, name)
+    }
+
+    fn rename_room(&self, id: i32, name: &str) -> RepoResult<Option<Room>> {
+        self.layout.lock()?.rename_room(id, name)
+    }
+
+    fn delete_room(&self, id: i32) -> RepoResult<usize> {
+        Ok(self
+            .layout
+            .lock()?
+            .trash_rooms(&[id], &Deletion::of("room", id)))
+    }
+
+    fn devices(&self, room_id: i32) -> RepoResult<Vec<Device>> {
+        Ok(self
+            .layout
+            .lock()?
+            .devices
+            .values()
+            .filter(|d| d.room == room_id)
+            .cloned()
+            .collect())
+    }
+
+    fn house_devices(&self, house_id: i32) -> RepoResult<Vec<Device>> {
+        let layout = self.layout.lock()?;
+
+        Ok(layout
+            .devices
+            .values()
+            .filter(|d| {
+                layout
+                    .rooms
+                    .get(&d.room)
+                    .is_some_and(|r| r.house == house_id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn device(&self, id: i32) -> RepoResult<Option<Device>> {
+        Ok(self.layout.lock()?.devices.get(&id).cloned())
+    }
+
+    fn add_device(&self, new: NewDevice) -> RepoResult<Device> {
+        self.layout.lock()?.add_device(new)
+    }
+
+    fn update_device(&self, id: i32, change: DeviceChange) -> RepoResult<Option<Device>> {
+        self.layout.lock()?.update_device(id, change)
+    }
+
+    fn record_reading(&self, id: i32, value: f64, taken_at: i64) -> RepoResult<()> {
+        if let Some(dev) = self.layout.lock()?.devices.get_mut(&id)
+            && dev.last_reading_at.is_none_or(|last| last <= taken_at)
+        {
+            dev.last_reading = Some(value);
+            dev.last_reading_at = Some(taken_at);
+        }
+
+        Ok(())
+    }
+
+    fn delete_device(&self, id: i32) -> RepoResult<usize> {
+        Ok(self
+            .layout
+            .lock()?
+            .trash_devices(&[id], &Deletion::of("device", id)))
+    }
+
+    fn trash(&self) -> RepoResult<Vec<Trashed>> {
+        let layout = self.layout.lock()?;
+        let trash = &layout.trash;
+
+        // Trashed devices may sit in a room trashed along with them.
+        let house_of = |room: i32| {
+            layout
+                .rooms
+                .get(&room)
+                .or_else(|| trash.rooms.get(&room).map(|(r, _)| r))
+                .map_or(0, |r| r.house)
+        };
+
+        Ok(trashed(
+            trash
+                .houses
+                .values()
+                .map(|(h, del)| (h.id, h.name.clone(), Some(del.at)))
+                .collect(),
+            trash
+                .rooms
+                .values()
+                .map(|(r, del)| (r.id, r.name.clone(), r.house, Some(del.at)))
+                .collect(),
+            trash
+                .devices
+                .values()
+                .map(|(d, del)| (d.id, d.name.clone(), house_of(d.room), d.room, Some(del.at)))
+                .collect(),
+        ))
+    }
+
+    fn restore_house(&self, id: i32) -> RepoResult<Option<House>> {
+        self.restoring(|layout| layout.restore_house(id))
+    }
+
+    fn restore_room(&self, id: i32) -> RepoResult<Option<Room>> {
+        self.restoring(|layout| layout.restore_room(id))
+    }
+
+    fn restore_device(&self, id: i32) -> RepoResult<Option<Device>> {
+        self.restoring(|layout| layout.restore_device(id))
+    }
+
+    fn purge(&self, before: i64) -> RepoResult<Purged> {
+        Ok(self.layout.lock()?.purge(before))
+    }
+
+    fn gone(&self, kind: Kind, ids: &[i32]) -> RepoResult<Vec<i32>> {
+        let layout = self.layout.lock()?;
+        let known = |id: &i32| match kind {
+            Kind::House => layout.houses.contains_key(id) || layout.trash.houses.contains_key(id),
+            Kind::Room => layout.rooms.contains_key(id) || layout.trash.rooms.contains_key(id),
+            Kind::Device => {
+                layout.devices.contains_key(id) || layout.trash.devices.contains_key(id)
+            }
+        };
+
+        Ok(ids.iter().copied().filter(|id| !known(id)).collect())
+    }
+
+    fn discard_house(&self, id: i32) -> RepoResult<usize> {
+        Ok(self.layout.lock()?.discard_house(id))
+    }
+
+    fn state_history(&self, devices: &[i32], until: i64) -> RepoResult<Vec<DeviceStateLog>> {
+        let mut res = self
+            .layout
+            .lock()?
+            .state_log
+            .iter()
+            .filter(|l| devices.contains(&l.device) && l.changed_at < until)
+            .cloned()
+            .collect::<Vec<_>>();
+        res.sort_by_key(|l| (l.changed_at, l.id));
+
+        Ok(res)
+    }
+
+    fn counts(&self) -> RepoResult<LayoutCounts> {
+        let layout = self.layout.lock()?;
+
+        let mut devices_on = BTreeMap::<String, i64>::new();
+        for dev in layout.devices.values().filter(|d| d.state) {
+            *devices_on.entry(dev.device_type.clone()).or_default() += 1;
+        }
+
+        Ok(LayoutCounts {
+            houses: layout.houses.len() as i64,
+            rooms: layout.rooms.len() as i64,
+            devices: layout.devices.len() as i64,
+            devices_on: devices_on.into_iter().collect(),
+        })
+    }
+
+    fn clear(&self) -> RepoResult<Cleared> {
+        let mut layout = self.layout.lock()?;
+
+        let res = Cleared {
+            houses: layout.houses.len() + layout.trash.houses.len(),
+            rooms: layout.rooms.len() + layout.trash.rooms.len(),
+            devices: layout.devices.len() + layout.trash.devices.len(),
+            state_changes: layout.state_log.len(),
+        };
+        *layout = Layout {
+            last_id: layout.last_id,
+            ..Layout::default()
+        };
+
+        Ok(res)
+    }
+
+    fn apply(&self, changes: &[LayoutChange]) -> RepoResult<Vec<i32>> {
+        let mut layout = self.layout.lock()?;
+
+        // Changes go to a copy, which replaces the layout once all of them succeed.
+        let mut draft = layout.clone();
+        let mut applied = Vec::with_capacity(changes.len());
+        for change in changes {
+            let id = draft.apply(change, &applied)?;
+            applied.push(id);
+        }
+        *layout = draft;
+
+        Ok(applied)
+    }
+}
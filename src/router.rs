@@ -0,0 +1,414 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    Router,
+    extract::Request,
+    handler::Handler,
+    http::Method,
+    middleware,
+    response::IntoResponse,
+    routing::{MethodFilter, MethodRouter, Route, on},
+};
+use tower::{Layer, Service};
+
+use crate::{AppState, audit, handlers, telemetry};
+
+type ApiRouter = Router<Arc<AppState>>;
+
+/// One method of one path, with the handler serving it.
+type Entry = (&'static str, Method, MethodRouter<Arc<AppState>>);
+
+fn route<H, T>(path: &'static str, method: Method, handler: H) -> Entry
+where
+    H: Handler<T, Arc<AppState>>,
+    T: 'static,
+{
+    let filter = MethodFilter::try_from(method.clone()).expect("routable method");
+
+    (path, method, on(filter, handler))
+}
+
+/// Every route of the API with the audit and tracing layers, ready to serve.
+///
+/// CORS is left to the caller, which knows the origins it is deployed for.
+/// Use [`RouterBuilder`] to mount it under a prefix or leave modules out.
+pub fn router(app_state: Arc<AppState>) -> Router {
+    RouterBuilder::new(app_state).build()
+}
+
+/// Group of routes that can be left out of the API.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Module {
+    /// Houses, rooms, devices and the house event stream.
+    Houses,
+    /// Sensor readings and their statistics.
+    Readings,
+    /// Threshold alerts on devices and houses.
+    Alerts,
+    /// Energy use of sockets.
+    Energy,
+    /// Members, invites and login sessions.
+    Access,
+    /// Everything under `/admin`.
+    Admin,
+    /// `/healthz` and `/readyz`.
+    Health,
+    /// `/metrics`.
+    Metrics,
+    /// `/openapi.json` and `/docs`.
+    Docs,
+}
+
+impl Module {
+    pub const ALL: [Module; 9] = [
+        Module::Houses,
+        Module::Readings,
+        Module::Alerts,
+        Module::Energy,
+        Module::Access,
+        Module::Admin,
+        Module::Health,
+        Module::Metrics,
+        Module::Docs,
+    ];
+
+    fn entries(self) -> Vec<Entry> {
+        match self {
+            Module::Houses => houses(),
+            Module::Readings => readings(),
+            Module::Alerts => alerts(),
+            Module::Energy => energy(),
+            Module::Access => access(),
+            Module::Admin => admin(),
+            Module::Health => health(),
+            Module::Metrics => metrics(),
+            Module::Docs => docs(),
+        }
+    }
+
+    /// The path and method of every route of the module, as mounted at the root.
+    pub fn paths(self) -> Vec<(&'static str, Method)> {
+        self.entries()
+            .into_iter()
+            .map(|(path, method, _)| (path, method))
+            .collect()
+    }
+
+    fn routes(self) -> ApiRouter {
+        // Routes on the same path merge into one for all their methods.
+        self.entries()
+            .into_iter()
+            .fold(Router::new(), |router, (path, _, handler)| {
+                router.route(path, handler)
+            })
+    }
+}
+
+type BoxedLayer = Box<dyn FnOnce(Router) -> Router + Send>;
+
+/// Options for embedding the API in a larger axum application.
+///
+/// ```no_run
+/// # fn embed(app_state: std::sync::Arc<otus_axum::AppState>) -> axum::Router {
+/// use otus_axum::router::{Module, RouterBuilder};
+///
+/// RouterBuilder::new(app_state)
+///     .prefix("/smarthome")
+///     .without(Module::Admin)
+///     .build()
+/// # }
+/// ```
+pub struct RouterBuilder {
+    app_state: Arc<AppState>,
+    prefix: String,
+    modules: Vec<Module>,
+    layers: Vec<BoxedLayer>,
+}
+
+impl RouterBuilder {
+    /// Every module, served from the root.
+    pub fn new(app_state: Arc<AppState>) -> Self {
+        Self {
+            app_state,
+            prefix: String::new(),
+            modules: Module::ALL.to_vec(),
+            layers: Vec::new(),
+        }
+    }
+
+    /// Path every route is nested under, such as `/smarthome`.
+    pub fn prefix(self, prefix: &str) -> Self {
+        let prefix = prefix.trim_matches('/');
+
+        Self {
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("/{prefix}")
+            },
+            ..self
+        }
+    }
+
+    /// Serves only these modules.
+    ///
+    /// `/openapi.json` still describes every route.
+    pub fn modules(self, modules: &[Module]) -> Self {
+        Self {
+            modules: modules.to_vec(),
+            ..self
+        }
+    }
+
+    pub fn without(self, module: Module) -> Self {
+        Self {
+            modules: self.modules.into_iter().filter(|m| *m != module).collect(),
+            ..self
+        }
+    }
+
+    /// Wraps the API routes, and only them, in `layer`; the last layer added runs first.
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.layers
+            .push(Box::new(move |router| router.layer(layer)));
+        self
+    }
+
+    pub fn build(self) -> Router {
+        // Route layers refuse a router without routes.
+        if self.modules.is_empty() {
+            return Router::new();
+        }
+
+        // Going through `ALL` drops repeated modules, whose routes would clash.
+        let api = Module::ALL
+            .into_iter()
+            .filter(|module| self.modules.contains(module))
+            .fold(Router::new(), |api, module| api.merge(module.routes()))
+            .route_layer(middleware::from_fn_with_state(
+                Arc::clone(&self.app_state),
+                audit::record,
+            ))
+            // Added last, so the request span also covers the audit layer.
+            .route_layer(middleware::from_fn(telemetry::trace_request))
+            .with_state(self.app_state);
+
+        let api = self.layers.into_iter().fold(api, |api, layer| layer(api));
+
+        if self.prefix.is_empty() {
+            api
+        } else {
+            Router::new().nest(&self.prefix, api)
+        }
+    }
+}
+
+fn houses() -> Vec<Entry> {
+    vec![
+        route("/house", Method::GET, handlers::list_houses),
+        route("/house", Method::POST, handlers::add_house),
+        route("/house/import", Method::POST, handlers::import_house),
+        route("/houses/{house_id}", Method::PUT, handlers::upd_house),
+        route("/houses/{house_id}", Method::DELETE, handlers::del_house),
+        route(
+            "/houses/{house_id}/config",
+            Method::PUT,
+            handlers::put_config,
+        ),
+        route("/houses/{house_id}/events", Method::GET, handlers::events),
+        route(
+            "/houses/{house_id}/export",
+            Method::GET,
+            handlers::export_house,
+        ),
+        route(
+            "/houses/{house_id}/restore",
+            Method::POST,
+            handlers::restore_house,
+        ),
+        route("/houses/{house_id}/rooms", Method::GET, handlers::get_rooms),
+        route("/houses/{house_id}/rooms", Method::POST, handlers::add_room),
+        route(
+            "/houses/{house_id}/rooms/{room_id}",
+            Method::PUT,
+            handlers::upd_room,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}",
+            Method::DELETE,
+            handlers::del_room,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/restore",
+            Method::POST,
+            handlers::restore_room,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices",
+            Method::GET,
+            handlers::get_devices,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices",
+            Method::POST,
+            handlers::add_device,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}",
+            Method::PUT,
+            handlers::upd_device,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}",
+            Method::DELETE,
+            handlers::del_device,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/restore",
+            Method::POST,
+            handlers::restore_device,
+        ),
+        route("/trash", Method::GET, handlers::list_trash),
+    ]
+}
+
+fn readings() -> Vec<Entry> {
+    vec![
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/readings",
+            Method::GET,
+            handlers::get_readings,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/readings",
+            Method::POST,
+            handlers::add_readings,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/readings/stats",
+            Method::GET,
+            handlers::get_reading_stats,
+        ),
+    ]
+}
+
+fn alerts() -> Vec<Entry> {
+    vec![
+        route(
+            "/houses/{house_id}/alerts",
+            Method::GET,
+            handlers::house_alerts,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/alerts",
+            Method::GET,
+            handlers::get_alerts,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/alerts",
+            Method::POST,
+            handlers::add_alert,
+        ),
+        route(
+            "/houses/{house_id}/rooms/{room_id}/devices/{device_id}/alerts/{alert_id}",
+            Method::DELETE,
+            handlers::del_alert,
+        ),
+    ]
+}
+
+fn energy() -> Vec<Entry> {
+    vec![route(
+        "/houses/{house_id}/energy",
+        Method::GET,
+        handlers::get_energy,
+    )]
+}
+
+fn access() -> Vec<Entry> {
+    vec![
+        route(
+            "/houses/{house_id}/invites",
+            Method::GET,
+            handlers::list_invites,
+        ),
+        route(
+            "/houses/{house_id}/invites",
+            Method::POST,
+            handlers::add_invite,
+        ),
+        route(
+            "/houses/{house_id}/invites/{invite_id}",
+            Method::DELETE,
+            handlers::del_invite,
+        ),
+        route(
+            "/houses/{house_id}/members",
+            Method::GET,
+            handlers::get_members,
+        ),
+        route(
+            "/houses/{house_id}/members",
+            Method::POST,
+            handlers::add_member,
+        ),
+        route(
+            "/houses/{house_id}/members/{user_id}",
+            Method::DELETE,
+            handlers::del_member,
+        ),
+        route("/invite", Method::GET, handlers::my_invite),
+        route("/login", Method::POST, handlers::login),
+        route("/logout", Method::POST, handlers::logout),
+    ]
+}
+
+fn admin() -> Vec<Entry> {
+    vec![
+        route("/admin/audit", Method::GET, handlers::list_audit),
+        route("/admin/backup", Method::POST, handlers::add_backup),
+        route("/admin/backups", Method::GET, handlers::list_backups),
+        route("/admin/house", Method::DELETE, handlers::drop_all),
+        route(
+            "/admin/house/drop-token",
+            Method::POST,
+            handlers::drop_all_token,
+        ),
+        route("/admin/tokens", Method::GET, handlers::list_tokens),
+        route("/admin/tokens", Method::POST, handlers::add_token),
+        route("/admin/restore", Method::POST, handlers::restore_backup),
+        route(
+            "/admin/tokens/{token_id}",
+            Method::DELETE,
+            handlers::revoke_token,
+        ),
+        route("/admin/users", Method::GET, handlers::list_users),
+        route("/admin/users", Method::POST, handlers::add_user),
+    ]
+}
+
+fn health() -> Vec<Entry> {
+    vec![
+        route("/healthz", Method::GET, handlers::healthz),
+        route("/readyz", Method::GET, handlers::readyz),
+    ]
+}
+
+fn metrics() -> Vec<Entry> {
+    vec![route("/metrics", Method::GET, handlers::get_metrics)]
+}
+
+fn docs() -> Vec<Entry> {
+    vec![
+        route("/openapi.json", Method::GET, handlers::openapi_json),
+        route("/docs", Method::GET, handlers::docs),
+        route("/docs/{file}", Method::GET, handlers::docs_asset),
+    ]
+}
@@ -1,5 +1,49 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    alert (id) {
+        id -> Integer,
+        device -> Integer,
+        kind -> Text,
+        threshold -> Double,
+        for_secs -> BigInt,
+        state -> Text,
+        breached_since -> Nullable<BigInt>,
+        changed_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    api_token (id) {
+        id -> Integer,
+        name -> Text,
+        token_hash -> Text,
+        admin -> Bool,
+        created_at -> BigInt,
+        revoked_at -> Nullable<BigInt>,
+        user -> Nullable<Integer>,
+        expires_at -> Nullable<BigInt>,
+        invite -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Integer,
+        at -> BigInt,
+        actor -> Text,
+        method -> Text,
+        route -> Text,
+        uri -> Text,
+        status -> Integer,
+        house -> Nullable<Integer>,
+        room -> Nullable<Integer>,
+        device -> Nullable<Integer>,
+        before -> Nullable<Text>,
+        after -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     device (id) {
         id -> Integer,
@@ -7,6 +51,21 @@ diesel::table! {
         name -> Text,
         device_type -> Text,
         state -> Bool,
+        power -> Nullable<Double>,
+        last_reading -> Nullable<Double>,
+        last_reading_at -> Nullable<BigInt>,
+        deleted_at -> Nullable<BigInt>,
+        deleted_with -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    device_state_log (id) {
+        id -> Integer,
+        device -> Integer,
+        state -> Bool,
+        power -> Nullable<Double>,
+        changed_at -> BigInt,
     }
 }
 
@@ -14,6 +73,52 @@ diesel::table! {
     house (id) {
         id -> Integer,
         name -> Text,
+        deleted_at -> Nullable<BigInt>,
+        deleted_with -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    house_member (house, user) {
+        house -> Integer,
+        user -> Integer,
+        role -> Text,
+    }
+}
+
+diesel::table! {
+    invite (id) {
+        id -> Integer,
+        house -> Integer,
+        name -> Text,
+        permission -> Text,
+        whole_house -> Bool,
+        created_at -> BigInt,
+        expires_at -> BigInt,
+        revoked_at -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    invite_device (invite, device) {
+        invite -> Integer,
+        device -> Integer,
+    }
+}
+
+diesel::table! {
+    invite_room (invite, room) {
+        invite -> Integer,
+        room -> Integer,
+    }
+}
+
+diesel::table! {
+    reading (id) {
+        id -> Integer,
+        device -> Integer,
+        value -> Double,
+        taken_at -> BigInt,
     }
 }
 
@@ -22,10 +127,47 @@ diesel::table! {
         id -> Integer,
         house -> Integer,
         name -> Text,
+        deleted_at -> Nullable<BigInt>,
+        deleted_with -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    user (id) {
+        id -> Integer,
+        login -> Text,
+        password_hash -> Text,
+        created_at -> BigInt,
     }
 }
 
+diesel::joinable!(alert -> device (device));
+diesel::joinable!(api_token -> invite (invite));
+diesel::joinable!(api_token -> user (user));
 diesel::joinable!(device -> room (room));
+diesel::joinable!(device_state_log -> device (device));
+diesel::joinable!(house_member -> house (house));
+diesel::joinable!(house_member -> user (user));
+diesel::joinable!(invite -> house (house));
+diesel::joinable!(invite_device -> device (device));
+diesel::joinable!(invite_device -> invite (invite));
+diesel::joinable!(invite_room -> invite (invite));
+diesel::joinable!(invite_room -> room (room));
+diesel::joinable!(reading -> device (device));
 diesel::joinable!(room -> house (house));
 
-diesel::allow_tables_to_appear_in_same_query!(device, house, room,);
+diesel::allow_tables_to_appear_in_same_query!(
+    alert,
+    api_token,
+    audit_log,
+    device,
+    device_state_log,
+    house,
+    house_member,
+    invite,
+    invite_device,
+    invite_room,
+    reading,
+    room,
+    user,
+);
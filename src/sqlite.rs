@@ -0,0 +1,116 @@
+//! Connection setup of the SQLite database behind [`AppState::pool`](crate::AppState).
+//!
+//! Concurrent writers queue on the `busy_timeout` instead of failing with
+//! `database is locked`; to get there every write transaction starts with
+//! `BEGIN IMMEDIATE` (`immediate_transaction`), so it takes the write lock
+//! before reading rather than failing to upgrade a read lock halfway through.
+//!
+//! Migrations run over a connection of their own ([`migrate`]): the ones that
+//! rebuild tables switch foreign keys off, which must not carry over to the pool.
+
+use clap::ValueEnum;
+use diesel::{
+    Connection, SqliteConnection,
+    connection::SimpleConnection,
+    r2d2::{ConnectionManager, CustomizeConnection, Pool, PoolError},
+};
+use diesel_migrations::MigrationHarness;
+use serde::{Deserialize, Serialize};
+
+use crate::{DbPool, MIGRATIONS};
+
+/// How hard SQLite syncs to disk on commit.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    /// Durable across application crashes; with WAL, a power loss may drop the last commits.
+    Normal,
+    Full,
+    Extra,
+}
+
+impl Synchronous {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+/// Pragmas every pooled connection starts with.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqliteSettings {
+    /// Write-ahead logging, so readers do not wait for the writer.
+    pub wal: bool,
+    /// Milliseconds a statement waits for a lock before failing.
+    pub busy_timeout_ms: u64,
+    pub synchronous: Synchronous,
+    /// Enforces the `REFERENCES` clauses of the schema.
+    pub foreign_keys: bool,
+}
+
+impl Default for SqliteSettings {
+    fn default() -> Self {
+        Self {
+            wal: true,
+            busy_timeout_ms: 5000,
+            synchronous: Synchronous::Normal,
+            foreign_keys: true,
+        }
+    }
+}
+
+impl SqliteSettings {
+    pub fn pragmas(&self) -> String {
+        // The timeout goes first so switching the journal mode waits for locks too.
+        format!(
+            "PRAGMA busy_timeout = {}; PRAGMA journal_mode = {}; PRAGMA synchronous = {}; PRAGMA foreign_keys = {};",
+            self.busy_timeout_ms,
+            if self.wal { "WAL" } else { "DELETE" },
+            self.synchronous.as_str(),
+            if self.foreign_keys { "ON" } else { "OFF" },
+        )
+    }
+}
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for SqliteSettings {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute(&self.pragmas())
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+/// Pool of up to `max_size` connections to the database at `url`, keeping
+/// `min_idle` of them open (all of them if `None`).
+pub fn pool(
+    url: &str,
+    max_size: u32,
+    min_idle: Option<u32>,
+    settings: SqliteSettings,
+) -> Result<DbPool, PoolError> {
+    Pool::builder()
+        .max_size(max_size)
+        .min_idle(min_idle)
+        .connection_customizer(Box::new(settings))
+        .build(ConnectionManager::new(url))
+}
+
+/// Runs the pending migrations on the database at `url`.
+pub fn migrate(
+    url: &str,
+    settings: &SqliteSettings,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = SqliteConnection::establish(url)?;
+    conn.batch_execute(&format!(
+        "PRAGMA busy_timeout = {};",
+        settings.busy_timeout_ms
+    ))?;
+    conn.run_pending_migrations(MIGRATIONS)?;
+
+    Ok(())
+}
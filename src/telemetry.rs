@@ -0,0 +1,216 @@
+use std::{cell::RefCell, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use diesel::connection::{Instrumentation, InstrumentationEvent};
+use tracing::{Instrument, Span, field};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    config::{Config, LogFormat},
+    metrics::METRICS,
+    random_token,
+};
+
+/// Header carrying the request id, taken from the client when it sends one.
+pub const REQUEST_ID: &str = "x-request-id";
+
+/// Installs the global subscriber; `RUST_LOG`, when set, wins over the configured level.
+pub fn init(config: &Config) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(config.log_level.as_str()));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match config.log_format {
+        LogFormat::Pretty => builder.pretty().init(),
+        LogFormat::Json => builder.json().init(),
+    }
+
+    if let Err(e) = diesel::connection::set_default_instrumentation(query_tracing) {
+        tracing::warn!("queries will not be traced: {e}");
+    }
+}
+
+/// Route layer wrapping every request in a span and logging its outcome.
+pub async fn trace_request(mut req: Request, next: Next) -> Response {
+    let started = Instant::now();
+
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID)
+        .filter(|v| !v.is_empty() && v.len() <= 64)
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_str(&random_token()).expect("alphanumeric"));
+    req.headers_mut().insert(REQUEST_ID, request_id.clone());
+
+    let method = req.method().clone();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| req.uri().path().to_owned(), |p| p.as_str().to_owned());
+
+    let span = tracing::info_span!(
+        "request",
+        method = %method,
+        route,
+        request_id = request_id.to_str().unwrap_or_default(),
+        status = field::Empty,
+        latency_ms = field::Empty,
+    );
+
+    let mut response = next.run(req).instrument(span.clone()).await;
+
+    let status = response.status();
+    let elapsed = started.elapsed();
+    let latency_ms = elapsed.as_secs_f64() * 1000.0;
+    span.record("status", status.as_u16());
+    span.record("latency_ms", latency_ms);
+
+    span.in_scope(|| {
+        if status.is_server_error() {
+            tracing::error!("request failed");
+        } else {
+            tracing::info!("request finished");
+        }
+    });
+
+    METRICS.observe_request(method.as_str(), &route, status.as_u16(), elapsed);
+
+    response.headers_mut().insert(REQUEST_ID, request_id);
+
+    response
+}
+
+thread_local! {
+    /// Span of the last query that finished on this thread, for [`Counted`].
+    static LAST_QUERY: RefCell<Option<Span>> = const { RefCell::new(None) };
+}
+
+/// Diesel instrumentation opening a `db.query` span per statement.
+///
+/// The span carries the SQL, its duration and the error, if any; Diesel does
+/// not tell instrumentation how many rows came back, so every call site adds
+/// that through [`Counted`] or [`CountedRow`]. The duration also feeds the
+/// query histogram of [`METRICS`].
+#[derive(Default)]
+pub struct QueryTracing {
+    open: Vec<(Span, Instant, String)>,
+}
+
+fn query_tracing() -> Option<Box<dyn Instrumentation>> {
+    Some(Box::new(QueryTracing::default()))
+}
+
+impl Instrumentation for QueryTracing {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        match event {
+            InstrumentationEvent::StartQuery { query, .. } => {
+                let query = query.to_string();
+                let statement = sql(&query);
+                let span = tracing::debug_span!(
+                    "db.query",
+                    sql = statement,
+                    elapsed_ms = field::Empty,
+                    rows = field::Empty,
+                    error = field::Empty,
+                );
+                // Only the leading keyword is kept for the query metrics.
+                let verb = statement.split_whitespace().next().unwrap_or_default();
+                self.open.push((span, Instant::now(), verb.to_owned()));
+            }
+            InstrumentationEvent::FinishQuery { error, .. } => {
+                let Some((span, started, statement)) = self.open.pop() else {
+                    return;
+                };
+
+                let elapsed = started.elapsed();
+                METRICS.observe_query(&statement, elapsed);
+
+                span.record("elapsed_ms", elapsed.as_secs_f64() * 1000.0);
+                if let Some(e) = error {
+                    span.record("error", field::display(e));
+                }
+                span.in_scope(|| tracing::debug!("query finished"));
+                LAST_QUERY.with_borrow_mut(|last| *last = Some(span));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Row count of a query result: rows affected or rows loaded.
+pub trait RowCount {
+    fn row_count(&self) -> usize;
+}
+
+impl RowCount for usize {
+    fn row_count(&self) -> usize {
+        *self
+    }
+}
+
+impl<T> RowCount for Vec<T> {
+    fn row_count(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> RowCount for Option<T> {
+    fn row_count(&self) -> usize {
+        usize::from(self.is_some())
+    }
+}
+
+/// Puts the row count on the span of the last query that ran on this thread.
+fn record_rows(rows: usize) {
+    if let Some(span) = LAST_QUERY.with_borrow_mut(Option::take) {
+        span.record("rows", rows);
+        span.in_scope(|| tracing::debug!(rows, "query rows"));
+    }
+}
+
+/// Records the row count of a query that just ran on the `rows` field of its
+/// `db.query` span, as in `.execute(conn).counted()?`.
+pub trait Counted {
+    fn counted(self) -> Self;
+}
+
+impl<T: RowCount> Counted for diesel::QueryResult<T> {
+    fn counted(self) -> Self {
+        if let Ok(res) = &self {
+            record_rows(res.row_count());
+        }
+
+        self
+    }
+}
+
+/// Like [`Counted`], for queries returning a single row, as in
+/// `.first(conn).counted_row()?`; finding none records 0.
+pub trait CountedRow {
+    fn counted_row(self) -> Self;
+}
+
+impl<T> CountedRow for diesel::QueryResult<T> {
+    fn counted_row(self) -> Self {
+        match &self {
+            Ok(_) => record_rows(1),
+            Err(diesel::result::Error::NotFound) => record_rows(0),
+            Err(_) => {}
+        }
+
+        self
+    }
+}
+
+/// The statement without its bind values, which may hold secrets such as password hashes.
+fn sql(query: &str) -> &str {
+    query
+        .split_once("-- binds:")
+        .map_or(query, |(sql, _)| sql)
+        .trim()
+}
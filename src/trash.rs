@@ -0,0 +1,233 @@
+//! Deleted houses, rooms and devices.
+//!
+//! Deletes only stamp the row and everything below it that was still there
+//! with the time, `deleted_at`, and the row the delete was asked for,
+//! `deleted_with`. A restore takes back what was deleted with the same row, so
+//! rows deleted on their own never come back with their house or room. Rows
+//! stay in the trash for [`TrashSettings::retention_secs`]; the purge then
+//! deletes them for good along with the readings, alerts, invites and members
+//! that refer to them.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    AppState,
+    layout::Kind,
+    repository::{Cleared, HomeRepository, Purged, RepoError},
+    schema,
+    telemetry::Counted,
+    unix_now,
+};
+
+/// How long deleted rows are kept and how often the expired ones are purged.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrashSettings {
+    /// Seconds a deleted row can be restored.
+    pub retention_secs: u64,
+    /// Seconds between purges, 0 for none.
+    pub purge_interval_secs: u64,
+}
+
+impl Default for TrashSettings {
+    fn default() -> Self {
+        Self {
+            retention_secs: 30 * 24 * 60 * 60,
+            purge_interval_secs: 60 * 60,
+        }
+    }
+}
+
+/// A deleted house, room or device.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Trashed {
+    pub kind: Kind,
+    pub id: i32,
+    pub name: String,
+    pub house: i32,
+    /// Room of a device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room: Option<i32>,
+    pub deleted_at: i64,
+}
+
+/// The rows that were deleted on their own rather than along with their house or room.
+pub fn top_level(trash: Vec<Trashed>) -> Vec<Trashed> {
+    let parents = trash.iter().map(|t| (t.kind, t.id)).collect::<HashSet<_>>();
+
+    trash
+        .into_iter()
+        .filter(|t| match t.kind {
+            Kind::House => true,
+            Kind::Room => !parents.contains(&(Kind::House, t.house)),
+            Kind::Device => t.room.is_none_or(|r| !parents.contains(&(Kind::Room, r))),
+        })
+        .collect()
+}
+
+/// Deletes for good what was deleted more than the retention before `now`.
+pub fn purge(app_state: &AppState, now: i64) -> Result<Cleared, Box<dyn std::error::Error>> {
+    let before = now.saturating_sub(app_state.trash.retention_secs as i64);
+    let mut dbh = app_state.pool.get()?;
+
+    // What refers to the rows lives in the SQLite database whichever store
+    // holds the layout; a store elsewhere may have purged rows whose
+    // references could not be dropped then.
+    let left = leftovers(app_state.repo.as_ref(), &mut dbh)?;
+
+    Ok(app_state
+        .repo
+        .purge_with(before, &mut dbh, &mut |conn, purged| {
+            drop_device_data(conn, &[&purged.devices[..], &left.devices].concat())?;
+            diesel::delete(schema::invite_room::table)
+                .filter(schema::invite_room::room.eq_any([&purged.rooms[..], &left.rooms].concat()))
+                .execute(conn)
+                .counted()?;
+            drop_house_data(conn, &[&purged.houses[..], &left.houses].concat())
+        })?)
+}
+
+/// The purged rows still referred to from the SQLite database.
+fn leftovers(repo: &dyn HomeRepository, conn: &mut SqliteConnection) -> Result<Purged, RepoError> {
+    let mut devices = schema::reading::table
+        .select(schema::reading::device)
+        .distinct()
+        .load::<i32>(conn)
+        .counted()?;
+    devices.extend(
+        schema::alert::table
+            .select(schema::alert::device)
+            .distinct()
+            .load::<i32>(conn)
+            .counted()?,
+    );
+    devices.extend(
+        schema::invite_device::table
+            .select(schema::invite_device::device)
+            .distinct()
+            .load::<i32>(conn)
+            .counted()?,
+    );
+    devices.sort_unstable();
+    devices.dedup();
+
+    let rooms = schema::invite_room::table
+        .select(schema::invite_room::room)
+        .distinct()
+        .load::<i32>(conn)
+        .counted()?;
+
+    let mut houses = schema::house_member::table
+        .select(schema::house_member::house)
+        .distinct()
+        .load::<i32>(conn)
+        .counted()?;
+    houses.extend(
+        schema::invite::table
+            .select(schema::invite::house)
+            .distinct()
+            .load::<i32>(conn)
+            .counted()?,
+    );
+    houses.sort_unstable();
+    houses.dedup();
+
+    Ok(Purged {
+        houses: repo.gone(Kind::House, &houses)?,
+        rooms: repo.gone(Kind::Room, &rooms)?,
+        devices: repo.gone(Kind::Device, &devices)?,
+        cleared: Cleared::default(),
+    })
+}
+
+/// Deletes the readings and alerts of the devices and takes them out of invites.
+fn drop_device_data(conn: &mut SqliteConnection, devices: &[i32]) -> diesel::QueryResult<()> {
+    diesel::delete(schema::reading::table.filter(schema::reading::device.eq_any(devices)))
+        .execute(conn)
+        .counted()?;
+    diesel::delete(schema::alert::table.filter(schema::alert::device.eq_any(devices)))
+        .execute(conn)
+        .counted()?;
+    diesel::delete(
+        schema::invite_device::table.filter(schema::invite_device::device.eq_any(devices)),
+    )
+    .execute(conn)
+    .counted()?;
+
+    Ok(())
+}
+
+/// Deletes the members and invites of the houses, revoking the invite tokens.
+fn drop_house_data(conn: &mut SqliteConnection, houses: &[i32]) -> diesel::QueryResult<()> {
+    diesel::delete(schema::house_member::table)
+        .filter(schema::house_member::house.eq_any(houses))
+        .execute(conn)
+        .counted()?;
+
+    let invites = schema::invite::table
+        .filter(schema::invite::house.eq_any(houses))
+        .select(schema::invite::id)
+        .load::<i32>(conn)
+        .counted()?;
+
+    diesel::delete(schema::invite_room::table)
+        .filter(schema::invite_room::invite.eq_any(&invites))
+        .execute(conn)
+        .counted()?;
+    diesel::delete(schema::invite_device::table)
+        .filter(schema::invite_device::invite.eq_any(&invites))
+        .execute(conn)
+        .counted()?;
+    diesel::update(schema::api_token::table)
+        .filter(schema::api_token::invite.eq_any(&invites))
+        .set((
+            schema::api_token::invite.eq(None::<i32>),
+            schema::api_token::revoked_at.eq(unix_now()),
+        ))
+        .execute(conn)
+        .counted()?;
+    diesel::delete(schema::invite::table)
+        .filter(schema::invite::id.eq_any(&invites))
+        .execute(conn)
+        .counted()?;
+
+    Ok(())
+}
+
+/// Runs [`purge`] every `period` until the server shuts down.
+pub fn spawn_purger(app_state: Arc<AppState>, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = app_state.shutdown.cancelled() => break,
+            }
+
+            let app_state = Arc::clone(&app_state);
+            let res = tokio::task::spawn_blocking(move || {
+                purge(&app_state, unix_now()).map_err(|e| e.to_string())
+            })
+            .await;
+
+            match res {
+                Ok(Ok(purged)) if purged.houses + purged.rooms + purged.devices > 0 => {
+                    tracing::info!(
+                        houses = purged.houses,
+                        rooms = purged.rooms,
+                        devices = purged.devices,
+                        "trash purged"
+                    )
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => tracing::error!("trash purge failed: {e}"),
+                Err(e) => tracing::error!("trash purge failed: {e}"),
+            }
+        }
+    })
+}
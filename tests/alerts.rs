@@ -0,0 +1,233 @@
+mod common;
+
+#[cfg(test)]
+mod alerts {
+    use crate::common;
+    use otus_axum::{
+        alerts::{self, AlertForm, AlertKind},
+        handlers::DeviceForm,
+        models::{Alert, Device, House, Room},
+        readings::ReadingForm,
+        unix_now,
+    };
+    use std::{collections::HashMap, time::Duration};
+
+    #[tokio::test]
+    async fn threshold_state_machine() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = common::client();
+
+        let house: House = client
+            .post(format!("{}/house", host))
+            .json(&HashMap::from([("name", "casa fria")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let room: Room = client
+            .post(format!("{}/houses/{}/rooms", host, house.id))
+            .json(&HashMap::from([("name", "sotano")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let device: Device = client
+            .post(format!(
+                "{}/houses/{}/rooms/{}/devices",
+                host, house.id, room.id
+            ))
+            .json(&DeviceForm {
+                name: "termometro".into(),
+                state: false,
+                device: "termometro".into(),
+                power: None,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let device_url = format!(
+            "{}/houses/{}/rooms/{}/devices/{}",
+            host, house.id, room.id, device.id
+        );
+
+        let too_cold: Alert = client
+            .post(format!("{}/alerts", device_url))
+            .json(&AlertForm {
+                kind: AlertKind::Below,
+                threshold: 15.0,
+                for_secs: 0,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(too_cold.state, "ok");
+
+        let too_hot: Alert = client
+            .post(format!("{}/alerts", device_url))
+            .json(&AlertForm {
+                kind: AlertKind::Above,
+                threshold: 30.0,
+                for_secs: 3600,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let mut events = client
+            .get(format!("{}/houses/{}/events", host, house.id))
+            .send()
+            .await
+            .unwrap();
+        assert!(events.status().is_success());
+
+        let now = unix_now();
+        let readings = |batch: &[(i64, f64)]| {
+            client
+                .post(format!("{}/readings", device_url))
+                .json(
+                    &batch
+                        .iter()
+                        .map(|&(offset, value)| ReadingForm {
+                            value,
+                            taken_at: Some(now + offset),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .send()
+        };
+
+        assert!(readings(&[(0, 10.0)]).await.unwrap().status().is_success());
+
+        let chunk = tokio::time::timeout(Duration::from_secs(5), events.chunk())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let chunk = String::from_utf8_lossy(&chunk);
+        assert!(chunk.contains(r#""type":"alert""#), "{chunk}");
+        assert!(chunk.contains(r#""state":"firing""#), "{chunk}");
+
+        let alerts_url = format!("{}/houses/{}/alerts", host, house.id);
+        let firing: Vec<Alert> = client
+            .get(format!("{}?state=firing", alerts_url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(firing.len(), 1);
+        assert_eq!(firing[0].id, too_cold.id);
+
+        // Breach shorter than `for_secs` keeps the rule quiet, a longer one fires it.
+        readings(&[(10, 35.0), (40, 35.0)]).await.unwrap();
+        let alerts: Vec<Alert> = client
+            .get(&alerts_url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let hot = alerts.iter().find(|a| a.id == too_hot.id).unwrap();
+        let cold = alerts.iter().find(|a| a.id == too_cold.id).unwrap();
+        assert_eq!(hot.state, "ok");
+        assert_eq!(hot.breached_since, Some(now + 10));
+        assert_eq!(cold.state, "resolved");
+
+        readings(&[(3610, 36.0)]).await.unwrap();
+        let firing: Vec<Alert> = client
+            .get(format!("{}?state=firing", alerts_url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(firing.len(), 1);
+        assert_eq!(firing[0].id, too_hot.id);
+    }
+
+    #[tokio::test]
+    async fn sweep_leaves_ended_breaches_alone() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+
+        let house = client.add_house("casa barrida").await.unwrap();
+        let room = client.add_room(house.id, "salon").await.unwrap();
+        let form = DeviceForm {
+            name: "termo".into(),
+            state: false,
+            device: "thermometer".into(),
+            power: None,
+        };
+        let thermo = client.add_device(house.id, room.id, &form).await.unwrap();
+        let rule = AlertForm {
+            kind: AlertKind::Above,
+            threshold: 30.0,
+            for_secs: 60,
+        };
+        client
+            .add_alert(house.id, room.id, thermo.id, &rule)
+            .await
+            .unwrap();
+
+        let now = unix_now();
+        let reading = |value, ago| ReadingForm {
+            value,
+            taken_at: Some(now - ago),
+        };
+        client
+            .add_readings(house.id, room.id, thermo.id, &[reading(35.0, 120)])
+            .await
+            .unwrap();
+        let mut stale = client
+            .alerts(house.id, room.id, thermo.id)
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(stale.breached_since, Some(now - 120));
+
+        // The breach ends after the sweep loaded the rule.
+        client
+            .add_readings(house.id, room.id, thermo.id, &[reading(20.0, 10)])
+            .await
+            .unwrap();
+        let mut dbh = server.state().pool.get().unwrap();
+        assert!(!alerts::fire(&mut dbh, &mut stale, now).unwrap());
+        alerts::sweep(server.state()).unwrap();
+        let alerts = client.alerts(house.id, room.id, thermo.id).await.unwrap();
+        assert_eq!(
+            (alerts[0].state.as_str(), alerts[0].breached_since),
+            ("ok", None)
+        );
+
+        client
+            .add_readings(house.id, room.id, thermo.id, &[reading(35.0, 5)])
+            .await
+            .unwrap();
+        alerts::sweep(server.state()).unwrap();
+        assert_eq!(
+            client.alerts(house.id, room.id, thermo.id).await.unwrap()[0].state,
+            "ok"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+mod common;
+
+#[cfg(test)]
+mod audit {
+    use crate::common;
+    use otus_axum::{
+        audit::AuditEntry,
+        auth::{IssuedToken, TokenForm},
+        handlers::{DropAllToken, DroppedCounts},
+        models::House,
+    };
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn mutations_are_recorded() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let house = renamed_house(host).await;
+        drop_all_needs_confirmation(host, house).await;
+    }
+
+    async fn issue(host: &str, name: &str) -> reqwest::Client {
+        let issued: IssuedToken = common::client()
+            .post(format!("{}/admin/tokens", host))
+            .json(&TokenForm {
+                name: name.into(),
+                admin: true,
+                user: None,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        common::client_with(&issued.token)
+    }
+
+    async fn renamed_house(host: &str) -> House {
+        let client = issue(host, "tester").await;
+
+        let house: House = client
+            .post(format!("{}/house", host))
+            .json(&HashMap::from([("name", "casa auditada")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let response = client
+            .put(format!("{}/houses/{}", host, house.id))
+            .json(&HashMap::from([("name", "casa renombrada")]))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let entries: Vec<AuditEntry> = client
+            .get(format!("{}/admin/audit?actor=tester&limit=2", host))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(entries.len(), 2);
+
+        let rename = &entries[0];
+        assert_eq!(rename.method, "PUT");
+        assert_eq!(rename.route, "/houses/{house_id}");
+        assert_eq!(rename.house, Some(house.id));
+        assert_eq!(rename.before.as_ref().unwrap()["name"], "casa auditada");
+        assert_eq!(rename.after.as_ref().unwrap()["name"], "casa renombrada");
+
+        let create = &entries[1];
+        assert_eq!(create.method, "POST");
+        assert_eq!(create.before, None);
+        assert_eq!(create.after.as_ref().unwrap()["id"], house.id);
+
+        house
+    }
+
+    async fn drop_all_needs_confirmation(host: &str, house: House) {
+        let client = issue(host, "wrecker").await;
+
+        let drop_token = || async {
+            client
+                .post(format!("{}/admin/house/drop-token", host))
+                .send()
+                .await
+                .unwrap()
+                .json::<DropAllToken>()
+                .await
+                .unwrap()
+        };
+        let drop_all = |confirm: String| {
+            client
+                .delete(format!("{}/admin/house", host))
+                .query(&[("confirm", confirm)])
+                .send()
+        };
+
+        // A rejected confirmation burns the token.
+        let token = drop_token().await;
+        let response = drop_all("wrong".into()).await.unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::PRECONDITION_REQUIRED
+        );
+        let response = drop_all(token.token).await.unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::PRECONDITION_REQUIRED
+        );
+
+        let token = drop_token().await;
+        let response = drop_all(token.token).await.unwrap();
+        assert!(response.status().is_success());
+
+        let dropped: DroppedCounts = response.json().await.unwrap();
+        assert!(dropped.houses >= 1);
+
+        let houses: Vec<House> = client
+            .get(format!("{}/house", host))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(houses.iter().all(|h| h.id != house.id));
+
+        let entries: Vec<AuditEntry> = client
+            .get(format!("{}/admin/audit?actor=wrecker&limit=1", host))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            (
+                entries[0].method.as_str(),
+                entries[0].route.as_str(),
+                entries[0].status
+            ),
+            ("DELETE", "/admin/house", 200)
+        );
+    }
+}
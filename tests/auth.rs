@@ -0,0 +1,96 @@
+mod common;
+
+#[cfg(test)]
+mod auth {
+    use crate::common;
+    use otus_axum::{
+        auth::{self, IssuedToken, TokenForm},
+        models::ApiToken,
+        random_token,
+    };
+    use reqwest::StatusCode;
+
+    #[tokio::test]
+    async fn bearer_tokens() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let response = reqwest::Client::new()
+            .get(format!("{}/house", host))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = common::client_with("not a token")
+            .get(format!("{}/house", host))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let issued: IssuedToken = common::client()
+            .post(format!("{}/admin/tokens", host))
+            .json(&TokenForm {
+                name: "household".into(),
+                admin: false,
+                user: None,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(!issued.admin);
+
+        let client = common::client_with(&issued.token);
+
+        let response = client.get(format!("{}/house", host)).send().await.unwrap();
+        assert!(response.status().is_success());
+
+        let response = client
+            .get(format!("{}/admin/tokens", host))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let tokens: Vec<ApiToken> = common::client()
+            .get(format!("{}/admin/tokens", host))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let listed = tokens.iter().find(|t| t.id == issued.id).unwrap();
+        assert_eq!(listed.revoked_at, None);
+
+        let response = common::client()
+            .delete(format!("{}/admin/tokens/{}", host, issued.id))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let response = client.get(format!("{}/house", host)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn bootstrap_token_from_env_file() {
+        let server = common::spawn().await;
+        let secret = random_token();
+
+        // Values read from .env files and secret mounts often end in a newline.
+        auth::bootstrap(&server.state().pool, &format!("{secret}\n")).unwrap();
+
+        let response = common::client_with(&secret)
+            .get(format!("{}/house", server.host()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
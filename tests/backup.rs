@@ -0,0 +1,83 @@
+mod common;
+
+#[cfg(test)]
+mod backup {
+    use crate::common;
+    use otus_axum::handlers::DeviceForm;
+    use reqwest::StatusCode;
+
+    #[tokio::test]
+    async fn restore_brings_the_data_back() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+
+        let house = client.add_house("casa guardada").await.unwrap();
+        let room = client.add_room(house.id, "salón").await.unwrap();
+        let form = DeviceForm {
+            name: "lámpara".into(),
+            state: true,
+            device: "light".into(),
+            power: None,
+        };
+        let lamp = client.add_device(house.id, room.id, &form).await.unwrap();
+
+        let backup = client.backup().await.unwrap();
+        assert!(backup.bytes > 0);
+        assert_eq!(client.backups().await.unwrap(), vec![backup.clone()]);
+
+        client
+            .delete_device(house.id, room.id, lamp.id)
+            .await
+            .unwrap();
+        client.add_house("casa nueva").await.unwrap();
+
+        let restored = client.restore(&backup.name).await.unwrap();
+        assert!(restored.rows > 0);
+
+        let houses = client.houses().await.unwrap();
+        assert_eq!(
+            houses.iter().map(|h| h.id).collect::<Vec<_>>(),
+            vec![house.id]
+        );
+
+        let devices = client.devices(house.id, room.id).await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, lamp.id);
+        assert_eq!(devices[0].name, lamp.name);
+    }
+
+    #[tokio::test]
+    async fn old_backups_are_pruned() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+        let keep = server.state().backups.as_ref().unwrap().keep;
+
+        let mut written = Vec::new();
+        for _ in 0..keep + 2 {
+            written.push(client.backup().await.unwrap().name);
+        }
+
+        let kept = client
+            .backups()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|b| b.name)
+            .collect::<Vec<_>>();
+        assert_eq!(kept.len(), keep);
+
+        // The newest one always survives.
+        assert!(kept.contains(written.last().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn unknown_backups_are_not_found() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+
+        for name in ["smarthome-1-missing.db", "../data.db"] {
+            let err = client.restore(name).await.unwrap_err();
+            assert_eq!(err.status(), Some(StatusCode::NOT_FOUND), "{name}");
+        }
+    }
+}
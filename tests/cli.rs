@@ -0,0 +1,109 @@
+mod common;
+
+#[cfg(test)]
+mod cli {
+    use crate::common;
+    use otus_axum::{
+        layout::{Action, ConfigSync},
+        models::{Device, House, Room},
+    };
+    use serde::de::DeserializeOwned;
+    use std::process::{Command, Output};
+
+    fn cli(host: &str, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_smarthome-cli"))
+            .args(["--url", host, "--token", &common::token()])
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    fn json<T: DeserializeOwned>(host: &str, args: &[&str]) -> T {
+        let output = cli(host, &[&["-o", "json"], args].concat());
+        assert!(output.status.success(), "{output:?}");
+
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+
+    /// The binary blocks its test thread, so the server needs another one.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn manages_a_house() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let [house]: [House; 1] = json(host, &["house", "add", "casa de la terminal"]);
+        let house_id = house.id.to_string();
+
+        let [room]: [Room; 1] = json(host, &["room", "add", &house_id, "salon"]);
+        let room_id = room.id.to_string();
+
+        let [device]: [Device; 1] = json(
+            host,
+            &[
+                "device", "add", &house_id, &room_id, "lampara", "--type", "socket", "--power",
+                "40",
+            ],
+        );
+        assert!(!device.state);
+        let device_id = device.id.to_string();
+
+        let [device]: [Device; 1] = json(host, &["device", "on", &house_id, &room_id, &device_id]);
+        assert!(device.state);
+        assert_eq!(device.power, Some(40.0));
+
+        let output = cli(host, &["report", &house_id]);
+        assert!(output.status.success());
+        let table = String::from_utf8(output.stdout).unwrap();
+        assert!(table.contains("casa de la terminal"));
+        assert!(
+            table
+                .lines()
+                .any(|l| l.contains("lampara") && l.contains(" on "))
+        );
+
+        let output = cli(host, &["device", "off", &house_id, &room_id, "0"]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("404"));
+
+        let houses: Vec<House> = json(host, &["house", "list"]);
+        assert!(houses.iter().any(|h| h.id == house.id));
+
+        assert!(cli(host, &["house", "rm", &house_id]).status.success());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn syncs_a_house_from_a_file() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let [house]: [House; 1] = json(host, &["house", "add", "casa versionada"]);
+        let house_id = house.id.to_string();
+        json::<[Room; 1]>(host, &["room", "add", &house_id, "salon"]);
+
+        let output = cli(host, &["house", "export", &house_id, "--ids"]);
+        assert!(output.status.success(), "{output:?}");
+        let yaml = String::from_utf8(output.stdout)
+            .unwrap()
+            .replace("salon", "sala");
+
+        let file = server.dir().join("casa.yaml");
+        std::fs::write(&file, yaml).unwrap();
+        let file = file.to_str().unwrap();
+
+        let plan: ConfigSync = json(host, &["house", "config", &house_id, file, "--dry-run"]);
+        assert!(plan.dry_run);
+        assert_eq!(plan.changes.len(), 1);
+        assert_eq!(plan.changes[0].action, Action::Rename);
+
+        let output = cli(host, &["house", "config", &house_id, file]);
+        assert!(output.status.success(), "{output:?}");
+        assert!(String::from_utf8_lossy(&output.stdout).contains("salon"));
+
+        let rooms: Vec<Room> = json(host, &["room", "list", &house_id]);
+        assert_eq!(rooms[0].name, "sala");
+
+        let output = cli(host, &["house", "config", &house_id, "missing.yaml"]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("cannot read"));
+    }
+}
@@ -0,0 +1,141 @@
+#![allow(dead_code)]
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::Router;
+use otus_axum::{
+    AppState, auth,
+    backup::BackupSettings,
+    client::SmartHomeClient,
+    random_token,
+    repository::MemoryRepository,
+    sqlite::{self, SqliteSettings},
+};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
+use tokio::net::TcpListener;
+
+/// Admin token every server under test is bootstrapped with,
+/// unless `SMARTHOME_TOKEN` names another one.
+pub fn token() -> String {
+    std::env::var("SMARTHOME_TOKEN").unwrap_or_else(|_| "otus-test-token".to_owned())
+}
+
+/// Client sending the admin token with every request.
+pub fn client() -> reqwest::Client {
+    client_with(&token())
+}
+
+pub fn client_with(token: &str) -> reqwest::Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+    );
+
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap()
+}
+
+/// Typed client of the server at `host`, authenticating with the admin token.
+pub fn api(host: &str) -> SmartHomeClient {
+    SmartHomeClient::new(host, &token()).unwrap()
+}
+
+/// The app serving its own temporary database on an ephemeral port,
+/// stopped and deleted once dropped.
+pub struct TestServer {
+    host: String,
+    state: Arc<AppState>,
+    dir: PathBuf,
+}
+
+impl TestServer {
+    /// Base URL, like `http://127.0.0.1:41234`.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn state(&self) -> &Arc<AppState> {
+        &self.state
+    }
+
+    /// Scratch directory deleted along with the server.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.state.shutdown.cancel();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Starts the router in-process on a fresh database with drop-all and
+/// backups enabled and [`token`] registered as an admin token.
+pub async fn spawn() -> TestServer {
+    spawn_with(otus_axum::router).await
+}
+
+/// Like [`spawn`], serving the app `app` builds around the state.
+pub async fn spawn_with(app: impl FnOnce(Arc<AppState>) -> Router) -> TestServer {
+    start(SqliteSettings::default(), |state| state, app).await
+}
+
+/// Like [`spawn`], keeping houses, rooms and devices in memory.
+pub async fn spawn_in_memory() -> TestServer {
+    // Readings and alerts would point at devices the database does not have.
+    let settings = SqliteSettings {
+        foreign_keys: false,
+        ..SqliteSettings::default()
+    };
+
+    start(
+        settings,
+        |state| state.repository(Arc::new(MemoryRepository::new())),
+        otus_axum::router,
+    )
+    .await
+}
+
+async fn start(
+    settings: SqliteSettings,
+    configure: impl FnOnce(AppState) -> AppState,
+    app: impl FnOnce(Arc<AppState>) -> Router,
+) -> TestServer {
+    let dir = std::env::temp_dir().join(format!("smarthome-test-{}", random_token()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let url = dir.join("data.db").to_string_lossy().into_owned();
+    sqlite::migrate(&url, &settings).unwrap();
+    let pool = sqlite::pool(&url, 4, None, settings).unwrap();
+    auth::bootstrap(&pool, &token()).unwrap();
+
+    let backups = BackupSettings {
+        dir: dir.join("backups"),
+        ..BackupSettings::default()
+    };
+    let state = Arc::new(configure(
+        AppState::new(pool).allow_drop_all(true).backups(backups),
+    ));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+
+    let app = app(Arc::clone(&state));
+    let shutdown = state.shutdown.clone();
+    tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+            .unwrap();
+    });
+
+    TestServer { host, state, dir }
+}
@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod config {
+    use clap::Parser;
+    use otus_axum::config::{Backend, Cli, Config, ConfigError, LogLevel};
+    use std::path::PathBuf;
+
+    fn write_toml(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "smarthome-{}-{}.toml",
+            name,
+            otus_axum::random_token()
+        ));
+        std::fs::write(&path, text).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn flags_override_file() {
+        let path = write_toml(
+            "layered",
+            r#"
+                database_url = "from-file.db"
+                port = 4000
+                pool_size = 4
+                cors_origins = ["https://home.example"]
+
+                [features]
+                alert_sweeper = false
+            "#,
+        );
+
+        let cli = Cli::try_parse_from([
+            "server",
+            "--config",
+            path.to_str().unwrap(),
+            "--port",
+            "5000",
+            "--log-level",
+            "debug",
+            "--allow-drop-all",
+        ])
+        .unwrap();
+        let config = Config::load(&cli).unwrap();
+
+        assert_eq!(config.database_url, "from-file.db");
+        assert_eq!(config.port, 5000);
+        assert_eq!(config.pool_size, 4);
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.cors_origins, vec!["https://home.example"]);
+        assert!(config.features.drop_all);
+        assert!(!config.features.alert_sweeper);
+
+        // What --print-config shows loads back to the same settings.
+        let printed: Config = toml::from_str(&config.to_toml()).unwrap();
+        assert_eq!(printed, config);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn print_config_hides_the_database_password() {
+        let config = Config {
+            database_url: "postgres://home:s3cr@t@db:5432/smarthome?sslmode=require".to_owned(),
+            ..Config::default()
+        };
+
+        let printed = config.to_toml();
+        assert!(!printed.contains("s3cr@t"), "{printed}");
+        assert!(
+            printed.contains(
+                r#"database_url = "postgres://home:***@db:5432/smarthome?sslmode=require""#
+            )
+        );
+        // The config itself still connects with the password.
+        assert!(config.database_url.contains("s3cr@t"));
+
+        // Nothing to hide without a password.
+        let cli = Cli::try_parse_from(["server", "--database-url", "data.db"]).unwrap();
+        let config = Config::load(&cli).unwrap();
+        assert!(config.to_toml().contains(r#"database_url = "data.db""#));
+    }
+
+    #[test]
+    fn backend_follows_the_url_scheme() {
+        let cli = Cli::try_parse_from(["server", "--database-url", "data.db"]).unwrap();
+        let config = Config::load(&cli).unwrap();
+        assert_eq!(config.backend(), Backend::Sqlite);
+        assert_eq!(config.sqlite_url(), "data.db");
+
+        let cli = Cli::try_parse_from([
+            "server",
+            "--database-url",
+            "postgresql://home@db/smarthome",
+            "--sqlite-url",
+            "access.db",
+        ])
+        .unwrap();
+
+        if cfg!(feature = "postgres") {
+            let config = Config::load(&cli).unwrap();
+            assert_eq!(config.backend(), Backend::Postgres);
+            assert_eq!(config.sqlite_url(), "access.db");
+            // --print-config shows foreign keys as they are applied.
+            assert!(!config.sqlite.foreign_keys);
+            assert!(config.to_toml().contains("foreign_keys = false"));
+
+            let path = write_toml("foreign-keys", "[sqlite]\nforeign_keys = true\n");
+            let cli = Cli::try_parse_from([
+                "server",
+                "--config",
+                path.to_str().unwrap(),
+                "--database-url",
+                "postgresql://home@db/smarthome",
+                "--sqlite-url",
+                "access.db",
+            ])
+            .unwrap();
+            assert!(matches!(Config::load(&cli), Err(ConfigError::Invalid(_))));
+            std::fs::remove_file(path).unwrap();
+        } else {
+            assert!(matches!(Config::load(&cli), Err(ConfigError::Invalid(_))));
+        }
+    }
+
+    #[test]
+    fn invalid_settings_are_reported() {
+        let cli = Cli::try_parse_from([
+            "server",
+            "--database-url",
+            "data.db",
+            "--pool-size",
+            "0",
+            "--cors-origin",
+            "*,localhost",
+        ])
+        .unwrap();
+
+        match Config::load(&cli) {
+            Err(ConfigError::Invalid(problems)) => assert_eq!(problems.len(), 2, "{problems:?}"),
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let path = write_toml("unknown", "prot = 3000\n");
+        let cli = Cli::try_parse_from(["server", "--config", path.to_str().unwrap()]).unwrap();
+        assert!(matches!(Config::load(&cli), Err(ConfigError::Parse(..))));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
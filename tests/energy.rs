@@ -0,0 +1,101 @@
+mod common;
+
+#[cfg(test)]
+mod energy {
+    use crate::common;
+    use otus_axum::{
+        energy::EnergyReport,
+        handlers::DeviceForm,
+        models::{Device, House, Room},
+    };
+    use std::{collections::HashMap, time::Duration};
+
+    #[tokio::test]
+    async fn socket_consumption() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = common::client();
+
+        let house: House = client
+            .post(format!("{}/house", host))
+            .json(&HashMap::from([("name", "casa con enchufes")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let room: Room = client
+            .post(format!("{}/houses/{}/rooms", host, house.id))
+            .json(&HashMap::from([("name", "garaje")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let devices_url = format!("{}/houses/{}/rooms/{}/devices", host, house.id, room.id);
+
+        let response = client
+            .post(&devices_url)
+            .json(&DeviceForm {
+                name: "termometro con enchufe".into(),
+                state: false,
+                device: "termometro".into(),
+                power: Some(10.0),
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let heater = DeviceForm {
+            name: "calentador".into(),
+            state: false,
+            device: "socket".into(),
+            power: Some(3600.0),
+        };
+
+        let device: Device = client
+            .post(&devices_url)
+            .json(&heater)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(device.power, Some(3600.0));
+
+        let response = client
+            .put(format!("{}/{}", devices_url, device.id))
+            .json(&DeviceForm {
+                state: true,
+                ..heater
+            })
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+
+        let report: EnergyReport = client
+            .get(format!("{}/houses/{}/energy?period=day", host, house.id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(report.rooms.len(), 1);
+        assert_eq!(report.rooms[0].devices.len(), 1);
+        assert_eq!(report.rooms[0].devices[0].device, device.id);
+        assert!(report.kwh >= 0.001, "unexpected consumption {}", report.kwh);
+        assert_eq!(report.kwh, report.rooms[0].kwh);
+    }
+}
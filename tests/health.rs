@@ -0,0 +1,41 @@
+mod common;
+
+#[cfg(test)]
+mod health {
+    use crate::common;
+    use otus_axum::health::Readiness;
+
+    #[tokio::test]
+    async fn probes_need_no_token() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("{}/healthz", host))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let response = client.get(format!("{}/readyz", host)).send().await.unwrap();
+        assert!(response.status().is_success());
+
+        let readiness: Readiness = response.json().await.unwrap();
+        assert!(readiness.ready);
+        assert_eq!(
+            readiness
+                .checks
+                .iter()
+                .map(|c| (c.name.as_str(), c.ok))
+                .collect::<Vec<_>>(),
+            vec![
+                ("database", true),
+                ("migrations", true),
+                ("writable", true),
+                ("layout", true)
+            ]
+        );
+    }
+}
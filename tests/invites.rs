@@ -0,0 +1,325 @@
+mod common;
+
+#[cfg(test)]
+mod invites {
+    use crate::common;
+    use otus_axum::{
+        alerts::{AlertForm, AlertKind},
+        auth::{InviteForm, InviteScope, IssuedInvite, Permission, TokenForm},
+        client::SmartHomeClient,
+        handlers::DeviceForm,
+        layout::{Format, ImportMode},
+        models::{Device, House, Room},
+    };
+    use reqwest::StatusCode;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn scoped_and_revocable() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = common::client();
+
+        let house: House = client
+            .post(format!("{}/house", host))
+            .json(&HashMap::from([("name", "casa vigilada")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let mut rooms = Vec::new();
+        for name in ["salon", "despacho"] {
+            let room: Room = client
+                .post(format!("{}/houses/{}/rooms", host, house.id))
+                .json(&HashMap::from([("name", name)]))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            rooms.push(room);
+        }
+
+        let mut devices = Vec::new();
+        for room in &rooms {
+            let device: Device = client
+                .post(format!(
+                    "{}/houses/{}/rooms/{}/devices",
+                    host, house.id, room.id
+                ))
+                .json(&DeviceForm {
+                    name: "lampara".into(),
+                    state: false,
+                    device: "socket".into(),
+                    power: None,
+                })
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            devices.push(device);
+        }
+
+        let invites_url = format!("{}/houses/{}/invites", host, house.id);
+
+        let response = client
+            .post(&invites_url)
+            .json(&InviteForm {
+                name: "ajena".into(),
+                permission: Permission::View,
+                rooms: vec![rooms[0].id, 0],
+                devices: vec![],
+                ttl_secs: 3600,
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let issued: IssuedInvite = client
+            .post(&invites_url)
+            .json(&InviteForm {
+                name: "limpieza".into(),
+                permission: Permission::Control,
+                rooms: vec![],
+                devices: vec![devices[0].id],
+                ttl_secs: 3600,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let cleaner = common::client_with(&issued.token);
+
+        let scope: InviteScope = cleaner
+            .get(format!("{}/invite", host))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(scope.house, house.id);
+        assert!(!scope.whole_house);
+        assert_eq!(scope.devices, vec![devices[0].id]);
+
+        let houses: Vec<House> = cleaner
+            .get(format!("{}/house", host))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(houses.len(), 1);
+        assert_eq!(houses[0].id, house.id);
+
+        let switch = |device: &Device| {
+            cleaner
+                .put(format!(
+                    "{}/houses/{}/rooms/{}/devices/{}",
+                    host, house.id, device.room, device.id
+                ))
+                .json(&DeviceForm {
+                    name: device.name.clone(),
+                    state: true,
+                    device: device.device_type.clone(),
+                    power: None,
+                })
+                .send()
+        };
+
+        assert!(switch(&devices[0]).await.unwrap().status().is_success());
+        assert_eq!(
+            switch(&devices[1]).await.unwrap().status(),
+            StatusCode::FORBIDDEN
+        );
+
+        // Control covers devices only, never the layout of the house.
+        let response = cleaner
+            .post(format!("{}/houses/{}/rooms", host, house.id))
+            .json(&HashMap::from([("name", "trastero")]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // Nor does it change, delete or watch the devices it switches.
+        let device_url = format!(
+            "{}/houses/{}/rooms/{}/devices/{}",
+            host, house.id, devices[0].room, devices[0].id
+        );
+        let response = cleaner
+            .put(&device_url)
+            .json(&DeviceForm {
+                name: "lampara rota".into(),
+                state: true,
+                device: "socket".into(),
+                power: None,
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = cleaner.delete(&device_url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = cleaner
+            .post(format!("{}/alerts", device_url))
+            .json(&AlertForm {
+                kind: AlertKind::Above,
+                threshold: 1.0,
+                for_secs: 0,
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let left: Vec<Device> = client
+            .get(format!(
+                "{}/houses/{}/rooms/{}/devices",
+                host, house.id, devices[0].room
+            ))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(left[0].name, "lampara");
+
+        let response = client
+            .delete(format!("{}/{}", invites_url, issued.id))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        assert_eq!(
+            switch(&devices[0]).await.unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let viewer: IssuedInvite = client
+            .post(&invites_url)
+            .json(&InviteForm {
+                name: "vecino".into(),
+                permission: Permission::View,
+                rooms: vec![rooms[1].id],
+                devices: vec![],
+                ttl_secs: 3600,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let neighbour = common::client_with(&viewer.token);
+
+        let response = neighbour
+            .get(format!(
+                "{}/houses/{}/rooms/{}/devices",
+                host, house.id, rooms[1].id
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let response = neighbour
+            .get(format!(
+                "{}/houses/{}/rooms/{}/devices",
+                host, house.id, rooms[0].id
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = neighbour
+            .put(format!(
+                "{}/houses/{}/rooms/{}/devices/{}",
+                host, house.id, rooms[1].id, devices[1].id
+            ))
+            .json(&DeviceForm {
+                name: "lampara".into(),
+                state: true,
+                device: "socket".into(),
+                power: None,
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let listed: Vec<InviteScope> = client
+            .get(&invites_url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 2);
+        assert!(listed[0].revoked_at.is_some());
+        assert_eq!(listed[1].rooms, vec![rooms[1].id]);
+    }
+
+    #[tokio::test]
+    async fn only_users_and_admins_add_houses() {
+        let server = common::spawn().await;
+        let admin = common::api(server.host());
+
+        let house = admin.add_house("casa prestada").await.unwrap();
+        let doc = admin
+            .export_house(house.id, Format::Json, false)
+            .await
+            .unwrap();
+        let viewer = admin
+            .add_invite(
+                house.id,
+                &InviteForm {
+                    name: "vecino".into(),
+                    permission: Permission::View,
+                    rooms: vec![],
+                    devices: vec![],
+                    ttl_secs: 3600,
+                },
+            )
+            .await
+            .unwrap();
+        let service = admin
+            .add_token(&TokenForm {
+                name: "servicio".into(),
+                admin: false,
+                user: None,
+            })
+            .await
+            .unwrap();
+
+        for token in [viewer.token, service.token] {
+            let client = SmartHomeClient::new(server.host(), &token).unwrap();
+
+            let err = client.add_house("casa nueva").await.unwrap_err();
+            assert_eq!(err.status(), Some(StatusCode::FORBIDDEN));
+            let err = client
+                .import_house(&doc, Format::Json, ImportMode::Rename)
+                .await
+                .unwrap_err();
+            assert_eq!(err.status(), Some(StatusCode::FORBIDDEN));
+        }
+        assert_eq!(admin.houses().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,364 @@
+mod common;
+
+#[cfg(test)]
+mod layout {
+    use crate::common;
+    use otus_axum::{
+        client::SmartHomeClient,
+        handlers::DeviceForm,
+        layout::{Action, DeviceDoc, Format, HouseDoc, ImportMode, Kind, RoomDoc},
+    };
+    use reqwest::StatusCode;
+
+    async fn furnished(client: &SmartHomeClient) -> i32 {
+        let house = client.add_house("casa portátil").await.unwrap();
+        let kitchen = client.add_room(house.id, "cocina").await.unwrap();
+        client.add_room(house.id, "desván").await.unwrap();
+
+        for (name, device, power) in [
+            ("hervidor", "socket", Some(1500.0)),
+            ("termo", "thermometer", None),
+        ] {
+            let form = DeviceForm {
+                name: name.into(),
+                state: false,
+                device: device.into(),
+                power,
+            };
+            client
+                .add_device(house.id, kitchen.id, &form)
+                .await
+                .unwrap();
+        }
+
+        house.id
+    }
+
+    fn expected() -> HouseDoc {
+        HouseDoc {
+            name: "casa portátil".into(),
+            rooms: vec![
+                RoomDoc {
+                    id: None,
+                    name: "cocina".into(),
+                    devices: vec![
+                        DeviceDoc {
+                            id: None,
+                            name: "hervidor".into(),
+                            device: "socket".into(),
+                            power: Some(1500.0),
+                        },
+                        DeviceDoc {
+                            id: None,
+                            name: "termo".into(),
+                            device: "thermometer".into(),
+                            power: None,
+                        },
+                    ],
+                },
+                RoomDoc {
+                    id: None,
+                    name: "desván".into(),
+                    devices: vec![],
+                },
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn export_in_both_formats() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+        let house = furnished(&client).await;
+
+        let json = client
+            .export_house(house, Format::Json, false)
+            .await
+            .unwrap();
+        assert_eq!(Format::Json.parse(&json).unwrap(), expected());
+
+        let yaml = client
+            .export_house(house, Format::Yaml, false)
+            .await
+            .unwrap();
+        assert!(yaml.contains("name: hervidor"), "{yaml}");
+        assert_eq!(Format::Yaml.parse(&yaml).unwrap(), expected());
+
+        let err = client
+            .export_house(-1, Format::Json, false)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn import_onto_another_server() {
+        let source = common::spawn().await;
+        let source_client = common::api(source.host());
+        let house = furnished(&source_client).await;
+        let yaml = source_client
+            .export_house(house, Format::Yaml, false)
+            .await
+            .unwrap();
+
+        let target = common::spawn_in_memory().await;
+        let client = common::api(target.host());
+
+        let imported = client
+            .import_house(&yaml, Format::Yaml, ImportMode::Fail)
+            .await
+            .unwrap();
+        assert!(imported.created);
+        assert_eq!(imported.house.name, "casa portátil");
+        assert_eq!((imported.rooms_added, imported.devices_added), (2, 2));
+
+        let copy = client
+            .export_house(imported.house.id, Format::Json, false)
+            .await
+            .unwrap();
+        assert_eq!(Format::Json.parse(&copy).unwrap(), expected());
+    }
+
+    #[tokio::test]
+    async fn conflict_modes() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+        let house = furnished(&client).await;
+        let json = client
+            .export_house(house, Format::Json, false)
+            .await
+            .unwrap();
+
+        let err = client
+            .import_house(&json, Format::Json, ImportMode::Fail)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::CONFLICT));
+        assert_eq!(client.houses().await.unwrap().len(), 1);
+
+        for expected_name in ["casa portátil (2)", "casa portátil (3)"] {
+            let renamed = client
+                .import_house(&json, Format::Json, ImportMode::Rename)
+                .await
+                .unwrap();
+            assert!(renamed.created);
+            assert_eq!(renamed.house.name, expected_name);
+            assert_eq!(renamed.devices_added, 2);
+        }
+
+        let mut doc = expected();
+        doc.rooms[0].devices[0].power = Some(2000.0);
+        doc.rooms.push(RoomDoc {
+            id: None,
+            name: "garaje".into(),
+            devices: vec![DeviceDoc {
+                id: None,
+                name: "cargador".into(),
+                device: "socket".into(),
+                power: Some(7400.0),
+            }],
+        });
+        let merged = client
+            .import_house(
+                &Format::Json.render(&doc).unwrap(),
+                Format::Json,
+                ImportMode::Merge,
+            )
+            .await
+            .unwrap();
+        assert!(!merged.created);
+        assert_eq!(merged.house.id, house);
+        assert_eq!(
+            (
+                merged.rooms_added,
+                merged.devices_added,
+                merged.devices_updated
+            ),
+            (1, 1, 1)
+        );
+
+        let after = client
+            .export_house(house, Format::Json, false)
+            .await
+            .unwrap();
+        assert_eq!(Format::Json.parse(&after).unwrap().rooms.len(), 3);
+
+        // Merging the same document again changes nothing.
+        let again = client
+            .import_house(
+                &Format::Json.render(&doc).unwrap(),
+                Format::Json,
+                ImportMode::Merge,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            (
+                again.rooms_added,
+                again.devices_added,
+                again.devices_updated
+            ),
+            (0, 0, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn inconsistent_documents_are_rejected() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+
+        let mut doc = expected();
+        doc.rooms[0].devices[1].power = Some(3.0);
+        let kettle = doc.rooms[0].devices[0].clone();
+        doc.rooms[0].devices.push(kettle);
+        let err = client
+            .import_house(
+                &Format::Yaml.render(&doc).unwrap(),
+                Format::Yaml,
+                ImportMode::Fail,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::BAD_REQUEST));
+        assert!(err.to_string().contains("listed twice"), "{err}");
+        assert!(
+            err.to_string().contains("only recorded for socket"),
+            "{err}"
+        );
+
+        let err = client
+            .import_house("name: [", Format::Yaml, ImportMode::Fail)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::BAD_REQUEST));
+
+        assert!(client.houses().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn config_dry_run_changes_nothing() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+        let house = furnished(&client).await;
+
+        let mut doc = expected();
+        doc.rooms.remove(1);
+        doc.rooms[0].devices[0].power = Some(2000.0);
+        let yaml = Format::Yaml.render(&doc).unwrap();
+
+        let plan = client
+            .sync_config(house, &yaml, Format::Yaml, true)
+            .await
+            .unwrap();
+        assert!(plan.dry_run);
+        assert_eq!(
+            plan.changes
+                .iter()
+                .map(|s| (s.action, s.kind, s.name.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Action::Delete, Kind::Room, "desván"),
+                (Action::Update, Kind::Device, "hervidor"),
+            ]
+        );
+
+        let after = client
+            .export_house(house, Format::Json, false)
+            .await
+            .unwrap();
+        assert_eq!(Format::Json.parse(&after).unwrap(), expected());
+    }
+
+    #[tokio::test]
+    async fn config_renames_by_id_deletes_and_creates() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+        let house = furnished(&client).await;
+
+        let exported = client
+            .export_house(house, Format::Yaml, true)
+            .await
+            .unwrap();
+        let mut doc = Format::Yaml.parse(&exported).unwrap();
+        let kitchen = doc.rooms[0].id.unwrap();
+        doc.name = "casa en git".into();
+        doc.rooms[0].name = "cocina grande".into();
+        doc.rooms[0].devices.remove(1);
+        doc.rooms[1].devices.push(DeviceDoc {
+            id: None,
+            name: "estufa".into(),
+            device: "socket".into(),
+            power: Some(900.0),
+        });
+        doc.rooms.push(RoomDoc {
+            id: None,
+            name: "garaje".into(),
+            devices: vec![],
+        });
+
+        let sync = client
+            .sync_config(
+                house,
+                &Format::Yaml.render(&doc).unwrap(),
+                Format::Yaml,
+                false,
+            )
+            .await
+            .unwrap();
+        assert!(!sync.dry_run);
+        assert_eq!(sync.changes.len(), 5);
+        assert!(sync.changes.iter().all(|s| s.id.is_some()));
+
+        let rooms = client.rooms(house).await.unwrap();
+        let renamed = rooms.iter().find(|r| r.id == kitchen).unwrap();
+        assert_eq!(renamed.name, "cocina grande");
+        assert_eq!(client.devices(house, kitchen).await.unwrap().len(), 1);
+
+        // Applying the same document again is a no-op.
+        let again = client
+            .sync_config(
+                house,
+                &Format::Yaml.render(&doc).unwrap(),
+                Format::Yaml,
+                false,
+            )
+            .await
+            .unwrap();
+        assert!(again.changes.is_empty(), "{:?}", again.changes);
+
+        let after = client
+            .export_house(house, Format::Json, false)
+            .await
+            .unwrap();
+        let after = Format::Json.parse(&after).unwrap();
+        assert_eq!(after.name, "casa en git");
+        assert_eq!(after.rooms.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn config_rejects_unknown_ids() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+        let house = furnished(&client).await;
+
+        let mut doc = expected();
+        doc.rooms[1].id = Some(-7);
+        let err = client
+            .sync_config(
+                house,
+                &Format::Json.render(&doc).unwrap(),
+                Format::Json,
+                false,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::BAD_REQUEST));
+        assert!(err.to_string().contains("room -7"), "{err}");
+
+        let err = client
+            .sync_config(-1, &Format::Json.render(&doc).unwrap(), Format::Json, true)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+    }
+}
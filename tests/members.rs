@@ -0,0 +1,244 @@
+mod common;
+
+#[cfg(test)]
+mod members {
+    use crate::common;
+    use otus_axum::{
+        auth::{Member, MemberForm, Role, Session, UserForm},
+        models::{House, Room, User},
+        random_token,
+    };
+    use reqwest::StatusCode;
+    use std::collections::HashMap;
+
+    /// Registers a user under a unique login and logs them in.
+    async fn sign_up(host: &str, login: &str) -> (User, reqwest::Client) {
+        let form = UserForm {
+            login: format!("{}-{}", login, random_token()),
+            password: "contraseña secreta".into(),
+        };
+
+        let user: User = common::client()
+            .post(format!("{}/admin/users", host))
+            .json(&form)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/login", host))
+            .json(&UserForm {
+                login: form.login.clone(),
+                password: "wrong password".into(),
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let session: Session = reqwest::Client::new()
+            .post(format!("{}/login", host))
+            .json(&form)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        (user, common::client_with(&session.token))
+    }
+
+    #[tokio::test]
+    async fn roles_per_house() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let (_, alice) = sign_up(host, "alice").await;
+        let (bob, bob_client) = sign_up(host, "bob").await;
+
+        let house: House = alice
+            .post(format!("{}/house", host))
+            .json(&HashMap::from([("name", "casa de alice")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let other: House = alice
+            .post(format!("{}/house", host))
+            .json(&HashMap::from([("name", "casa de campo")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let room: Room = alice
+            .post(format!("{}/houses/{}/rooms", host, other.id))
+            .json(&HashMap::from([("name", "establo")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let houses: Vec<House> = bob_client
+            .get(format!("{}/house", host))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(houses.is_empty());
+
+        let rooms_url = format!("{}/houses/{}/rooms", host, house.id);
+        let response = bob_client.get(&rooms_url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let grant = |role: Role| {
+            alice
+                .post(format!("{}/houses/{}/members", host, house.id))
+                .json(&MemberForm {
+                    login: bob.login.clone(),
+                    role,
+                })
+                .send()
+        };
+
+        assert!(grant(Role::Guest).await.unwrap().status().is_success());
+
+        let response = bob_client.get(&rooms_url).send().await.unwrap();
+        assert!(response.status().is_success());
+        let response = bob_client
+            .post(&rooms_url)
+            .json(&HashMap::from([("name", "cocina")]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        assert!(grant(Role::Member).await.unwrap().status().is_success());
+
+        let response = bob_client
+            .post(&rooms_url)
+            .json(&HashMap::from([("name", "cocina")]))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let response = bob_client
+            .put(format!("{}/houses/{}", host, house.id))
+            .json(&HashMap::from([("name", "casa de bob")]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // A room of another house is not reachable through this one.
+        let response = alice
+            .put(format!("{}/{}", rooms_url, room.id))
+            .json(&HashMap::from([("name", "robado")]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let members: Vec<Member> = bob_client
+            .get(format!("{}/houses/{}/members", host, house.id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].role, Role::Owner);
+        assert_eq!(members[1].user, bob.id);
+        assert_eq!(members[1].role, Role::Member);
+
+        let response = alice
+            .delete(format!("{}/houses/{}/members/{}", host, house.id, bob.id))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let response = bob_client.get(&rooms_url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = bob_client
+            .post(format!("{}/logout", host))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        let response = bob_client
+            .get(format!("{}/house", host))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn foreign_ids_are_not_revealed() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let (_, alice) = sign_up(host, "alice").await;
+        let (_, eve) = sign_up(host, "eve").await;
+
+        let house: House = alice
+            .post(format!("{}/house", host))
+            .json(&HashMap::from([("name", "casa privada")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let room: Room = alice
+            .post(format!("{}/houses/{}/rooms", host, house.id))
+            .json(&HashMap::from([("name", "cocina")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        // Existing and missing ids look the same from outside the house.
+        for path in [
+            format!("/houses/{}/rooms", house.id),
+            format!("/houses/{}/rooms", house.id + 1000),
+            format!("/houses/{}/rooms/{}/devices", house.id, room.id),
+            format!("/houses/{}/rooms/{}/devices", house.id, room.id + 1000),
+        ] {
+            let response = eve.get(format!("{host}{path}")).send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{path}");
+        }
+
+        // Members still learn what is missing.
+        let response = alice
+            .get(format!(
+                "{}/houses/{}/rooms/{}/devices",
+                host,
+                house.id,
+                room.id + 1000
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
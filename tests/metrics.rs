@@ -0,0 +1,58 @@
+mod common;
+
+#[cfg(test)]
+mod metrics {
+    use crate::common;
+    use otus_axum::models::House;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn prometheus_text() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = common::client();
+
+        let _: House = client
+            .post(format!("{}/house", host))
+            .json(&HashMap::from([("name", "casa medida")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let response = client
+            .get(format!("{}/metrics", host))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert!(
+            response.headers()["content-type"]
+                .to_str()
+                .unwrap()
+                .starts_with("text/plain")
+        );
+
+        let body = response.text().await.unwrap();
+        for metric in [
+            r#"http_requests_total{method="POST",route="/house",status="200"}"#,
+            "http_request_duration_seconds_bucket",
+            "db_pool_max_connections",
+            "db_pool_idle_connections",
+            "smarthome_houses",
+            "smarthome_devices",
+        ] {
+            assert!(body.contains(metric), "{metric} missing from\n{body}");
+        }
+
+        let response = reqwest::Client::new()
+            .get(format!("{}/metrics", host))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    }
+}
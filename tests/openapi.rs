@@ -0,0 +1,110 @@
+mod common;
+
+#[cfg(test)]
+mod openapi {
+    use crate::common;
+    use axum::http::Method;
+    use otus_axum::{openapi::ApiDoc, router::Module};
+    use utoipa::OpenApi;
+
+    /// Every `(method, path)` the server routes.
+    fn routed() -> Vec<(Method, &'static str)> {
+        Module::ALL
+            .into_iter()
+            .flat_map(Module::paths)
+            .map(|(path, method)| (method, path))
+            .collect()
+    }
+
+    #[test]
+    fn every_route_is_documented() {
+        let doc = ApiDoc::openapi();
+        let routes = routed();
+        assert!(routes.len() > 40, "{routes:?}");
+
+        let missing = routes
+            .iter()
+            .filter(|(method, path)| {
+                let Some(item) = doc.paths.paths.get(*path) else {
+                    return true;
+                };
+                match *method {
+                    Method::GET => item.get.is_none(),
+                    Method::POST => item.post.is_none(),
+                    Method::PUT => item.put.is_none(),
+                    Method::DELETE => item.delete.is_none(),
+                    _ => true,
+                }
+            })
+            .collect::<Vec<_>>();
+        assert!(missing.is_empty(), "undocumented routes: {missing:?}");
+
+        let documented = doc
+            .paths
+            .paths
+            .keys()
+            .filter(|path| !routes.iter().any(|(_, p)| p == *path))
+            .collect::<Vec<_>>();
+        assert!(
+            documented.is_empty(),
+            "documented but not routed: {documented:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn document_is_served_without_token() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("{}/openapi.json", host))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let doc: serde_json::Value = response.json().await.unwrap();
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(doc["paths"]["/houses/{house_id}/rooms"]["post"].is_object());
+        assert_eq!(
+            doc["components"]["securitySchemes"]["bearer"]["scheme"],
+            "bearer"
+        );
+
+        let response = client.get(format!("{}/docs", host)).send().await.unwrap();
+        assert!(response.status().is_success());
+        let page = response.text().await.unwrap();
+        assert!(page.contains("openapi.json"));
+        assert!(
+            !page.contains("https://"),
+            "the page loads nothing from elsewhere"
+        );
+
+        // The assets the page loads are served by the server itself.
+        for (asset, content_type) in [
+            ("swagger-ui-bundle.js", "javascript"),
+            ("swagger-ui.css", "text/css"),
+        ] {
+            let response = client
+                .get(format!("{}/docs/{}", host, asset))
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status().is_success(), "{asset}");
+            assert!(
+                response.headers()["content-type"]
+                    .to_str()
+                    .unwrap()
+                    .contains(content_type)
+            );
+        }
+        let response = client
+            .get(format!("{}/docs/nothing.js", host))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,138 @@
+mod common;
+
+#[cfg(test)]
+mod readings {
+    use crate::common;
+    use otus_axum::{
+        handlers::DeviceForm,
+        models::{Device, House, Room},
+        readings::{ReadingForm, ReadingStats},
+    };
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn ingest_and_aggregate() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = common::client();
+
+        let house: House = client
+            .post(format!("{}/house", host))
+            .json(&HashMap::from([("name", "casa con sensores")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let room: Room = client
+            .post(format!("{}/houses/{}/rooms", host, house.id))
+            .json(&HashMap::from([("name", "dormitorio")]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let devices_url = format!("{}/houses/{}/rooms/{}/devices", host, house.id, room.id);
+
+        let thermometer: Device = client
+            .post(&devices_url)
+            .json(&DeviceForm {
+                name: "termometro".into(),
+                state: false,
+                device: "termometro".into(),
+                power: None,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let socket: Device = client
+            .post(&devices_url)
+            .json(&DeviceForm {
+                name: "enchufe".into(),
+                state: false,
+                device: "socket".into(),
+                power: None,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let batch =
+            [(1000, 18.0), (1010, 22.0), (1060, 20.0), (1030, 18.5)].map(|(taken_at, value)| {
+                ReadingForm {
+                    value,
+                    taken_at: Some(taken_at),
+                }
+            });
+
+        let response = client
+            .post(format!("{}/{}/readings", devices_url, socket.id))
+            .json(&batch)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let stored: usize = client
+            .post(format!("{}/{}/readings", devices_url, thermometer.id))
+            .json(&batch)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(stored, batch.len());
+
+        let devices: Vec<Device> = client
+            .get(&devices_url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let thermometer = devices
+            .into_iter()
+            .find(|d| d.id == thermometer.id)
+            .unwrap();
+        assert_eq!(thermometer.last_reading, Some(20.0));
+        assert_eq!(thermometer.last_reading_at, Some(1060));
+
+        let stats: Vec<ReadingStats> = client
+            .get(format!(
+                "{}/{}/readings/stats?from=1000&to=1100&interval=50",
+                devices_url, thermometer.id
+            ))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].from, stats[0].to), (1000, 1050));
+        assert_eq!(
+            (stats[0].min, stats[0].max, stats[0].count),
+            (18.0, 22.0, 3)
+        );
+        assert_eq!(stats[0].avg, 19.5);
+        assert_eq!(
+            (stats[1].min, stats[1].max, stats[1].count),
+            (20.0, 20.0, 1)
+        );
+    }
+}
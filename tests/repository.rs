@@ -0,0 +1,342 @@
+mod common;
+
+#[cfg(test)]
+mod repository {
+    use crate::common;
+    use diesel::{Connection, QueryDsl, RunQueryDsl, SqliteConnection};
+    use otus_axum::{
+        energy::Period,
+        handlers::DeviceForm,
+        layout::Kind,
+        models::NewDevice,
+        random_token,
+        repository::{
+            DeviceChange, HomeRepository, LayoutChange, MemoryRepository, RepoError,
+            SqliteRepository, Target,
+        },
+        schema,
+        sqlite::{self, SqliteSettings},
+        unix_now,
+    };
+    use reqwest::StatusCode;
+
+    fn socket(room: i32, name: &str) -> NewDevice {
+        NewDevice {
+            name: name.to_owned(),
+            room,
+            device_type: "socket".to_owned(),
+            state: false,
+            power: Some(100.0),
+        }
+    }
+
+    /// Walks the layout through its life cycle, the same for every backend.
+    fn exercise(repo: &dyn HomeRepository) {
+        let house = repo.add_house("casa").unwrap();
+        assert!(matches!(
+            repo.add_house("casa"),
+            Err(RepoError::Conflict(_))
+        ));
+        let other = repo.add_house("otra casa").unwrap();
+        assert_eq!(repo.houses().unwrap().len(), 2);
+        assert_eq!(repo.houses_in(&[other.id]).unwrap()[0].name, "otra casa");
+        assert_eq!(
+            repo.rename_house(house.id, "casa nueva")
+                .unwrap()
+                .unwrap()
+                .name,
+            "casa nueva"
+        );
+        assert!(repo.rename_house(-1, "nada").unwrap().is_none());
+
+        let kitchen = repo.add_room(house.id, "cocina").unwrap();
+        let hall = repo.add_room(house.id, "salon").unwrap();
+        // Room names are unique per house only.
+        repo.add_room(other.id, "cocina").unwrap();
+        assert!(matches!(
+            repo.rename_room(hall.id, "cocina"),
+            Err(RepoError::Conflict(_))
+        ));
+        assert_eq!(repo.rooms(house.id).unwrap().len(), 2);
+        assert_eq!(repo.room(kitchen.id).unwrap().unwrap().house, house.id);
+
+        let kettle = repo.add_device(socket(kitchen.id, "hervidor")).unwrap();
+        let lamp = repo.add_device(socket(hall.id, "lampara")).unwrap();
+        assert_eq!(repo.devices(kitchen.id).unwrap().len(), 1);
+        assert_eq!(repo.house_devices(house.id).unwrap().len(), 2);
+
+        let on = DeviceChange {
+            name: kettle.name.clone(),
+            device_type: kettle.device_type.clone(),
+            state: true,
+            power: kettle.power,
+        };
+        assert!(
+            repo.update_device(kettle.id, on.clone())
+                .unwrap()
+                .unwrap()
+                .state
+        );
+        // Unchanged state and power leave the history alone.
+        repo.update_device(kettle.id, on).unwrap();
+        assert!(
+            repo.update_device(
+                -1,
+                DeviceChange {
+                    name: "nada".to_owned(),
+                    device_type: "socket".to_owned(),
+                    state: false,
+                    power: None,
+                }
+            )
+            .unwrap()
+            .is_none()
+        );
+
+        let history = repo.state_history(&[kettle.id], i64::MAX).unwrap();
+        assert_eq!(
+            history.iter().map(|l| l.state).collect::<Vec<_>>(),
+            [false, true]
+        );
+        assert!(repo.state_history(&[kettle.id], 0).unwrap().is_empty());
+
+        repo.record_reading(lamp.id, 2.0, 20).unwrap();
+        repo.record_reading(lamp.id, 1.0, 10).unwrap();
+        let lamp_now = repo.device(lamp.id).unwrap().unwrap();
+        assert_eq!(lamp_now.last_reading, Some(2.0));
+        assert_eq!(lamp_now.last_reading_at, Some(20));
+
+        let counts = repo.counts().unwrap();
+        assert_eq!((counts.houses, counts.rooms, counts.devices), (2, 3, 2));
+        assert_eq!(counts.devices_on, [("socket".to_owned(), 1)]);
+
+        assert_eq!(repo.delete_room(hall.id).unwrap(), 1);
+        assert!(repo.device(lamp.id).unwrap().is_none());
+        assert_eq!(repo.trash().unwrap().len(), 2);
+        // A device goes back to its room only once the room is back.
+        assert!(matches!(
+            repo.restore_device(lamp.id),
+            Err(RepoError::Missing(_))
+        ));
+        assert_eq!(repo.restore_room(hall.id).unwrap().unwrap().name, "salon");
+        assert!(repo.restore_room(hall.id).unwrap().is_none());
+        assert_eq!(repo.devices(hall.id).unwrap().len(), 1);
+
+        assert_eq!(repo.delete_device(kettle.id).unwrap(), 1);
+        // Deleted names are free, so the restore conflicts with the new device.
+        let second = repo.add_device(socket(kitchen.id, "hervidor")).unwrap();
+        assert!(matches!(
+            repo.restore_device(kettle.id),
+            Err(RepoError::Conflict(_))
+        ));
+        repo.delete_device(second.id).unwrap();
+        assert!(repo.restore_device(kettle.id).unwrap().unwrap().state);
+        assert_eq!(repo.counts().unwrap().devices, 2);
+
+        repo.delete_device(kettle.id).unwrap();
+        assert_eq!(repo.state_history(&[kettle.id], i64::MAX).unwrap().len(), 2);
+        assert!(repo.purge(i64::MIN).unwrap().devices.is_empty());
+        let purged = repo.purge(i64::MAX).unwrap();
+        assert!(purged.devices.contains(&kettle.id));
+        assert_eq!(
+            (purged.cleared.devices, purged.cleared.state_changes),
+            (2, 3)
+        );
+        assert_eq!(repo.gone(Kind::Device, &[kettle.id]).unwrap(), [kettle.id]);
+        assert!(repo.gone(Kind::Room, &[kitchen.id]).unwrap().is_empty());
+        assert!(repo.restore_device(kettle.id).unwrap().is_none());
+        assert!(
+            repo.state_history(&[kettle.id], i64::MAX)
+                .unwrap()
+                .is_empty()
+        );
+
+        repo.add_device(socket(kitchen.id, "tostadora")).unwrap();
+        assert_eq!(repo.delete_house(house.id).unwrap(), 1);
+        assert!(repo.room(kitchen.id).unwrap().is_none());
+        assert!(repo.house_devices(house.id).unwrap().is_empty());
+        assert_eq!(repo.counts().unwrap().houses, 1);
+        assert_eq!(
+            repo.restore_house(house.id).unwrap().unwrap().name,
+            "casa nueva"
+        );
+        assert_eq!(repo.house_devices(house.id).unwrap().len(), 2);
+        repo.delete_house(house.id).unwrap();
+        let trash = repo.trash().unwrap();
+        assert_eq!(trash.len(), 5);
+        assert!(trash.iter().all(|t| t.deleted_at <= unix_now()));
+
+        let added = repo
+            .apply(&[
+                LayoutChange::AddHouse {
+                    name: "casa importada".to_owned(),
+                },
+                LayoutChange::AddRoom {
+                    house: Target::Added(0),
+                    name: "patio".to_owned(),
+                },
+                LayoutChange::AddDevice {
+                    room: Target::Added(1),
+                    name: "riego".to_owned(),
+                    device_type: "socket".to_owned(),
+                    power: Some(5.0),
+                },
+            ])
+            .unwrap();
+        let watering = repo.house_devices(added[0]).unwrap();
+        assert_eq!(watering.len(), 1);
+        assert_eq!((watering[0].id, watering[0].room), (added[2], added[1]));
+        assert_eq!(repo.discard_house(added[0]).unwrap(), 1);
+        assert!(repo.house_devices(added[0]).unwrap().is_empty());
+        assert!(repo.room(added[1]).unwrap().is_none());
+        assert!(repo.restore_house(added[0]).unwrap().is_none());
+
+        // A failing follow-up leaves no house behind, whatever the backend.
+        let mut dbh = SqliteConnection::establish(":memory:").unwrap();
+        let orphan = [LayoutChange::AddHouse {
+            name: "casa sin dueño".to_owned(),
+        }];
+        assert!(
+            repo.apply_with(&orphan, &mut dbh, &mut |_, _| Err(
+                diesel::result::Error::RollbackTransaction
+            ))
+            .is_err()
+        );
+        assert!(
+            repo.houses()
+                .unwrap()
+                .iter()
+                .all(|h| h.name != "casa sin dueño")
+        );
+        assert_eq!(
+            repo.add_house("casa sin dueño").unwrap().name,
+            "casa sin dueño"
+        );
+
+        // A failing change undoes the ones before it.
+        assert!(matches!(
+            repo.apply(&[
+                LayoutChange::RenameHouse {
+                    id: other.id,
+                    name: "casa renombrada".to_owned(),
+                },
+                LayoutChange::AddRoom {
+                    house: Target::Id(other.id),
+                    name: "cocina".to_owned(),
+                },
+            ]),
+            Err(RepoError::Conflict(_))
+        ));
+        assert_eq!(repo.house(other.id).unwrap().unwrap().name, "otra casa");
+        assert!(matches!(
+            repo.apply(&[LayoutChange::DeleteDevice { id: -1 }]),
+            Err(RepoError::Missing(_))
+        ));
+
+        let mut ran = false;
+        let cleared = repo
+            .clear_with(&mut dbh, &mut |_| {
+                ran = true;
+                Ok(())
+            })
+            .unwrap();
+        assert!(ran);
+        assert_eq!((cleared.houses, cleared.rooms, cleared.devices), (3, 3, 2));
+        assert!(repo.houses().unwrap().is_empty());
+        assert!(repo.trash().unwrap().is_empty());
+    }
+
+    #[test]
+    fn memory_backend() {
+        exercise(&MemoryRepository::new());
+    }
+
+    #[test]
+    fn sqlite_backend() {
+        let path = std::env::temp_dir().join(format!("smarthome-repo-{}.db", random_token()));
+
+        let url = path.to_string_lossy();
+        sqlite::migrate(&url, &SqliteSettings::default()).unwrap();
+        let pool = sqlite::pool(&url, 1, None, SqliteSettings::default()).unwrap();
+
+        let repo = SqliteRepository::new(pool);
+        exercise(&repo);
+
+        // The layout stays when what runs along with the clear fails.
+        repo.add_house("casa").unwrap();
+        let mut dbh = SqliteConnection::establish(":memory:").unwrap();
+        assert!(
+            repo.clear_with(&mut dbh, &mut |_| Err(
+                diesel::result::Error::RollbackTransaction
+            ))
+            .is_err()
+        );
+        assert_eq!(repo.houses().unwrap().len(), 1);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    /// Needs a Postgres database to own, named by `SMARTHOME_TEST_PG_URL`,
+    /// such as `postgres://postgres@localhost/smarthome_test`.
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_backend() {
+        let Ok(url) = std::env::var("SMARTHOME_TEST_PG_URL") else {
+            eprintln!("SMARTHOME_TEST_PG_URL is not set, skipping");
+            return;
+        };
+
+        let repo = otus_axum::postgres::PgRepository::connect(&url, 2).unwrap();
+        repo.clear().unwrap();
+
+        exercise(&repo);
+    }
+
+    #[tokio::test]
+    async fn api_on_memory_backend() {
+        let server = common::spawn_in_memory().await;
+        let client = common::api(server.host());
+
+        let house = client.add_house("casa en memoria").await.unwrap();
+        let room = client.add_room(house.id, "cocina").await.unwrap();
+        let form = DeviceForm {
+            name: "hervidor".into(),
+            state: false,
+            device: "socket".into(),
+            power: Some(2000.0),
+        };
+        let device = client.add_device(house.id, room.id, &form).await.unwrap();
+        let err = client
+            .add_device(house.id, room.id, &form)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::CONFLICT));
+
+        let device = client
+            .update_device(
+                house.id,
+                room.id,
+                device.id,
+                &DeviceForm {
+                    state: true,
+                    ..form
+                },
+            )
+            .await
+            .unwrap();
+        assert!(device.state);
+
+        let report = client.energy(house.id, Period::Day).await.unwrap();
+        assert_eq!(report.rooms[0].devices[0].device, device.id);
+
+        // Nothing of the layout reaches the database.
+        let mut dbh = server.state().pool.get().unwrap();
+        let houses: i64 = schema::house::table.count().get_result(&mut dbh).unwrap();
+        assert_eq!(houses, 0);
+
+        assert_eq!(client.delete_house(house.id).await.unwrap(), "1");
+        assert!(client.houses().await.unwrap().is_empty());
+    }
+}
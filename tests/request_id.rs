@@ -0,0 +1,26 @@
+mod common;
+
+#[cfg(test)]
+mod request_id {
+    use crate::common;
+    use otus_axum::telemetry::REQUEST_ID;
+
+    #[tokio::test]
+    async fn echoed_or_generated() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = common::client();
+
+        let response = client
+            .get(format!("{}/house", host))
+            .header(REQUEST_ID, "casa-42")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[REQUEST_ID], "casa-42");
+
+        let response = client.get(format!("{}/house", host)).send().await.unwrap();
+        assert!(!response.headers()[REQUEST_ID].is_empty());
+    }
+}
@@ -1,200 +1,132 @@
+mod common;
+
 #[cfg(test)]
 mod crud {
+    use crate::common;
     use otus_axum::{
+        client::{ClientError, SmartHomeClient},
         handlers::DeviceForm,
         models::{Device, House, Room},
     };
-    use std::collections::HashMap;
-
-    static HTTP_HOST: &str = "http://localhost:3000";
+    use reqwest::StatusCode;
 
     #[tokio::test]
     async fn add_and_update_ops() {
-        set_up().await;
-
-        upd_the_device(
-            new_device(upd_the_room(new_room(upd_the_house(new_house().await).await).await).await)
-                .await,
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = common::api(host);
+
+        let room = upd_the_room(
+            &client,
+            new_room(
+                &client,
+                upd_the_house(&client, new_house(&client).await).await,
+            )
+            .await,
         )
         .await;
-    }
-
-    async fn set_up() {
-        let client = reqwest::Client::new();
+        let house_id = room.house;
 
-        let response = client
-            .delete(format!("{}/house", HTTP_HOST))
-            .send()
-            .await
-            .unwrap();
+        let device = new_device(&client, room).await;
+        upd_the_device(&client, house_id, device).await;
+    }
 
-        assert!(response.status().is_success());
+    #[tokio::test]
+    async fn errors_are_typed() {
+        let server = common::spawn().await;
+        let host = server.host();
+
+        let client = common::api(host);
+
+        let err = client.delete_room(i32::MAX, i32::MAX).await.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+
+        let anonymous = SmartHomeClient::builder().base_url(host).build().unwrap();
+        match anonymous.houses().await {
+            Err(ClientError::Api { status, .. }) => assert_eq!(status, StatusCode::UNAUTHORIZED),
+            other => panic!("{other:?}"),
+        }
+
+        let unreachable = SmartHomeClient::new("http://localhost:9", "nobody").unwrap();
+        assert!(matches!(
+            unreachable.houses().await,
+            Err(ClientError::Http(_))
+        ));
     }
 
-    async fn new_house() -> House {
+    async fn new_house(client: &SmartHomeClient) -> House {
         let name = "la casa de mi primо";
 
-        let mut data = HashMap::new();
-        data.insert("name", name);
-
-        let client = reqwest::Client::new();
-        let response = client
-            .post(format!("{}/{}", HTTP_HOST, "house"))
-            .json(&data)
-            .send()
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
-
-        let result = response.json::<House>().await;
-        assert!(result.is_ok());
-
-        let house = result.unwrap();
+        let house = client.add_house(name).await.unwrap();
 
         assert_eq!(&house.name, name);
 
         house
     }
 
-    async fn upd_the_house(house: House) -> House {
+    async fn upd_the_house(client: &SmartHomeClient, house: House) -> House {
         let name = "la casa de mi primo updated";
 
-        let renamed_house = House {
-            name: name.to_owned(),
-            ..house
-        };
-
-        let client = reqwest::Client::new();
-        let response = client
-            .put(format!("{}/houses/{}", HTTP_HOST, house.id))
-            .json(&renamed_house)
-            .send()
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
-
-        let result = response.json::<House>().await;
-        assert!(result.is_ok());
-
-        let upd_house = result.unwrap();
+        let upd_house = client.update_house(house.id, name).await.unwrap();
 
         assert_eq!(&upd_house.name, name);
+        assert_eq!(client.houses().await.unwrap().len(), 1);
 
         upd_house
     }
 
-    async fn new_room(house: House) -> Room {
+    async fn new_room(client: &SmartHomeClient, house: House) -> Room {
         let name = "cocina";
-        let mut data = HashMap::new();
-        data.insert("name", name);
-
-        let client = reqwest::Client::new();
-        let response = client
-            .post(format!("{}/houses/{}/rooms", HTTP_HOST, house.id))
-            .json(&data)
-            .send()
-            .await;
-
-        let response = response.unwrap();
-
-        assert!(response.status().is_success());
 
-        let result = response.json::<Room>().await;
-        assert!(result.is_ok());
-
-        let room = result.unwrap();
+        let room = client.add_room(house.id, name).await.unwrap();
 
         assert_eq!(&room.name, name);
 
         room
     }
 
-    async fn upd_the_room(room: Room) -> Room {
+    async fn upd_the_room(client: &SmartHomeClient, room: Room) -> Room {
         let name = "cocina updated";
 
-        let renamed_room = Room {
-            name: name.to_owned(),
-            ..room
-        };
-
-        let client = reqwest::Client::new();
-        let response = client
-            .put(format!(
-                "{}/houses/{}/rooms/{}",
-                HTTP_HOST, room.house, room.id
-            ))
-            .json(&renamed_room)
-            .send()
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
-
-        let result = response.json::<Room>().await;
-        assert!(result.is_ok());
-
-        let upd_room = result.unwrap();
+        let upd_room = client.update_room(room.house, room.id, name).await.unwrap();
 
         assert_eq!(&upd_room.name, name);
+        assert_eq!(client.rooms(room.house).await.unwrap().len(), 1);
 
         room
     }
 
-    async fn new_device(room: Room) -> Device {
+    async fn new_device(client: &SmartHomeClient, room: Room) -> Device {
         let name = "temperatura en el refrigorico";
         let data = DeviceForm {
             name: name.into(),
             state: false,
             device: "termometro".into(),
+            power: None,
         };
 
-        let url = format!(
-            "{}/houses/{}/rooms/{}/devices",
-            HTTP_HOST, room.house, room.id
-        );
-
-        let client = reqwest::Client::new();
-        let response = client.post(url).json(&data).send().await.unwrap();
-
-        assert!(response.status().is_success());
-
-        let result = response.json::<Device>().await;
-        assert!(result.is_ok());
-
-        let device = result.unwrap();
+        let device = client.add_device(room.house, room.id, &data).await.unwrap();
 
         assert_eq!(&device.name, name);
+        assert_eq!(client.devices(room.house, room.id).await.unwrap().len(), 1);
 
         device
     }
 
-    async fn upd_the_device(device: Device) -> Device {
+    async fn upd_the_device(client: &SmartHomeClient, house_id: i32, device: Device) -> Device {
         let renamed = DeviceForm {
             name: "hello world".into(),
             state: true,
             device: "socket".into(),
+            power: Some(60.0),
         };
 
-        let client = reqwest::Client::new();
-        let response = client
-            .put(format!(
-                "{}/houses/1/rooms/{}/devices/{}",
-                HTTP_HOST, device.room, device.id
-            ))
-            .json(&renamed)
-            .send()
+        let device = client
+            .update_device(house_id, device.room, device.id, &renamed)
             .await
             .unwrap();
 
-        assert!(response.status().is_success());
-
-        let result = response.json::<Device>().await;
-        assert!(result.is_ok());
-
-        let device = result.unwrap();
-
         assert_eq!(device.name, renamed.name);
 
         device
@@ -0,0 +1,105 @@
+mod common;
+
+#[cfg(test)]
+mod router {
+    use crate::common;
+    use axum::{Router, extract::Request, http::HeaderValue, middleware::Next, routing::get};
+    use otus_axum::{
+        models::House,
+        router::{Module, RouterBuilder},
+    };
+    use reqwest::StatusCode;
+    use std::collections::HashMap;
+
+    async fn tag(req: Request, next: Next) -> axum::response::Response {
+        let mut response = next.run(req).await;
+        response
+            .headers_mut()
+            .insert("x-embedded", HeaderValue::from_static("yes"));
+        response
+    }
+
+    #[tokio::test]
+    async fn prefix_modules_and_layers() {
+        let server = common::spawn_with(|state| {
+            RouterBuilder::new(state)
+                .prefix("/smarthome/")
+                .without(Module::Admin)
+                .layer(axum::middleware::from_fn(tag))
+                .build()
+        })
+        .await;
+        let host = server.host();
+        let client = common::client();
+
+        let response = client
+            .post(format!("{}/smarthome/house", host))
+            .json(&HashMap::from([("name", "casa anidada")]))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()["x-embedded"], "yes");
+        let house: House = response.json().await.unwrap();
+
+        let response = client
+            .get(format!("{}/smarthome/houses/{}/rooms", host, house.id))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        for path in ["/house", "/smarthome/admin/tokens"] {
+            let response = client
+                .get(format!("{}{}", host, path))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn nests_in_another_app() {
+        let server = common::spawn_with(|state| {
+            Router::new()
+                .route("/", get(|| async { "the rest of our service" }))
+                .nest(
+                    "/smarthome",
+                    RouterBuilder::new(state)
+                        .modules(&[Module::Health, Module::Docs, Module::Docs])
+                        .build(),
+                )
+        })
+        .await;
+        let host = server.host();
+        let client = reqwest::Client::new();
+
+        let response = client.get(host).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "the rest of our service");
+
+        let response = client
+            .get(format!("{}/smarthome/healthz", host))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let doc: serde_json::Value = client
+            .get(format!("{}/smarthome/openapi.json", host))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(doc["servers"][0]["url"], "/smarthome");
+
+        let response = client
+            .get(format!("{}/smarthome/house", host))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
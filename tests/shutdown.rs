@@ -0,0 +1,80 @@
+mod common;
+
+#[cfg(all(test, unix))]
+mod shutdown {
+    use crate::common;
+    use otus_axum::models::House;
+    use std::{
+        collections::HashMap,
+        process::{Command, Stdio},
+        time::Duration,
+    };
+
+    /// Port of the server process started by this test.
+    static PORT: u16 = 3917;
+
+    #[tokio::test]
+    async fn sigterm_drains_and_exits() {
+        let dir = std::env::temp_dir().join(format!("smarthome-{}", otus_axum::random_token()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut server = Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--port", &PORT.to_string(), "--drain-timeout-secs", "5"])
+            .arg("--database-url")
+            .arg(dir.join("data.db"))
+            .env("BOOTSTRAP_TOKEN", common::token())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let host = format!("http://localhost:{PORT}");
+        let client = common::client();
+
+        let mut house = None;
+        for _ in 0..50 {
+            if let Ok(response) = client
+                .post(format!("{}/house", host))
+                .json(&HashMap::from([("name", "casa efimera")]))
+                .send()
+                .await
+            {
+                house = Some(response.json::<House>().await.unwrap());
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let house = house.expect("server did not come up");
+
+        let mut events = client
+            .get(format!("{}/houses/{}/events", host, house.id))
+            .send()
+            .await
+            .unwrap();
+        assert!(events.status().is_success());
+
+        let killed = Command::new("kill")
+            .args(["-TERM", &server.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(killed.success());
+
+        // The open event stream is closed instead of holding the shutdown up.
+        let end = tokio::time::timeout(Duration::from_secs(3), async {
+            while events.chunk().await.unwrap().is_some() {}
+        })
+        .await;
+        assert!(end.is_ok(), "event stream outlived the shutdown");
+
+        let mut status = None;
+        for _ in 0..50 {
+            status = server.try_wait().unwrap();
+            if status.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(status.is_some_and(|s| s.success()), "{status:?}");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+mod common;
+
+#[cfg(test)]
+mod sqlite {
+    use crate::common;
+    use diesel::{QueryableByName, RunQueryDsl, sql_query, sql_types::Text};
+    use futures_util::future::join_all;
+    use otus_axum::handlers::DeviceForm;
+
+    #[derive(QueryableByName)]
+    struct Pragma {
+        #[diesel(sql_type = Text)]
+        value: String,
+    }
+
+    /// Value of the pragma, whose single column is named `column`.
+    fn pragma(dbh: &mut diesel::SqliteConnection, name: &str, column: &str) -> String {
+        sql_query(format!(
+            "SELECT CAST({column} AS TEXT) AS value FROM pragma_{name}"
+        ))
+        .get_result::<Pragma>(dbh)
+        .unwrap()
+        .value
+    }
+
+    #[tokio::test]
+    async fn pragmas_are_applied() {
+        let server = common::spawn().await;
+        let mut dbh = server.state().pool.get().unwrap();
+
+        assert_eq!(pragma(&mut dbh, "journal_mode", "journal_mode"), "wal");
+        assert_eq!(pragma(&mut dbh, "busy_timeout", "timeout"), "5000");
+        // NORMAL
+        assert_eq!(pragma(&mut dbh, "synchronous", "synchronous"), "1");
+        assert_eq!(pragma(&mut dbh, "foreign_keys", "foreign_keys"), "1");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_writes_do_not_fail() {
+        let server = common::spawn().await;
+        let client = common::api(server.host());
+
+        let house = client.add_house("casa concurrida").await.unwrap();
+        let room = client.add_room(house.id, "pasillo").await.unwrap();
+
+        let added = join_all((0..40).map(|i| {
+            let client = client.clone();
+            async move {
+                let form = DeviceForm {
+                    name: format!("enchufe {i}"),
+                    state: false,
+                    device: "socket".into(),
+                    power: Some(5.0),
+                };
+                client.add_device(house.id, room.id, &form).await
+            }
+        }))
+        .await;
+
+        let failed = added
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .collect::<Vec<_>>();
+        assert!(failed.is_empty(), "{failed:?}");
+        assert_eq!(client.devices(house.id, room.id).await.unwrap().len(), 40);
+
+        // Deleting the house clears what references it despite the foreign keys.
+        assert_eq!(client.delete_house(house.id).await.unwrap(), "1");
+    }
+}
@@ -0,0 +1,276 @@
+mod common;
+
+#[cfg(test)]
+mod telemetry {
+    use crate::common;
+    use diesel::{Connection, RunQueryDsl, SqliteConnection};
+    use otus_axum::{
+        alerts::{self, AlertForm, AlertKind, AlertsQuery},
+        audit::AuditQuery,
+        auth::{InviteForm, MemberForm, Permission, Role, TokenForm, UserForm},
+        energy::Period,
+        handlers::DeviceForm,
+        layout::{Format, ImportMode},
+        random_token,
+        readings::{ReadingForm, ReadingsQuery},
+        telemetry::{Counted, QueryTracing},
+        trash, unix_now,
+    };
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+    use tracing_subscriber::fmt::{MakeWriter, format::FmtSpan};
+
+    /// Log output kept in memory.
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Logs {
+        type Writer = Logs;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn query_spans_carry_row_counts() {
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(logs.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut conn = SqliteConnection::establish(":memory:").unwrap();
+            conn.set_instrumentation(QueryTracing::default());
+
+            diesel::sql_query("CREATE TABLE t (x INTEGER)")
+                .execute(&mut conn)
+                .unwrap();
+            let inserted = diesel::sql_query("INSERT INTO t VALUES (1), (2), (3)")
+                .execute(&mut conn)
+                .counted()
+                .unwrap();
+            assert_eq!(inserted, 3);
+        });
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let counted = logs
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|line| line["fields"]["message"] == "query rows")
+            .expect("a row count was logged");
+        assert_eq!(counted["fields"]["rows"], 3);
+        assert!(
+            counted["span"]["sql"]
+                .as_str()
+                .unwrap()
+                .starts_with("INSERT")
+        );
+    }
+
+    /// Statements Diesel, the pool and the migration harness issue on their own, with no
+    /// call site to count them.
+    fn uncounted(sql: &str) -> bool {
+        ["BEGIN", "COMMIT", "ROLLBACK", "SAVEPOINT", "RELEASE"]
+            .iter()
+            .any(|verb| sql.starts_with(verb))
+            || sql == "SELECT 1"
+            || sql.contains("__diesel_schema_migrations")
+    }
+
+    #[tokio::test]
+    async fn every_query_span_has_rows() {
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(logs.clone())
+            .finish();
+        tracing::subscriber::set_global_default(subscriber).unwrap();
+
+        let server = common::spawn().await;
+        let state = server.state();
+        // Tests do not install the default instrumentation, so trace the pool by hand.
+        let mut held = (0..state.pool.max_size())
+            .map(|_| state.pool.get().unwrap())
+            .collect::<Vec<_>>();
+        for conn in &mut held {
+            conn.set_instrumentation(QueryTracing::default());
+        }
+        drop(held);
+
+        let client = common::api(server.host());
+        let house = client.add_house("casa trazada").await.unwrap();
+        let room = client.add_room(house.id, "salon").await.unwrap();
+        let mut form = DeviceForm {
+            name: "termo".into(),
+            state: false,
+            device: "thermometer".into(),
+            power: None,
+        };
+        let thermo = client.add_device(house.id, room.id, &form).await.unwrap();
+        form.state = true;
+        client
+            .update_device(house.id, room.id, thermo.id, &form)
+            .await
+            .unwrap();
+        client
+            .add_alert(
+                house.id,
+                room.id,
+                thermo.id,
+                &AlertForm {
+                    kind: AlertKind::Above,
+                    threshold: 30.0,
+                    for_secs: 60,
+                },
+            )
+            .await
+            .unwrap();
+        let now = unix_now();
+        client
+            .add_readings(
+                house.id,
+                room.id,
+                thermo.id,
+                &[
+                    ReadingForm {
+                        value: 35.0,
+                        taken_at: Some(now - 120),
+                    },
+                    ReadingForm {
+                        value: 20.0,
+                        taken_at: Some(now - 60),
+                    },
+                ],
+            )
+            .await
+            .unwrap();
+        let query = ReadingsQuery {
+            from: Some(now - 600),
+            to: None,
+            interval: Some(60),
+        };
+        client
+            .readings(house.id, room.id, thermo.id, &query)
+            .await
+            .unwrap();
+        client
+            .reading_stats(house.id, room.id, thermo.id, &query)
+            .await
+            .unwrap();
+        client
+            .house_alerts(house.id, &AlertsQuery { state: None })
+            .await
+            .unwrap();
+        client.energy(house.id, Period::Day).await.unwrap();
+
+        let doc = client
+            .export_house(house.id, Format::Json, false)
+            .await
+            .unwrap();
+        client
+            .import_house(&doc, Format::Json, ImportMode::Rename)
+            .await
+            .unwrap();
+
+        let user = UserForm {
+            login: format!("vecino-{}", random_token()),
+            password: "contraseña secreta".into(),
+        };
+        client.add_user(&user).await.unwrap();
+        client
+            .add_member(
+                house.id,
+                &MemberForm {
+                    login: user.login.clone(),
+                    role: Role::Guest,
+                },
+            )
+            .await
+            .unwrap();
+        client.members(house.id).await.unwrap();
+        client.login(&user.login, &user.password).await.unwrap();
+        let invite = client
+            .add_invite(
+                house.id,
+                &InviteForm {
+                    name: "visita".into(),
+                    permission: Permission::View,
+                    rooms: vec![room.id],
+                    devices: vec![],
+                    ttl_secs: 3600,
+                },
+            )
+            .await
+            .unwrap();
+        client.invites(house.id).await.unwrap();
+        client.delete_invite(house.id, invite.id).await.unwrap();
+        let token = client
+            .add_token(&TokenForm {
+                name: "servicio".into(),
+                admin: false,
+                user: None,
+            })
+            .await
+            .unwrap();
+        client.tokens().await.unwrap();
+        client.revoke_token(token.id).await.unwrap();
+
+        client.delete_room(house.id, room.id).await.unwrap();
+        client.restore_room(house.id, room.id).await.unwrap();
+        client.delete_house(house.id).await.unwrap();
+        client.trash().await.unwrap();
+        alerts::sweep(state).unwrap();
+        trash::purge(state, now + state.trash.retention_secs as i64 + 1).unwrap();
+        client
+            .audit(&AuditQuery {
+                actor: None,
+                route: None,
+                house: None,
+                room: None,
+                device: None,
+                since: None,
+                until: None,
+                limit: None,
+            })
+            .await
+            .unwrap();
+        client.readyz().await.unwrap();
+        drop(server);
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let closed = logs
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|line| {
+                line["span"]["name"] == "db.query" && line["fields"]["message"] == "close"
+            })
+            .collect::<Vec<_>>();
+        assert!(closed.len() > 100, "{} query spans", closed.len());
+
+        let missing = closed
+            .iter()
+            .filter(|line| line["span"]["error"].is_null() && line["span"]["rows"].is_null())
+            .filter_map(|line| line["span"]["sql"].as_str())
+            .filter(|sql| !uncounted(sql))
+            .collect::<Vec<_>>();
+        assert!(missing.is_empty(), "no row count on {missing:#?}");
+    }
+}
@@ -0,0 +1,247 @@
+mod common;
+
+#[cfg(test)]
+mod trash {
+    use crate::common::{self, TestServer};
+    use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+    use otus_axum::{
+        auth::UserForm, client::SmartHomeClient, handlers::DeviceForm, layout::Kind, random_token,
+        readings::ReadingForm, schema, trash, unix_now,
+    };
+    use reqwest::StatusCode;
+
+    fn socket(name: &str) -> DeviceForm {
+        DeviceForm {
+            name: name.into(),
+            state: false,
+            device: "socket".into(),
+            power: Some(100.0),
+        }
+    }
+
+    async fn deletes_and_restores(server: TestServer) {
+        let client = common::api(server.host());
+
+        let house = client.add_house("casa borrada").await.unwrap();
+        let kitchen = client.add_room(house.id, "cocina").await.unwrap();
+        let kettle = client
+            .add_device(house.id, kitchen.id, &socket("hervidor"))
+            .await
+            .unwrap();
+        assert!(client.trash().await.unwrap().is_empty());
+
+        client
+            .delete_device(house.id, kitchen.id, kettle.id)
+            .await
+            .unwrap();
+        assert!(
+            client
+                .devices(house.id, kitchen.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let trash = client.trash().await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(
+            (trash[0].kind, trash[0].id, trash[0].room),
+            (Kind::Device, kettle.id, Some(kitchen.id))
+        );
+
+        // The name is free again, so the old device cannot come back under it.
+        let second = client
+            .add_device(house.id, kitchen.id, &socket("hervidor"))
+            .await
+            .unwrap();
+        let err = client
+            .restore_device(house.id, kitchen.id, kettle.id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::CONFLICT));
+        client
+            .delete_device(house.id, kitchen.id, second.id)
+            .await
+            .unwrap();
+        assert_eq!(
+            client
+                .restore_device(house.id, kitchen.id, kettle.id)
+                .await
+                .unwrap()
+                .id,
+            kettle.id
+        );
+
+        // Devices deleted along with their room are listed under it only.
+        client.delete_room(house.id, kitchen.id).await.unwrap();
+        let trash = client.trash().await.unwrap();
+        assert!(
+            trash
+                .iter()
+                .any(|t| (t.kind, t.id) == (Kind::Room, kitchen.id))
+        );
+        assert!(trash.iter().all(|t| t.kind != Kind::Device));
+        let err = client
+            .restore_device(house.id, kitchen.id, kettle.id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::CONFLICT));
+
+        client.delete_house(house.id).await.unwrap();
+        let trash = client.trash().await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!((trash[0].kind, trash[0].id), (Kind::House, house.id));
+        let err = client.rooms(house.id).await.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+        let err = client.restore_room(house.id, kitchen.id).await.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::CONFLICT));
+
+        assert_eq!(
+            client.restore_house(house.id).await.unwrap().name,
+            "casa borrada"
+        );
+        // The room was deleted before the house, so it stays in the trash.
+        assert!(client.rooms(house.id).await.unwrap().is_empty());
+        client.restore_room(house.id, kitchen.id).await.unwrap();
+        assert_eq!(
+            client.devices(house.id, kitchen.id).await.unwrap()[0].id,
+            kettle.id
+        );
+        // Only the device deleted on its own is left.
+        let trash = client.trash().await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].id, second.id);
+
+        let err = client.restore_house(house.id).await.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn restore_from_sqlite() {
+        deletes_and_restores(common::spawn().await).await;
+    }
+
+    #[tokio::test]
+    async fn restore_from_memory() {
+        deletes_and_restores(common::spawn_in_memory().await).await;
+    }
+
+    async fn purges_after_retention(server: TestServer) {
+        let client = common::api(server.host());
+
+        let house = client.add_house("casa purgada").await.unwrap();
+        let room = client.add_room(house.id, "cocina").await.unwrap();
+        let form = DeviceForm {
+            name: "termo".into(),
+            state: false,
+            device: "thermometer".into(),
+            power: None,
+        };
+        let thermo = client.add_device(house.id, room.id, &form).await.unwrap();
+        client
+            .add_readings(
+                house.id,
+                room.id,
+                thermo.id,
+                &[ReadingForm {
+                    value: 1.0,
+                    taken_at: None,
+                }],
+            )
+            .await
+            .unwrap();
+        client.delete_house(house.id).await.unwrap();
+
+        let state = server.state();
+        let now = unix_now();
+        assert_eq!(trash::purge(state, now).unwrap().houses, 0);
+        assert_eq!(client.trash().await.unwrap().len(), 1);
+
+        let later = now + state.trash.retention_secs as i64 + 1;
+        let purged = trash::purge(state, later).unwrap();
+        assert_eq!((purged.houses, purged.rooms, purged.devices), (1, 1, 1));
+        assert!(client.trash().await.unwrap().is_empty());
+
+        let readings = schema::reading::table
+            .count()
+            .get_result::<i64>(&mut state.pool.get().unwrap())
+            .unwrap();
+        assert_eq!(readings, 0);
+
+        let err = client.restore_house(house.id).await.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn purge_from_sqlite() {
+        purges_after_retention(common::spawn().await).await;
+    }
+
+    #[tokio::test]
+    async fn purge_from_memory() {
+        purges_after_retention(common::spawn_in_memory().await).await;
+    }
+
+    #[tokio::test]
+    async fn purge_drops_references_left_behind() {
+        use schema::reading::dsl::*;
+
+        let server = common::spawn_in_memory().await;
+        let client = common::api(server.host());
+
+        let house = client.add_house("casa viva").await.unwrap();
+        let room = client.add_room(house.id, "cocina").await.unwrap();
+        let form = DeviceForm {
+            name: "termo".into(),
+            state: false,
+            device: "thermometer".into(),
+            power: None,
+        };
+        let thermo = client.add_device(house.id, room.id, &form).await.unwrap();
+        client
+            .add_readings(
+                house.id,
+                room.id,
+                thermo.id,
+                &[ReadingForm {
+                    value: 1.0,
+                    taken_at: None,
+                }],
+            )
+            .await
+            .unwrap();
+
+        // As if a purge had dropped the device but failed to drop its readings.
+        let state = server.state();
+        let mut dbh = state.pool.get().unwrap();
+        diesel::insert_into(reading)
+            .values((device.eq(thermo.id + 1000), value.eq(2.0), taken_at.eq(0)))
+            .execute(&mut dbh)
+            .unwrap();
+
+        trash::purge(state, unix_now()).unwrap();
+        let left = reading.select(device).load::<i32>(&mut dbh).unwrap();
+        assert_eq!(left, [thermo.id]);
+    }
+
+    #[tokio::test]
+    async fn trash_of_other_houses_is_hidden() {
+        let server = common::spawn().await;
+        let admin = common::api(server.host());
+
+        let house = admin.add_house("casa ajena").await.unwrap();
+        admin.delete_house(house.id).await.unwrap();
+
+        let form = UserForm {
+            login: format!("extraño-{}", random_token()),
+            password: "contraseña secreta".into(),
+        };
+        admin.add_user(&form).await.unwrap();
+        let session = admin.login(&form.login, &form.password).await.unwrap();
+        let stranger = SmartHomeClient::new(server.host(), &session.token).unwrap();
+
+        assert!(stranger.trash().await.unwrap().is_empty());
+        let err = stranger.restore_house(house.id).await.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::FORBIDDEN));
+        assert_eq!(admin.trash().await.unwrap().len(), 1);
+    }
+}